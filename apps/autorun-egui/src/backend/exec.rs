@@ -1,6 +1,12 @@
 use super::Autorun;
 use autorun_ipc::Message;
 
+/// Keeps a runaway loop in the editor from freezing the game for good.
+const BUDGET: autorun_types::Budget = autorun_types::Budget {
+	instructions: None,
+	time_ms: Some(10_000),
+};
+
 impl Autorun {
 	pub fn run_code(&mut self, realm: autorun_types::Realm, code: impl Into<String>) -> anyhow::Result<()> {
 		let code = code.into();
@@ -9,7 +15,7 @@ impl Autorun {
 			anyhow::bail!("Not connected to autorun server");
 		}
 
-		self.send_message(Message::RunCode(realm, code))?;
+		self.send_message(Message::RunCode(realm, code, BUDGET))?;
 		Ok(())
 	}
 }
@@ -436,8 +436,9 @@ impl App {
 
 							// Color-coded realm selector
 							let (realm_color, realm_text) = match self.realm_state {
-								Realm::Menu => (Color32::from_rgb(100, 200, 100), "Menu"),   // Green
-								Realm::Client => (Color32::from_rgb(255, 165, 0), "Client"), // Orange
+								Realm::Menu => (Color32::from_rgb(100, 200, 100), "Menu"),     // Green
+								Realm::Client => (Color32::from_rgb(255, 165, 0), "Client"),   // Orange
+								Realm::Server => (Color32::from_rgb(100, 150, 255), "Server"), // Blue
 							};
 
 							ui.scope(|ui| {
@@ -446,10 +447,11 @@ impl App {
 									.width(110.0)
 									.selected_text(realm_text)
 									.show_ui(ui, |ui| {
-										for realm in [Realm::Menu, Realm::Client] {
+										for realm in [Realm::Menu, Realm::Client, Realm::Server] {
 											let (color, text) = match realm {
 												Realm::Menu => (Color32::from_rgb(100, 200, 100), "Menu"),
 												Realm::Client => (Color32::from_rgb(255, 165, 0), "Client"),
+												Realm::Server => (Color32::from_rgb(100, 150, 255), "Server"),
 											};
 											ui.scope(|ui| {
 												ui.style_mut().visuals.override_text_color = Some(color);
@@ -18,17 +18,28 @@ fn create_if_dne(p: std::path::PathBuf) -> std::io::Result<std::path::PathBuf> {
 impl Workspace {
 	const PLUGINS_DIR: &str = "plugins";
 	const LOGS_DIR: &str = "logs";
+	const CACHE_DIR: &str = "cache";
 	const SETTINGS_FILE: &str = "settings.toml";
 
 	fn plugins(&self) -> std::io::Result<cap_std::fs::Dir> {
 		self.path.open_dir(Self::PLUGINS_DIR)
 	}
 
+	/// Path of the plugins directory, for what can't go through cap-std like watching it for changes.
+	pub fn unsafe_plugins_path(&self) -> std::path::PathBuf {
+		self.unsafe_raw_path.join(Self::PLUGINS_DIR)
+	}
+
 	#[allow(unused)]
 	fn logs(&self) -> std::io::Result<cap_std::fs::Dir> {
 		self.path.open_dir(Self::LOGS_DIR)
 	}
 
+	/// Directory for what Autorun caches between runs, out of reach of plugins.
+	pub fn cache(&self) -> std::io::Result<cap_std::fs::Dir> {
+		self.path.open_dir(Self::CACHE_DIR)
+	}
+
 	/// Creates a workspace from a specific directory.
 	/// You should probably use `from_cwd` instead which uses the standard ./autorun directory.
 	pub fn from_dir(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
@@ -36,6 +47,7 @@ impl Workspace {
 
 		create_if_dne(path.join(Self::PLUGINS_DIR))?;
 		create_if_dne(path.join(Self::LOGS_DIR))?;
+		create_if_dne(path.join(Self::CACHE_DIR))?;
 
 		let settings = path.join(Self::SETTINGS_FILE);
 		if !settings.exists() {
@@ -62,6 +62,10 @@ impl Plugin {
 		self.src()?.open_dir("menu")
 	}
 
+	pub fn server(&self) -> std::io::Result<Dir> {
+		self.src()?.open_dir("server")
+	}
+
 	pub fn shared(&self) -> std::io::Result<Dir> {
 		self.src()?.open_dir("shared")
 	}
@@ -82,6 +86,10 @@ impl Plugin {
 		self.menu()?.read(Self::INIT_FILE)
 	}
 
+	pub fn read_server_init(&self) -> std::io::Result<Vec<u8>> {
+		self.server()?.read(Self::INIT_FILE)
+	}
+
 	pub fn read_shared_init(&self) -> std::io::Result<Vec<u8>> {
 		self.shared()?.read(Self::INIT_FILE)
 	}
@@ -110,6 +118,23 @@ impl Plugin {
 	}
 }
 
+/// Sorts plugins by their ordering, then moves each one after the dependencies it has among them.
+/// Plugins depending on each other in a cycle keep their ordering.
+pub fn sort(plugins: &mut Vec<Plugin>) {
+	plugins.sort_by_key(|p| p.config().plugin.ordering.unwrap_or(9999));
+
+	let mut remaining = std::mem::take(plugins);
+	while !remaining.is_empty() {
+		let is_ready = |plugin: &Plugin| {
+			let mut dependencies = plugin.config().plugin.dependencies.iter().flatten();
+			dependencies.all(|dep| !remaining.iter().any(|p| &p.config().plugin.name == dep))
+		};
+
+		let next = remaining.iter().position(is_ready).unwrap_or(0);
+		plugins.push(remaining.remove(next));
+	}
+}
+
 nestify::nest! {
 	#[derive(Debug, Clone, Serialize, Deserialize)]*
 	pub struct Config {
@@ -119,6 +144,17 @@ nestify::nest! {
 			pub version: String,
 			pub description: String,
 			pub ordering: Option<u32>,
+			/// Run in the shared environment instead of an isolated one, for libraries like std that define builtins.
+			/// Only honored for plugins listed in `shared_plugins` of the settings.
+			pub shared: Option<bool>,
+			/// Plugins this one requires modules from, which are loaded before it.
+			pub dependencies: Option<Vec<String>>,
+			/// Directories modules are looked up in by Autorun.require, relative to the plugin. Defaults to `src`.
+			pub search_paths: Option<Vec<String>>,
+			/// Milliseconds a single call into the plugin's Lua code may take before it is stopped with an error.
+			pub budget_ms: Option<u64>,
+			/// Lua instructions a single call into the plugin's Lua code may run before it is stopped with an error.
+			pub budget_instructions: Option<u64>,
 
 			pub language: #[serde(rename_all = "lowercase")] #[non_exhaustive] pub enum ConfigPluginLanguage {
 				Lua,
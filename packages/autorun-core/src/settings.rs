@@ -6,11 +6,22 @@ nestify::nest! {
 	#[derive(Debug, Deserialize, Serialize, PartialEq)]*
 	pub struct Settings {
 		pub autorun: pub struct AutorunSettings {
-			pub check_version: bool
+			pub check_version: bool,
+			/// Reload Lua plugins when their files change.
+			#[serde(default)]
+			pub hot_reload: bool,
+			/// Plugins allowed to run in the shared environment when they ask to, as they can change what every other
+			/// plugin sees.
+			#[serde(default = "default_shared_plugins")]
+			pub shared_plugins: Vec<String>
 		}
 	}
 }
 
+fn default_shared_plugins() -> Vec<String> {
+	vec!["std".to_owned()]
+}
+
 impl Workspace {
 	fn read_settings(&self) -> anyhow::Result<Settings> {
 		let content = self.path.read("settings.toml")?;
@@ -29,6 +40,11 @@ impl Workspace {
 		Ok(self.settings.get().expect("Should exist"))
 	}
 
+	/// Loads the plugin in the given directory of the plugins directory.
+	pub fn get_plugin(&self, dir: &str) -> anyhow::Result<plugins::Plugin> {
+		plugins::Plugin::from_dir(self.plugins()?.open_dir(dir)?)
+	}
+
 	/// Retrieves all plugins (configs lazily loaded).
 	/// Returns a tuple of (plugins, errors).
 	pub fn get_plugins(&self) -> anyhow::Result<(Vec<plugins::Plugin>, Vec<anyhow::Error>)> {
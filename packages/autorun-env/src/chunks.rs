@@ -0,0 +1,119 @@
+//! Registry of the names given to chunks loaded by Autorun.
+//! Chunk names are prefixed with the nonce of their environment so [`crate::EnvHandle::is_chunk_name_authorized`] can
+//! recognize them, followed by an id instead of the name itself so Lua never truncates them. This maps them back to a
+//! readable `plugin:path` when Autorun reports errors and tracebacks.
+use std::ffi::CString;
+use std::sync::Mutex;
+
+use autorun_lua::LuaState;
+
+/// Readable names of the chunks of each environment, indexed by id.
+static CHUNKS: Mutex<Vec<(u64, Vec<String>)>> = Mutex::new(Vec::new());
+
+/// Returns the chunk name to load a chunk with, reusing the id of `readable` if a chunk had the same name before.
+pub fn register(nonce: u64, readable: &str) -> anyhow::Result<CString> {
+	let mut chunks = CHUNKS.lock().unwrap();
+
+	let names = match chunks.iter().position(|(n, _)| *n == nonce) {
+		Some(pos) => &mut chunks[pos].1,
+		None => {
+			chunks.push((nonce, Vec::new()));
+			&mut chunks.last_mut().unwrap().1
+		}
+	};
+
+	let id = match names.iter().position(|name| name == readable) {
+		Some(id) => id,
+		None => {
+			names.push(readable.to_owned());
+			names.len() - 1
+		}
+	};
+
+	Ok(CString::new(format!("{nonce}-{id}"))?)
+}
+
+/// Forgets the chunk names of an environment, once its state is closed.
+pub fn forget(nonce: u64) {
+	CHUNKS.lock().unwrap().retain(|(n, _)| *n != nonce);
+}
+
+/// Returns the readable name of a chunk name given by [`register`].
+pub fn readable(chunk_name: &str) -> Option<String> {
+	let (nonce, id) = chunk_name.split_once('-')?;
+	let (nonce, id) = (nonce.parse::<u64>().ok()?, id.parse::<usize>().ok()?);
+
+	let chunks = CHUNKS.lock().unwrap();
+	let (_, names) = chunks.iter().find(|(n, _)| *n == nonce)?;
+	names.get(id).cloned()
+}
+
+/// Replaces every chunk name given by [`register`] in an error message or traceback with its readable name.
+pub fn prettify(message: &str) -> String {
+	const START: &str = "[string \"";
+	const END: &str = "\"]";
+
+	let mut pretty = String::with_capacity(message.len());
+	let mut rest = message;
+
+	while let Some(start) = rest.find(START) {
+		let after = &rest[start + START.len()..];
+		let Some(end) = after.find(END) else {
+			break;
+		};
+
+		pretty.push_str(&rest[..start]);
+		match readable(&after[..end]) {
+			Some(name) => pretty.push_str(&name),
+			None => pretty.push_str(&rest[start..start + START.len() + end + END.len()]),
+		}
+
+		rest = &after[end + END.len()..];
+	}
+
+	pretty.push_str(rest);
+	pretty
+}
+
+/// Message handler for protected calls, appending a traceback to the error.
+pub extern "C-unwind" fn traceback(state: *mut LuaState) -> i32 {
+	let lua = autorun_lua::get_api().expect("Failed to get Lua API");
+
+	match lua.raw.tostring(state, 1) {
+		Some(message) => {
+			let message = CString::new(message.as_bytes()).unwrap_or_default();
+			lua.raw.traceback(state, state, message.as_ptr(), 1);
+		}
+
+		// Leave anything that isn't a message as is, like error objects.
+		None => lua.raw.pushvalue(state, 1),
+	}
+
+	1
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::errors;
+	use crate::testing::Env;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn names_plugin_chunks_in_errors_and_tracebacks() {
+		let env = Env::new();
+		let init = "
+			_G.loaded = select(2, Autorun.load('return +', 'snippet.lua'))
+			local function fail() error('boom') end
+			fail()
+		";
+		let why = env.plugin("named", "", &[("src/menu/init.lua", init)]).unwrap_err();
+		assert_eq!(why.to_string(), "named:menu/init.lua:3: boom");
+		assert_eq!(env.results("loaded"), "named:snippet.lua:1: unexpected symbol near '+'");
+
+		let report = errors::recent().pop().unwrap();
+		assert_eq!(report.plugin.as_deref(), Some("named"));
+		let traceback = report.traceback.unwrap();
+		assert!(traceback.contains("named:menu/init.lua:3: in function 'fail'"), "{traceback}");
+		assert!(traceback.contains("named:menu/init.lua:4: in main chunk"), "{traceback}");
+	}
+}
@@ -0,0 +1,445 @@
+//! Conversion between Lua values and serde data, used by `Autorun.json` and `Autorun.toml`.
+//!
+//! Encoding rules, shared by both formats:
+//! - Tables whose keys are exactly `1..n` become arrays, empty tables become objects.
+//! - Arrays with holes are handled according to [`SparseArrays`].
+//! - Any other table becomes an object. Number keys are stringified, other key types are an error.
+//! - NaN and infinite numbers, functions, userdata, threads and cycles are an error.
+//! - Object keys are always emitted sorted.
+use anyhow::{Context, bail};
+use autorun_lua::{LuaApi, LuaTypeId, LuaValue};
+use autorun_types::LuaState;
+use serde_json::{Map, Number, Value};
+use std::ffi::{c_int, c_void};
+
+/// Deepest level of nested tables that will be encoded or decoded.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SparseArrays {
+	/// Refuse to encode arrays with holes.
+	Error,
+	/// Fill holes with null.
+	Null,
+	/// Encode the array as an object with stringified indices.
+	Object,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeOptions {
+	pub pretty: bool,
+	pub sparse: SparseArrays,
+}
+
+impl Default for EncodeOptions {
+	fn default() -> Self {
+		Self {
+			pretty: false,
+			sparse: SparseArrays::Error,
+		}
+	}
+}
+
+impl EncodeOptions {
+	/// Reads options from a Lua table at `index`, keeping defaults for missing fields.
+	pub fn from_lua(lua: &LuaApi, state: *mut LuaState, index: c_int) -> anyhow::Result<Self> {
+		let mut opts = Self::default();
+		match lua.raw.typeid(state, index) {
+			LuaTypeId::None | LuaTypeId::Nil => return Ok(opts),
+			LuaTypeId::Table => (),
+			_ => bail!("Options must be a table"),
+		}
+
+		lua.raw.getfield(state, index, c"pretty".as_ptr());
+		opts.pretty = lua.raw.toboolean(state, -1);
+		lua.raw.pop(state, 1);
+
+		lua.raw.getfield(state, index, c"sparse".as_ptr());
+		let sparse = match lua.raw.to::<LuaValue>(state, -1) {
+			LuaValue::Nil => None,
+			LuaValue::String(b"error") => Some(SparseArrays::Error),
+			LuaValue::String(b"null") => Some(SparseArrays::Null),
+			LuaValue::String(b"object") => Some(SparseArrays::Object),
+			_ => {
+				lua.raw.pop(state, 1);
+				bail!("Option 'sparse' must be one of 'error', 'null' or 'object'");
+			}
+		};
+		lua.raw.pop(state, 1);
+
+		if let Some(sparse) = sparse {
+			opts.sparse = sparse;
+		}
+
+		Ok(opts)
+	}
+}
+
+enum Key {
+	Index(usize),
+	Name(String),
+}
+
+fn number(n: f64) -> anyhow::Result<Number> {
+	if !n.is_finite() {
+		bail!("Cannot encode {n}, only finite numbers are supported");
+	}
+
+	// Keep integers as integers so 1 doesn't become 1.0
+	if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 {
+		return Ok(Number::from(n as i64));
+	}
+
+	Number::from_f64(n).context("Failed to encode number")
+}
+
+fn string(lua: &LuaApi, state: *mut LuaState, index: c_int) -> anyhow::Result<String> {
+	match lua.raw.to::<LuaValue>(state, index) {
+		LuaValue::String(s) => Ok(std::str::from_utf8(s).context("Cannot encode a string that isn't valid UTF-8")?.to_owned()),
+		_ => bail!("Expected a string"),
+	}
+}
+
+/// Converts the Lua value at `index` into a JSON value.
+pub fn to_json(lua: &LuaApi, state: *mut LuaState, index: c_int, opts: &EncodeOptions) -> anyhow::Result<Value> {
+	let index = if index < 0 { lua.raw.gettop(state) + index + 1 } else { index };
+	Encoder {
+		lua,
+		state,
+		opts,
+		path: Vec::new(),
+	}
+	.value(index)
+}
+
+struct Encoder<'a> {
+	lua: &'a LuaApi,
+	state: *mut LuaState,
+	opts: &'a EncodeOptions,
+	/// Tables currently being encoded, used to detect cycles.
+	path: Vec<*const c_void>,
+}
+
+impl Encoder<'_> {
+	fn value(&mut self, index: c_int) -> anyhow::Result<Value> {
+		let lua = &self.lua.raw;
+		match lua.typeid(self.state, index) {
+			LuaTypeId::Nil => Ok(Value::Null),
+			LuaTypeId::Boolean => Ok(Value::Bool(lua.toboolean(self.state, index))),
+			LuaTypeId::Number => Ok(Value::Number(number(lua.tonumber(self.state, index))?)),
+			LuaTypeId::String => Ok(Value::String(string(self.lua, self.state, index)?)),
+			LuaTypeId::Table => self.table(index),
+			other => bail!("Cannot encode a value of type {other}"),
+		}
+	}
+
+	fn key(&self, index: c_int) -> anyhow::Result<Key> {
+		let lua = &self.lua.raw;
+		match lua.typeid(self.state, index) {
+			LuaTypeId::Number => {
+				let n = lua.tonumber(self.state, index);
+				if n >= 1.0 && n.fract() == 0.0 && n <= u32::MAX as f64 {
+					Ok(Key::Index(n as usize))
+				} else if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 {
+					Ok(Key::Name((n as i64).to_string()))
+				} else {
+					Ok(Key::Name(n.to_string()))
+				}
+			}
+
+			LuaTypeId::String => Ok(Key::Name(string(self.lua, self.state, index)?)),
+			other => bail!("Cannot encode a table key of type {other}, only strings and numbers are supported"),
+		}
+	}
+
+	fn table(&mut self, index: c_int) -> anyhow::Result<Value> {
+		let lua = self.lua;
+		let ptr = lua.raw.topointer(self.state, index);
+		if self.path.contains(&ptr) {
+			bail!("Cannot encode a table that contains itself");
+		}
+
+		if self.path.len() >= MAX_DEPTH {
+			bail!("Cannot encode tables nested deeper than {MAX_DEPTH} levels");
+		}
+
+		if !lua.raw.checkstack(self.state, 4) {
+			bail!("Lua stack overflow while encoding");
+		}
+
+		self.path.push(ptr);
+
+		let mut entries = Vec::new();
+		let mut all_indices = true;
+
+		lua.raw.pushnil(self.state);
+		while lua.raw.next(self.state, index) != 0 {
+			let top = lua.raw.gettop(self.state);
+			let entry = self.key(top - 1).and_then(|key| Ok((key, self.value(top)?)));
+
+			let (key, value) = match entry {
+				Ok(entry) => entry,
+				Err(why) => {
+					lua.raw.pop(self.state, 2);
+					self.path.pop();
+					return Err(why);
+				}
+			};
+
+			all_indices &= matches!(key, Key::Index(_));
+			entries.push((key, value));
+			lua.raw.pop(self.state, 1);
+		}
+
+		self.path.pop();
+
+		let len = entries.len();
+		let max_index = entries
+			.iter()
+			.map(|(key, _)| match key {
+				Key::Index(i) => *i,
+				Key::Name(_) => 0,
+			})
+			.max()
+			.unwrap_or(0);
+
+		if len > 0 && all_indices && max_index == len {
+			let mut array = vec![Value::Null; len];
+			for (key, value) in entries {
+				if let Key::Index(i) = key {
+					array[i - 1] = value;
+				}
+			}
+
+			return Ok(Value::Array(array));
+		}
+
+		if len > 0 && all_indices {
+			match self.opts.sparse {
+				SparseArrays::Error => {
+					bail!("Cannot encode sparse array (highest index {max_index}, but {len} elements), see the 'sparse' option")
+				}
+
+				SparseArrays::Null => {
+					// Don't let a single huge index allocate an enormous array of nulls.
+					if max_index > len * 4 + 64 {
+						bail!("Array is too sparse to fill with null (highest index {max_index}, but {len} elements)");
+					}
+
+					let mut array = vec![Value::Null; max_index];
+					for (key, value) in entries {
+						if let Key::Index(i) = key {
+							array[i - 1] = value;
+						}
+					}
+
+					return Ok(Value::Array(array));
+				}
+
+				SparseArrays::Object => (),
+			}
+		}
+
+		let mut object = Map::new();
+		for (key, value) in entries {
+			let key = match key {
+				Key::Index(i) => i.to_string(),
+				Key::Name(name) => name,
+			};
+
+			if object.insert(key, value).is_some() {
+				bail!("Cannot encode a table with both a number and a string version of the same key");
+			}
+		}
+
+		Ok(Value::Object(object))
+	}
+}
+
+/// Pushes a JSON value onto the stack as a Lua value. Null becomes nil.
+pub fn push_json(lua: &LuaApi, state: *mut LuaState, value: &Value) -> anyhow::Result<()> {
+	push_json_at(lua, state, value, 0)
+}
+
+fn push_json_at(lua: &LuaApi, state: *mut LuaState, value: &Value, depth: usize) -> anyhow::Result<()> {
+	if depth > MAX_DEPTH || !lua.raw.checkstack(state, 3) {
+		bail!("Cannot decode values nested deeper than {MAX_DEPTH} levels");
+	}
+
+	match value {
+		Value::Null => lua.raw.pushnil(state),
+		Value::Bool(b) => lua.raw.pushboolean(state, *b),
+		Value::Number(n) => lua.raw.pushnumber(state, n.as_f64().unwrap_or(f64::NAN)),
+		Value::String(s) => lua.raw.push(state, s.as_str()),
+		Value::Array(array) => {
+			lua.raw.createtable(state, array.len() as c_int, 0);
+			for (i, value) in array.iter().enumerate() {
+				if let Err(why) = push_json_at(lua, state, value, depth + 1) {
+					lua.raw.pop(state, 1);
+					return Err(why);
+				}
+
+				lua.raw.rawseti(state, -2, i as c_int + 1);
+			}
+		}
+
+		Value::Object(object) => {
+			lua.raw.createtable(state, 0, object.len() as c_int);
+			for (key, value) in object {
+				lua.raw.push(state, key.as_str());
+				if let Err(why) = push_json_at(lua, state, value, depth + 1) {
+					lua.raw.pop(state, 2);
+					return Err(why);
+				}
+
+				lua.raw.rawset(state, -3);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Converts an encoded value into TOML, which has no null and requires a table at the root.
+pub fn json_to_toml(value: Value) -> anyhow::Result<toml::Table> {
+	match json_to_toml_value(value)? {
+		toml::Value::Table(table) => Ok(table),
+		_ => bail!("TOML can only encode tables with string keys at the top level"),
+	}
+}
+
+fn json_to_toml_value(value: Value) -> anyhow::Result<toml::Value> {
+	Ok(match value {
+		Value::Null => bail!("TOML cannot encode nil, use the 'object' sparse option for arrays with holes"),
+		Value::Bool(b) => toml::Value::Boolean(b),
+		Value::Number(n) => match n.as_i64() {
+			Some(i) => toml::Value::Integer(i),
+			None => toml::Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+		},
+		Value::String(s) => toml::Value::String(s),
+		Value::Array(array) => toml::Value::Array(array.into_iter().map(json_to_toml_value).collect::<Result<_, _>>()?),
+		Value::Object(object) => toml::Value::Table(
+			object
+				.into_iter()
+				.map(|(k, v)| Ok((k, json_to_toml_value(v)?)))
+				.collect::<anyhow::Result<_>>()?,
+		),
+	})
+}
+
+/// Pushes a TOML value onto the stack as a Lua value. Datetimes become strings.
+pub fn push_toml(lua: &LuaApi, state: *mut LuaState, value: &toml::Value) -> anyhow::Result<()> {
+	push_toml_at(lua, state, value, 0)
+}
+
+fn push_toml_at(lua: &LuaApi, state: *mut LuaState, value: &toml::Value, depth: usize) -> anyhow::Result<()> {
+	if depth > MAX_DEPTH || !lua.raw.checkstack(state, 3) {
+		bail!("Cannot decode values nested deeper than {MAX_DEPTH} levels");
+	}
+
+	match value {
+		toml::Value::Boolean(b) => lua.raw.pushboolean(state, *b),
+		toml::Value::Integer(i) => lua.raw.pushnumber(state, *i as f64),
+		toml::Value::Float(f) => lua.raw.pushnumber(state, *f),
+		toml::Value::String(s) => lua.raw.push(state, s.as_str()),
+		toml::Value::Datetime(dt) => lua.raw.push(state, dt.to_string()),
+		toml::Value::Array(array) => {
+			lua.raw.createtable(state, array.len() as c_int, 0);
+			for (i, value) in array.iter().enumerate() {
+				if let Err(why) = push_toml_at(lua, state, value, depth + 1) {
+					lua.raw.pop(state, 1);
+					return Err(why);
+				}
+
+				lua.raw.rawseti(state, -2, i as c_int + 1);
+			}
+		}
+
+		toml::Value::Table(table) => {
+			lua.raw.createtable(state, 0, table.len() as c_int);
+			for (key, value) in table {
+				lua.raw.push(state, key.as_str());
+				if let Err(why) = push_toml_at(lua, state, value, depth + 1) {
+					lua.raw.pop(state, 2);
+					return Err(why);
+				}
+
+				lua.raw.rawset(state, -3);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autorun_test::State;
+
+	fn json(state: &State, code: &str, sparse: SparseArrays) -> anyhow::Result<Value> {
+		let opts = EncodeOptions { pretty: false, sparse };
+		state.eval(code);
+		let value = to_json(state.lua(), state.state, -1, &opts);
+		state.lua().raw.pop(state.state, 1);
+		value
+	}
+
+	fn encode(state: &State, code: &str, sparse: SparseArrays) -> anyhow::Result<String> {
+		Ok(serde_json::to_string(&json(state, code, sparse)?)?)
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn sorts_keys_and_stringifies_number_keys() {
+		let state = State::new();
+
+		let code = "return { b = 1, a = { d = true, c = 'x' }, [2.5] = 2, [-1] = 0, list = { 'x', 'y' }, empty = {} }";
+		assert_eq!(
+			encode(&state, code, SparseArrays::Error).unwrap(),
+			r#"{"-1":0,"2.5":2,"a":{"c":"x","d":true},"b":1,"empty":{},"list":["x","y"]}"#
+		);
+
+		let why = encode(&state, "return { [true] = 1 }", SparseArrays::Error).unwrap_err();
+		assert!(why.to_string().contains("key of type"), "{why}");
+		let why = encode(&state, "return { [1] = 'a', ['1'] = 'b', x = 1 }", SparseArrays::Error).unwrap_err();
+		assert!(why.to_string().contains("same key"), "{why}");
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn encodes_sparse_arrays_by_option() {
+		let state = State::new();
+		let sparse = "return { 1, nil, 3 }";
+
+		let why = encode(&state, sparse, SparseArrays::Error).unwrap_err();
+		assert!(why.to_string().contains("sparse array"), "{why}");
+		assert_eq!(encode(&state, sparse, SparseArrays::Null).unwrap(), "[1,null,3]");
+		assert_eq!(encode(&state, sparse, SparseArrays::Object).unwrap(), r#"{"1":1,"3":3}"#);
+
+		let why = encode(&state, "return { [1] = 1, [1000] = 2 }", SparseArrays::Null).unwrap_err();
+		assert!(why.to_string().contains("too sparse"), "{why}");
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn round_trips_nested_tables() {
+		let state = State::new();
+		let lua = state.lua();
+		let code = "return { name = 'x', ratio = 0.5, list = { 1, 2, { k = 'v', deep = { true } } }, [10] = 'a', [20] = 'b' }";
+		let expected = r#"{"10":"a","20":"b","list":[1,2,{"deep":[true],"k":"v"}],"name":"x","ratio":0.5}"#;
+		assert_eq!(encode(&state, code, SparseArrays::Object).unwrap(), expected);
+
+		let json = json(&state, code, SparseArrays::Object).unwrap();
+		push_json(lua, state.state, &json).unwrap();
+		state.pop_global("decoded");
+		assert_eq!(encode(&state, "return decoded", SparseArrays::Error).unwrap(), expected);
+
+		let toml = toml::to_string(&json_to_toml(json).unwrap()).unwrap();
+		let table: toml::Table = toml::from_str(&toml).unwrap();
+		push_toml(lua, state.state, &toml::Value::Table(table)).unwrap();
+		state.pop_global("decoded");
+		assert_eq!(encode(&state, "return decoded", SparseArrays::Error).unwrap(), expected);
+		assert_eq!(state.results("decoded['10'], tostring(decoded.list[3].deep[1])"), "a,true");
+	}
+}
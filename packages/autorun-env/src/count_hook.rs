@@ -0,0 +1,198 @@
+//! Count hook shared by the watchdog and the sampler, as a state only holds a single hook at a time.
+//! Each listener asks for its own interval, the hook runs at the smallest one and calls them once theirs has passed.
+//! While it is set, a hook set by something else is suspended and the JIT is turned off, since compiled code doesn't run
+//! hooks. Both are put back as they were once no listener is left.
+use std::ffi::{c_int, c_void};
+use std::sync::Mutex;
+
+use autorun_lua::{LUA_MASKCOUNT, LUAJIT_MODE_ENGINE, LUAJIT_MODE_OFF, LUAJIT_MODE_ON, LuaApi, LuaTypeId, REGISTRY_INDEX};
+use autorun_types::LuaState;
+
+use crate::{sampler, watchdog};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Listener {
+	Sampler,
+	Watchdog,
+}
+
+const LISTENERS: usize = 2;
+
+struct Dispatch {
+	/// Registry of the state, identifying it along with its coroutines, which share its hook.
+	registry: usize,
+	/// Interval of each listener, and how many instructions ran since it was last called.
+	listeners: [Option<(c_int, c_int)>; LISTENERS],
+	/// Hook that was set before, as its function, mask and count.
+	previous: (usize, c_int, c_int),
+	/// Whether the JIT was on before.
+	jit: bool,
+}
+
+static DISPATCH: Mutex<Vec<Dispatch>> = Mutex::new(Vec::new());
+
+/// Calls `listener` every `interval` instructions run in the state, or stops calling it if `None`.
+pub fn set(lua: &LuaApi, state: *mut LuaState, listener: Listener, interval: Option<c_int>) {
+	let registry = lua.raw.topointer(state, REGISTRY_INDEX) as usize;
+	let mut dispatch = DISPATCH.lock().unwrap();
+
+	let pos = match dispatch.iter().position(|d| d.registry == registry) {
+		Some(pos) => {
+			// Something replaced the hook in the meantime, so that is what to put back.
+			let current = lua.raw.gethook(state);
+			if current != hook as *const c_void {
+				dispatch[pos].previous = (current as usize, lua.raw.gethookmask(state), lua.raw.gethookcount(state));
+			}
+
+			pos
+		}
+
+		None if interval.is_none() => return,
+
+		None => {
+			let previous = (
+				lua.raw.gethook(state) as usize,
+				lua.raw.gethookmask(state),
+				lua.raw.gethookcount(state),
+			);
+
+			let jit = jit_enabled(lua, state);
+			if jit {
+				lua.raw.jit_setmode(state, 0, LUAJIT_MODE_ENGINE | LUAJIT_MODE_OFF);
+			}
+
+			dispatch.push(Dispatch {
+				registry,
+				listeners: [None; LISTENERS],
+				previous,
+				jit,
+			});
+
+			dispatch.len() - 1
+		}
+	};
+
+	dispatch[pos].listeners[listener as usize] = interval.map(|interval| (interval.max(1), 0));
+
+	match dispatch[pos].listeners.iter().flatten().map(|(interval, _)| *interval).min() {
+		Some(count) => lua.raw.sethook(state, hook as *const c_void, LUA_MASKCOUNT, count),
+		None => {
+			let Dispatch { previous, jit, .. } = dispatch.remove(pos);
+			let (hook, mask, count) = previous;
+			lua.raw.sethook(state, hook as *const c_void, mask, count);
+
+			if jit {
+				lua.raw.jit_setmode(state, 0, LUAJIT_MODE_ENGINE | LUAJIT_MODE_ON);
+			}
+		}
+	}
+}
+
+/// Forgets the listeners of a state that is about to close, leaving the state itself alone.
+pub fn forget(lua: &LuaApi, state: *mut LuaState) {
+	forget_registry(lua.raw.topointer(state, REGISTRY_INDEX) as usize);
+}
+
+/// Same as [`forget`], from the address of the registry of a state that may already be closed.
+pub fn forget_registry(registry: usize) {
+	DISPATCH.lock().unwrap().retain(|d| d.registry != registry);
+}
+
+/// Whether the JIT is on, as `jit.status` tells.
+fn jit_enabled(lua: &LuaApi, state: *mut LuaState) -> bool {
+	let top = lua.raw.gettop(state);
+
+	lua.raw.getfield(state, REGISTRY_INDEX, c"_LOADED".as_ptr());
+	let enabled = lua.raw.typeid(state, -1) == LuaTypeId::Table && {
+		lua.raw.getfield(state, -1, c"jit".as_ptr());
+		lua.raw.typeid(state, -1) == LuaTypeId::Table && {
+			lua.raw.getfield(state, -1, c"status".as_ptr());
+			lua.raw.pcall(state, 0, 1, 0).is_ok() && lua.raw.toboolean(state, -1)
+		}
+	};
+
+	lua.raw.settop(state, top);
+	enabled
+}
+
+extern "C-unwind" fn hook(state: *mut LuaState, _ar: *mut c_void) {
+	let Ok(lua) = autorun_lua::get_api() else {
+		return;
+	};
+
+	let registry = lua.raw.topointer(state, REGISTRY_INDEX) as usize;
+	let ran = lua.raw.gethookcount(state);
+
+	let due = {
+		let mut dispatch = DISPATCH.lock().unwrap();
+		let Some(current) = dispatch.iter_mut().find(|d| d.registry == registry) else {
+			return;
+		};
+
+		let mut due = [None; LISTENERS];
+		for (listener, due) in current.listeners.iter_mut().zip(&mut due) {
+			if let Some((interval, count)) = listener {
+				*count += ran;
+				if *count >= *interval {
+					*due = Some(std::mem::take(count));
+				}
+			}
+		}
+
+		due
+	};
+
+	if due[Listener::Sampler as usize].is_some() {
+		sampler::sample_now(lua, state);
+	}
+
+	// Last, as it errors out of the hook once a budget is exceeded.
+	if let Some(instructions) = due[Listener::Watchdog as usize] {
+		watchdog::check(lua, state, instructions);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autorun_test::State;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn chains_listeners_and_puts_back_what_was_there() {
+		let _serial = autorun_test::serial();
+		let state = State::new();
+		let lua = state.lua();
+		state.run("debug.sethook(function() end, '', 50) _G.previous = debug.gethook()", 0);
+
+		set(lua, state.state, Listener::Sampler, Some(100));
+		set(lua, state.state, Listener::Watchdog, Some(1000));
+		assert_eq!(lua.raw.gethook(state.state), hook as *const c_void);
+		assert_eq!(
+			state.results("tostring(jit.status()), select(3, debug.gethook())"),
+			"false,100"
+		);
+
+		// The remaining listener keeps the hook, at its own interval.
+		set(lua, state.state, Listener::Sampler, None);
+		assert_eq!(
+			state.results("tostring(jit.status()), select(3, debug.gethook())"),
+			"false,1000"
+		);
+
+		set(lua, state.state, Listener::Watchdog, None);
+		assert_eq!(
+			state.results("tostring(jit.status()), tostring(debug.gethook() == previous), select(3, debug.gethook())"),
+			"true,true,50"
+		);
+
+		// The JIT is left off when that is how it was.
+		state.run("debug.sethook() jit.off()", 0);
+		set(lua, state.state, Listener::Watchdog, Some(1000));
+		set(lua, state.state, Listener::Watchdog, None);
+		assert_eq!(
+			state.results("tostring(jit.status()), tostring(debug.gethook())"),
+			"false,nil"
+		);
+	}
+}
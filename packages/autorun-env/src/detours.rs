@@ -0,0 +1,446 @@
+//! Registry of every detour created from Lua.
+//! Each detoured function gets a single target, either a retour detour for C functions or a patched prototype for Lua
+//! functions, and every hook placed on it forms an ordered chain that runs in creation order. Lua only ever holds hook
+//! ids, so removing a hook twice or using it after removal is just an error. Hooks belong to a state rather than the
+//! thread that created them, so they run and can be managed from any of its coroutines.
+//!
+//! The machine code and relocated prototypes of a target are never freed once created, since Lua may still hold an
+//! original function running them. A target without any enabled hooks is simply disabled.
+use std::sync::Mutex;
+
+use autorun_jit::Function;
+use autorun_lua::{LuaApi, LuaCFunction, RawHandle};
+use autorun_luajit::{GCProto, LJState, MRef, ProtoDetour};
+use autorun_types::{LuaState, Realm};
+
+use crate::lifecycle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+	/// Replaces the function, receiving the rest of the chain as `original`.
+	Detour,
+	/// Runs before the rest of the chain and may rewrite the arguments.
+	Before,
+	/// Runs once the call returns, on whatever it returned, and may rewrite the return values.
+	After,
+}
+
+impl HookKind {
+	pub fn name(&self) -> &'static str {
+		match self {
+			HookKind::Detour => "detour",
+			HookKind::Before => "before",
+			HookKind::After => "after",
+		}
+	}
+}
+
+struct Hook {
+	id: u32,
+	kind: HookKind,
+	realm: Realm,
+	/// Main thread of the state, see [`lifecycle::main_state`].
+	state: usize,
+	owner: Option<String>,
+	callback: RawHandle,
+	/// Handle to the detoured function in the state of the hook, so it can be listed.
+	target: RawHandle,
+	enabled: bool,
+}
+
+enum Backend {
+	Native {
+		detour: Box<retour::GenericDetour<LuaCFunction>>,
+		original: LuaCFunction,
+		_detour_trampoline: Function,
+		_retour_trampoline: Function,
+	},
+	Proto {
+		patch: ProtoDetour,
+		realm: Realm,
+		/// Table keeping the stub and the constants of the original code alive, see [`Target::proto`].
+		anchors: RawHandle,
+		/// Cleared once the prototype may have been collected, after which it is never touched again.
+		live: bool,
+	},
+}
+
+/// A detoured function, shared by every hook placed on it.
+pub struct Target {
+	address: usize,
+	backend: Backend,
+	hooks: Vec<Hook>,
+}
+
+// SAFETY: Targets are only created, called and modified on the game thread.
+// The mutex below only exists so they can live in a static.
+unsafe impl Send for Target {}
+
+impl Target {
+	/// `original` must call through `detour`, and `detour_trampoline` must run the chain of this target.
+	pub fn native(
+		address: usize,
+		detour: Box<retour::GenericDetour<LuaCFunction>>,
+		original: LuaCFunction,
+		detour_trampoline: Function,
+		retour_trampoline: Function,
+	) -> Self {
+		Self {
+			address,
+			backend: Backend::Native {
+				detour,
+				original,
+				_detour_trampoline: detour_trampoline,
+				_retour_trampoline: retour_trampoline,
+			},
+			hooks: Vec::new(),
+		}
+	}
+
+	/// `patch` must redirect to a stub running the chain of this target, which `anchors` keeps alive along with the
+	/// constants of the original code. The prototype itself is kept alive by the hooks, which hold one of its closures.
+	pub fn proto(patch: ProtoDetour, realm: Realm, anchors: RawHandle) -> Self {
+		Self {
+			address: patch.target() as usize,
+			backend: Backend::Proto {
+				patch,
+				realm,
+				anchors,
+				live: true,
+			},
+			hooks: Vec::new(),
+		}
+	}
+
+	/// Enables the detour only while there is a hook to run, so an unused target behaves exactly like the original.
+	/// Lua functions are only patched once `thread` shows none of their closures are running, see [`is_running`].
+	/// Without it they are left unpatched, which only removing hooks relies on as it never needs to patch anything.
+	fn sync(&mut self, thread: Option<(&LuaApi, *mut LuaState)>) -> anyhow::Result<()> {
+		let wanted = self.hooks.iter().any(|h| h.enabled);
+
+		match &mut self.backend {
+			Backend::Native { detour, .. } if wanted != detour.is_enabled() => unsafe {
+				match wanted {
+					true => detour.enable()?,
+					false => detour.disable()?,
+				}
+			},
+			Backend::Proto { patch, live: true, .. } if wanted && !patch.is_enabled() => {
+				if thread.is_none_or(|(lua, state)| is_running(lua, state, patch.target())) {
+					anyhow::bail!("Cannot detour a function while it is running.");
+				}
+
+				unsafe { patch.enable() }
+			}
+			Backend::Proto { patch, live: true, .. } if !wanted => unsafe { patch.disable() },
+			_ => {}
+		}
+
+		Ok(())
+	}
+
+	/// Stops tracking the prototype of a Lua function once nothing holds on to it anymore, as its address may be
+	/// reused. Must be called after the last hook was removed and the target synced.
+	fn retire_unused(&mut self) {
+		if let Backend::Proto { live, .. } = &mut self.backend
+			&& self.hooks.is_empty()
+		{
+			*live = false;
+			self.address = 0;
+		}
+	}
+}
+
+struct Registry {
+	next_id: u32,
+	/// Indexed by the target id baked into its trampoline, never shrinks.
+	targets: Vec<Target>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+	next_id: 1,
+	targets: Vec::new(),
+});
+
+/// Information about a hook, as returned by [`list`].
+#[derive(Debug, Clone)]
+pub struct HookInfo {
+	pub id: u32,
+	pub kind: HookKind,
+	pub target: RawHandle,
+	pub owner: Option<String>,
+	pub enabled: bool,
+}
+
+/// A hook to add to a chain with [`add_hook`]. Its handles are owned by the hook from then on.
+pub struct NewHook {
+	pub kind: HookKind,
+	pub realm: Realm,
+	pub owner: Option<String>,
+	pub callback: RawHandle,
+	/// Handle to the function the hook is placed on.
+	pub target: RawHandle,
+}
+
+/// A hook to run as part of a chain, as returned by [`chain`].
+#[derive(Debug, Clone)]
+pub struct ChainHook {
+	pub kind: HookKind,
+	pub callback: RawHandle,
+	pub realm: Realm,
+	pub owner: Option<String>,
+}
+
+/// How to call the function a hook was placed on, as returned by [`original`].
+pub enum Original {
+	/// Calls through the detour of a C function.
+	Native(LuaCFunction),
+	/// Lua functions have a separate original per closure, made by pointing a closure sharing the upvalues of
+	/// `target` at `pc`. `anchors` is the table of the target, see [`Target::proto`].
+	Proto {
+		target: RawHandle,
+		anchors: RawHandle,
+		pc: MRef,
+	},
+}
+
+/// Returns the id of the target for the function at `address`, if it is detoured.
+pub fn find_target(address: usize) -> Option<u32> {
+	let registry = REGISTRY.lock().unwrap();
+	registry
+		.targets
+		.iter()
+		.position(|t| t.address == address)
+		.map(|index| index as u32)
+}
+
+/// Adds a target built outside of the registry, for when doing so has to run Lua code.
+/// Its hooks only get to know the id afterwards.
+pub fn insert_target(target: Target) -> u32 {
+	let mut registry = REGISTRY.lock().unwrap();
+	registry.targets.push(target);
+
+	registry.targets.len() as u32 - 1
+}
+
+/// Returns the id of the target for the function at `address`, creating it with `create` if it isn't detoured yet.
+/// `create` receives the id the new target will have.
+pub fn get_or_create_target(address: usize, create: impl FnOnce(u32) -> anyhow::Result<Target>) -> anyhow::Result<u32> {
+	let mut registry = REGISTRY.lock().unwrap();
+	if let Some(index) = registry.targets.iter().position(|t| t.address == address) {
+		return Ok(index as u32);
+	}
+
+	let index = registry.targets.len() as u32;
+	registry.targets.push(create(index)?);
+
+	Ok(index)
+}
+
+/// Adds a hook placed from the given state to the end of the chain of a target, returning the id Lua will refer to it by.
+pub fn add_hook(lua: &LuaApi, state: *mut LuaState, target_id: u32, hook: NewHook) -> anyhow::Result<u32> {
+	let main = lifecycle::main_state(lua, state);
+	let mut registry = REGISTRY.lock().unwrap();
+
+	let id = registry.next_id;
+	registry.next_id = registry.next_id.wrapping_add(1).max(1);
+
+	let entry = registry
+		.targets
+		.get_mut(target_id as usize)
+		.ok_or_else(|| anyhow::anyhow!("Detour target does not exist."))?;
+
+	entry.hooks.push(Hook {
+		id,
+		kind: hook.kind,
+		realm: hook.realm,
+		state: main as usize,
+		owner: hook.owner,
+		callback: hook.callback,
+		target: hook.target,
+		enabled: true,
+	});
+
+	if let Err(why) = entry.sync(Some((lua, state))) {
+		entry.hooks.pop();
+		return Err(why);
+	}
+
+	Ok(id)
+}
+
+fn check_owner(hook: &Hook, caller: Option<&str>) -> anyhow::Result<()> {
+	match hook.owner.as_deref() {
+		Some(owner) if Some(owner) != caller => anyhow::bail!("Detour belongs to plugin '{owner}'."),
+		_ => Ok(()),
+	}
+}
+
+/// Finds the target and position of a hook placed from any thread of a state, failing if it doesn't exist or belongs to
+/// a plugin other than `caller`.
+fn find(
+	registry: &Registry,
+	lua: &LuaApi,
+	state: *mut LuaState,
+	id: u32,
+	caller: Option<&str>,
+) -> anyhow::Result<(usize, usize)> {
+	let main = lifecycle::main_state(lua, state) as usize;
+	for (target_index, target) in registry.targets.iter().enumerate() {
+		if let Some(pos) = target.hooks.iter().position(|h| h.id == id && h.state == main) {
+			check_owner(&target.hooks[pos], caller)?;
+			return Ok((target_index, pos));
+		}
+	}
+
+	anyhow::bail!("Detour does not exist or was already removed.")
+}
+
+/// Enables or disables a single hook, failing if it doesn't exist or belongs to a plugin other than `caller`.
+pub fn set_enabled(lua: &LuaApi, state: *mut LuaState, id: u32, caller: Option<&str>, enabled: bool) -> anyhow::Result<()> {
+	let mut registry = REGISTRY.lock().unwrap();
+	let (target_index, pos) = find(&registry, lua, state, id, caller)?;
+
+	let target = &mut registry.targets[target_index];
+	let was_enabled = std::mem::replace(&mut target.hooks[pos].enabled, enabled);
+	target
+		.sync(Some((lua, state)))
+		.inspect_err(|_| target.hooks[pos].enabled = was_enabled)
+}
+
+/// Returns the original function of the target of a hook.
+/// Fails if the hook doesn't exist or belongs to a plugin other than `caller`.
+pub fn original(lua: &LuaApi, state: *mut LuaState, id: u32, caller: Option<&str>) -> anyhow::Result<Original> {
+	let registry = REGISTRY.lock().unwrap();
+	let (target_index, pos) = find(&registry, lua, state, id, caller)?;
+
+	let target = &registry.targets[target_index];
+	Ok(match &target.backend {
+		Backend::Native { original, .. } => Original::Native(*original),
+		Backend::Proto { patch, anchors, .. } => Original::Proto {
+			target: target.hooks[pos].target,
+			anchors: *anchors,
+			pc: patch.original_pc(),
+		},
+	})
+}
+
+/// Returns the original function of a detoured C function.
+pub fn native_original(target_id: u32) -> Option<LuaCFunction> {
+	let registry = REGISTRY.lock().unwrap();
+	match registry.targets.get(target_id as usize)?.backend {
+		Backend::Native { original, .. } => Some(original),
+		Backend::Proto { .. } => None,
+	}
+}
+
+fn free(lua: &LuaApi, state: *mut LuaState, hook: Hook) {
+	let _ = hook.callback.free(lua, state);
+	let _ = hook.target.free(lua, state);
+}
+
+/// Removes a hook from its chain, failing if it doesn't exist or belongs to a plugin other than `caller`.
+pub fn remove(lua: &LuaApi, state: *mut LuaState, id: u32, caller: Option<&str>) -> anyhow::Result<()> {
+	let mut registry = REGISTRY.lock().unwrap();
+	let (target_index, pos) = find(&registry, lua, state, id, caller)?;
+
+	let target = &mut registry.targets[target_index];
+	let hook = target.hooks.remove(pos);
+	let synced = target.sync(None);
+	target.retire_unused();
+
+	free(lua, state, hook);
+	synced
+}
+
+/// Removes every hook created by the given plugin in a realm.
+pub fn remove_plugin(lua: &LuaApi, state: *mut LuaState, realm: Realm, plugin: &str) {
+	let mut registry = REGISTRY.lock().unwrap();
+	for target in &mut registry.targets {
+		let (removed, kept) = std::mem::take(&mut target.hooks)
+			.into_iter()
+			.partition::<Vec<_>, _>(|h| h.realm == realm && h.owner.as_deref() == Some(plugin));
+
+		target.hooks = kept;
+		let _ = target.sync(None);
+		if !removed.is_empty() {
+			target.retire_unused();
+		}
+
+		for hook in removed {
+			free(lua, state, hook);
+		}
+	}
+}
+
+/// Whether a closure of `proto` is running on the stack of `state` or of the main thread of its state, which are the
+/// only threads that can be resuming `state`. Coroutines that yielded, or resumed the main thread through another
+/// coroutine, have stacks of their own that can't be found, so their frames aren't seen.
+pub fn is_running(lua: &LuaApi, state: *mut LuaState, proto: *const GCProto) -> bool {
+	let main = lifecycle::main_state(lua, state);
+	[state, main]
+		.into_iter()
+		.any(|thread| autorun_luajit::is_running(thread as *mut LJState, proto))
+}
+
+/// Removes every hook of a realm whose state has been torn down.
+/// Must be called once the old state is gone, as the registry refs are not freed, and patched prototypes of Lua
+/// functions died with the state.
+pub fn remove_realm(realm: Realm) {
+	let mut registry = REGISTRY.lock().unwrap();
+	for target in &mut registry.targets {
+		target.hooks.retain(|h| h.realm != realm);
+
+		if let Backend::Proto { realm: proto_realm, .. } = target.backend
+			&& proto_realm == realm
+		{
+			target.hooks.clear();
+			target.retire_unused();
+		}
+
+		let _ = target.sync(None);
+	}
+}
+
+/// Returns the enabled hooks of a target placed from any thread of the given state, in chain order.
+pub fn chain(lua: &LuaApi, target_id: u32, state: *mut LuaState) -> Option<Vec<ChainHook>> {
+	let main = lifecycle::main_state(lua, state) as usize;
+	let registry = REGISTRY.lock().unwrap();
+	let target = registry.targets.get(target_id as usize)?;
+
+	let hooks = target
+		.hooks
+		.iter()
+		.filter(|h| h.enabled && h.state == main)
+		.map(|h| ChainHook {
+			kind: h.kind,
+			callback: h.callback,
+			realm: h.realm,
+			owner: h.owner.clone(),
+		})
+		.collect();
+
+	Some(hooks)
+}
+
+/// Lists the hooks created from any thread of the given state, in creation order.
+pub fn list(lua: &LuaApi, state: *mut LuaState) -> Vec<HookInfo> {
+	let main = lifecycle::main_state(lua, state) as usize;
+	let registry = REGISTRY.lock().unwrap();
+	let mut hooks: Vec<HookInfo> = registry
+		.targets
+		.iter()
+		.flat_map(|t| &t.hooks)
+		.filter(|h| h.state == main)
+		.map(|h| HookInfo {
+			id: h.id,
+			kind: h.kind,
+			target: h.target,
+			owner: h.owner.clone(),
+			enabled: h.enabled,
+		})
+		.collect();
+
+	hooks.sort_by_key(|h| h.id);
+	hooks
+}
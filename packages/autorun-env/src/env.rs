@@ -1,22 +1,44 @@
 pub mod global;
 
 use anyhow::Context;
-use autorun_core::plugins::Plugin;
+use autorun_core::plugins::{ConfigPluginLanguage, Plugin};
 use autorun_log::*;
-use autorun_lua::{Globals, IntoLua, IntoLuaArgs, LuaApi, LuaFunction, LuaTable, LuaValue, RawLuaApi};
+use autorun_lua::{Globals, IntoLua, IntoLuaArgs, LUA_MULTRET, LuaApi, LuaFunction, LuaTable, LuaTypeId, RawLuaApi};
 use autorun_luajit::{GCRef, LJState, index2adr};
-use autorun_types::{LuaState, Realm};
+use autorun_types::{Budget, LuaState, Realm};
 use std::ffi::{CStr, CString, c_int};
+use std::sync::{Arc, Mutex};
 
-use crate::functions;
+use crate::{chunks, detours, errors, events, functions, modules, profiler, timers, watchdog};
 
-#[derive(Debug, Clone, Copy)]
+/// Environment of a single plugin, which falls back to a read-only view of the shared environment for anything it
+/// doesn't define itself.
+#[derive(Debug)]
+struct PluginEnv {
+	plugin: Arc<Plugin>,
+	env: LuaTable,
+	env_gcr: GCRef,
+	/// Whether the plugin runs in the shared environment rather than its own.
+	shared: bool,
+	/// Results of the modules required from the plugin, by path.
+	modules: LuaTable,
+}
+
+#[derive(Debug, Clone)]
 pub struct EnvHandle {
 	realm: Realm,
 	env_gcr: GCRef,
 	chunk_nonce: u64,
+	/// Shared environment holding the builtins and the Autorun table.
 	env: LuaTable,
 	autorun: LuaTable,
+	/// Plugins allowed to run in the shared environment when they ask to, by name.
+	shared_plugins: Arc<[String]>,
+	plugins: Arc<Mutex<Vec<PluginEnv>>>,
+	/// Plugin whose code is currently being run from Rust, used when the stack doesn't tell us.
+	current_plugin: Arc<Mutex<Option<Arc<Plugin>>>>,
+	/// Modules being required, as `plugin:path`, to catch them requiring each other.
+	requiring: Arc<Mutex<Vec<String>>>,
 }
 
 impl IntoLua for &EnvHandle {
@@ -25,104 +47,357 @@ impl IntoLua for &EnvHandle {
 	}
 }
 
+/// Environment of the realm of `state`, provided the caller runs in it. Otherwise warns about the call to `name` and
+/// fails with an empty message.
+fn authorize(lua: &LuaApi, state: *mut LuaState, name: &str) -> anyhow::Result<EnvHandle> {
+	let realm = crate::global::get_realm(state);
+	let env = crate::global::get_realm_env(realm).ok_or_else(|| anyhow::anyhow!("env doesn't exist somehow"))?;
+
+	if !env.is_active(lua, state) {
+		warn!("Attempted to call '{name}' outside of authorized environment");
+
+		// todo: potentially add a silenterror type so we can return that and it'll return a nil.
+		// right now this would kind of leak the fact that it's an autorun function.
+		anyhow::bail!("");
+	}
+
+	Ok(env)
+}
+
 macro_rules! wrap {
 	($func:expr) => {
 		autorun_lua::as_lua_function!(|lua: &LuaApi, state: *mut LuaState| {
-			let realm = crate::global::get_realm(state);
-			let env = crate::global::get_realm_env(realm).ok_or_else(|| anyhow::anyhow!("env doesn't exist somehow"))?;
-
-			if !env.is_active(lua, state) {
-				warn!(
-					"Attempted to call '{}' outside of authorized environment",
-					stringify!($func)
-				);
-
-				// todo: potentially add a silenterror type so we can return that and it'll return a nil.
-				// right now this would kind of leak the fact that it's an autorun function.
-				lua.error(state, c"");
-			} else {
-				$func(lua, state, env)
-			}
+			let env = authorize(lua, state, stringify!($func))?;
+			$func(lua, state, env)
+		})
+	};
+}
+
+/// The caller of a typed function along with the environment it runs in, taken before any of the Lua arguments.
+/// Fails just like [`authorize`] when the caller isn't authorized, naming the function as Lua called it.
+struct Authorized(&'static LuaApi, *mut LuaState, EnvHandle);
+
+unsafe impl autorun_lua::LuaArgument for Authorized {
+	fn take(_lua: &RawLuaApi, state: *mut LuaState, _index: &mut c_int) -> Result<Self, String> {
+		let lua = autorun_lua::get_api().map_err(|why| why.to_string())?;
+		let name = match lua.raw.getinfo(state, 0, c"n") {
+			Some(info) if !info.name.is_null() => unsafe { CStr::from_ptr(info.name) }.to_string_lossy(),
+			_ => "?".into(),
+		};
+
+		let env = authorize(lua, state, &name).map_err(|why| why.to_string())?;
+		Ok(Authorized(lua, state, env))
+	}
+}
+
+/// Like [`wrap!`], for functions taking typed Lua arguments after the environment, through [`LuaApi::create_function`].
+/// The caller is checked before any argument, so it gets nothing but the empty error either way.
+/// Argument types are those `$func` takes, only their names are given.
+macro_rules! typed {
+	($lua:expr, $state:expr, $func:path, |$($arg:ident),*|) => {
+		$lua.create_function($state, |Authorized(lua, state, env): Authorized, $($arg),*| {
+			$func(lua, state, env, $($arg),*)
 		})
 	};
 }
 
+/// Raises an error when assigning to a read-only view, as made by [`push_read_only`].
+fn assign_read_only(lua: &LuaApi, state: *mut LuaState) -> anyhow::Result<()> {
+	match lua.raw.tostring(state, 2) {
+		Some(key) => anyhow::bail!("Can't assign '{key}' in a table shared by every plugin"),
+		None => anyhow::bail!("Can't assign to a table shared by every plugin"),
+	}
+}
+
+/// Replaces the table at the top of the stack with a read-only view of it, which reads fall through to and assigning to
+/// errors. Tables it holds get a view of their own too, `depth` levels down, except for the globals.
+fn push_read_only(lua: &LuaApi, state: *mut LuaState, depth: usize) {
+	lua.raw.push(state, Globals);
+	let globals = lua.raw.topointer(state, -1);
+	lua.raw.pop(state, 1);
+
+	// Views of the tables it holds sit between the view and the table, as they could be assigned away in the view.
+	lua.raw.createtable(state, 0, 0);
+
+	if depth > 0 {
+		lua.raw.pushnil(state);
+		while lua.raw.next(state, -3) != 0 {
+			if lua.raw.typeid(state, -1) == LuaTypeId::Table && lua.raw.topointer(state, -1) != globals {
+				push_read_only(lua, state, depth - 1);
+				lua.raw.pushvalue(state, -2);
+				lua.raw.insert(state, -2);
+				lua.raw.rawset(state, -4);
+			} else {
+				lua.raw.pop(state, 1);
+			}
+		}
+	}
+
+	lua.raw.createtable(state, 0, 1);
+	lua.raw.push(state, c"__index");
+	lua.raw.pushvalue(state, -4);
+	lua.raw.rawset(state, -3);
+	lua.raw.setmetatable(state, -2);
+
+	lua.raw.createtable(state, 0, 0);
+	lua.raw.createtable(state, 0, 3);
+	lua.raw.push(state, c"__index");
+	lua.raw.pushvalue(state, -4);
+	lua.raw.rawset(state, -3);
+	lua.raw.push(state, c"__newindex");
+	lua.raw.pushcfunction(state, autorun_lua::as_lua_function!(assign_read_only));
+	lua.raw.rawset(state, -3);
+	lua.raw.push(state, c"__metatable");
+	lua.raw.pushboolean(state, false);
+	lua.raw.rawset(state, -3);
+	lua.raw.setmetatable(state, -2);
+
+	lua.raw.remove(state, -2);
+	lua.raw.remove(state, -2);
+}
+
 impl EnvHandle {
 	pub fn realm(&self) -> Realm {
 		self.realm
 	}
 
-	pub fn is_function_authorized(&self, lua: &LuaApi, state: *mut LuaState, func_index: Option<i32>) -> anyhow::Result<bool> {
-		let func_index = func_index.unwrap_or(-1);
-
+	/// Returns the GCRef of the table at the given stack index.
+	fn gcr_at(state: *mut LuaState, index: c_int) -> anyhow::Result<GCRef> {
 		let lj_state = state as *mut LJState;
 		let lj_state = unsafe { lj_state.as_ref().context("Failed to dereference LJState")? };
+		let tvalue = index2adr(lj_state, index).context("Failed to get TValue for environment")?;
 
-		lua.raw.getfenv(state, func_index);
-		let function_env_tvalue = index2adr(lj_state, -1).context("Failed to get TValue for function environment")?;
-		let function_env_gcr = unsafe { (*function_env_tvalue).gcr };
+		Ok(unsafe { (*tvalue).gcr })
+	}
 
-		lua.raw.pop(state, 1);
-		Ok(function_env_gcr == self.env_gcr)
+	/// Whether the given environment is the shared environment or one of the plugin environments.
+	fn is_family(&self, env_gcr: GCRef) -> bool {
+		env_gcr == self.env_gcr || self.plugins.lock().unwrap().iter().any(|p| p.env_gcr == env_gcr)
 	}
 
-	pub fn is_active(&self, lua: &LuaApi, state: *mut LuaState) -> bool {
-		let Ok(info) = lua.getinfo(state, 1, c"f") else {
-			return false;
-		};
+	/// Returns the environment GCRef of the function at the given stack level, if there is one.
+	fn level_env_gcr(lua: &LuaApi, state: *mut LuaState, level: c_int) -> Option<GCRef> {
+		lua.raw.getinfo(state, level, c"f")?;
+		lua.raw.getfenv(state, -1);
+		let env_gcr = Self::gcr_at(state, -1).ok();
+		lua.raw.pop(state, 2);
 
-		let Ok(Some(env)) = lua.getfenv(state, &info.func.unwrap()) else {
-			return false;
-		};
+		env_gcr
+	}
 
-		lua.equal(state, &env, &self.env)
+	pub fn is_function_authorized(&self, lua: &LuaApi, state: *mut LuaState, func_index: Option<i32>) -> anyhow::Result<bool> {
+		let func_index = func_index.unwrap_or(-1);
+
+		lua.raw.getfenv(state, func_index);
+		let function_env_gcr = Self::gcr_at(state, -1);
+		lua.raw.pop(state, 1);
+
+		Ok(self.is_family(function_env_gcr?))
 	}
 
-	pub fn get_active_plugin(&self, lua: &LuaApi, state: *mut LuaState) -> Option<&Plugin> {
+	pub fn is_active(&self, lua: &LuaApi, state: *mut LuaState) -> bool {
+		match Self::level_env_gcr(lua, state, 1) {
+			Some(env_gcr) => self.is_family(env_gcr),
+			None => false,
+		}
+	}
+
+	/// Finds the plugin responsible for the current call.
+	/// This is the innermost function on the stack running inside a plugin environment, skipping anything running in
+	/// the shared environment (like std helpers) or outside of autorun. Falls back to the plugin currently being run from Rust.
+	pub fn get_active_plugin(&self, lua: &LuaApi, state: *mut LuaState) -> Option<Arc<Plugin>> {
 		if !self.is_active(lua, state) {
 			return None;
 		}
 
-		let dir: *mut Plugin = lua.get(state, &self.autorun, "PLUGIN").ok()?;
-		let dir = unsafe { dir.as_ref() }?;
+		let mut level = 1;
+		while let Some(env_gcr) = Self::level_env_gcr(lua, state, level) {
+			if env_gcr != self.env_gcr {
+				let plugins = self.plugins.lock().unwrap();
+				if let Some(entry) = plugins.iter().find(|p| p.env_gcr == env_gcr) {
+					return Some(entry.plugin.clone());
+				}
+			}
+
+			level += 1;
+		}
 
-		Some(dir)
+		self.current_plugin.lock().unwrap().clone()
 	}
 
-	fn create_autorun_table(lua: &LuaApi, state: *mut LuaState) -> LuaTable {
+	fn create_autorun_table(lua: &'static LuaApi, state: *mut LuaState) -> LuaTable {
 		let t = lua.table(state);
 		lua.set(state, &t, "print", wrap!(functions::print));
-		lua.set(state, &t, "read", wrap!(functions::read));
-		lua.set(state, &t, "write", wrap!(functions::write));
-		lua.set(state, &t, "writeAsync", wrap!(functions::write_async));
-		lua.set(state, &t, "mkdir", wrap!(functions::mkdir));
-		lua.set(state, &t, "append", wrap!(functions::append));
-		lua.set(state, &t, "exists", wrap!(functions::exists));
+		lua.set(state, &t, "inspect", wrap!(functions::inspect));
+		lua.set(state, &t, "read", &typed!(lua, state, functions::read, |path|));
+		lua.set(
+			state,
+			&t,
+			"readAsync",
+			&typed!(lua, state, functions::read_async, |path, callback|),
+		);
+		lua.set(state, &t, "write", &typed!(lua, state, functions::write, |path, content|));
+		lua.set(
+			state,
+			&t,
+			"writeAsync",
+			&typed!(lua, state, functions::write_async, |path, content, callback|),
+		);
+		lua.set(state, &t, "mkdir", &typed!(lua, state, functions::mkdir, |path|));
+		lua.set(state, &t, "append", &typed!(lua, state, functions::append, |path, content|));
+		lua.set(state, &t, "exists", &typed!(lua, state, functions::exists, |path|));
 		lua.set(state, &t, "detour", wrap!(functions::detour));
+		lua.set(state, &t, "hookBefore", wrap!(functions::hook_before));
+		lua.set(state, &t, "hookAfter", wrap!(functions::hook_after));
 		lua.set(state, &t, "enableDetour", wrap!(functions::detour_enable));
 		lua.set(state, &t, "disableDetour", wrap!(functions::detour_disable));
 		lua.set(state, &t, "removeDetour", wrap!(functions::detour_remove));
+		lua.set(state, &t, "listDetours", wrap!(functions::detour_list));
 		lua.set(state, &t, "getOriginalFunction", wrap!(functions::detour_get_original));
 		lua.set(state, &t, "copyFastFunction", wrap!(functions::copy_fast_function));
-		lua.set(state, &t, "load", wrap!(functions::load));
-		lua.set(state, &t, "triggerRemote", wrap!(functions::trigger_remote));
+		lua.set(state, &t, "load", &typed!(lua, state, functions::load, |source, chunk_name|));
+		lua.set(state, &t, "require", &typed!(lua, state, functions::require, |name|));
+		lua.set(
+			state,
+			&t,
+			"on",
+			&typed!(lua, state, functions::on, |event, callback, priority|),
+		);
+		lua.set(
+			state,
+			&t,
+			"once",
+			&typed!(lua, state, functions::once, |event, callback, priority|),
+		);
+		lua.set(state, &t, "off", &typed!(lua, state, functions::off, |id|));
+		lua.set(
+			state,
+			&t,
+			"onError",
+			&typed!(lua, state, functions::on_error, |callback, priority|),
+		);
+		lua.set(state, &t, "getProfile", wrap!(functions::get_profile));
+		lua.set(state, &t, "startSampling", wrap!(functions::start_sampling));
+		lua.set(state, &t, "stopSampling", wrap!(functions::stop_sampling));
+		lua.set(state, &t, "unload", &typed!(lua, state, functions::unload, |name|));
+		lua.set(state, &t, "reload", &typed!(lua, state, functions::reload, |name|));
+		lua.set(state, &t, "trigger", &typed!(lua, state, functions::trigger, |event|));
+		lua.set(
+			state,
+			&t,
+			"triggerRemote",
+			&typed!(lua, state, functions::trigger_remote, |event|),
+		);
 		lua.set(state, &t, "isFunctionAuthorized", wrap!(functions::is_function_authorized));
 		lua.set(state, &t, "isProtoAuthorized", wrap!(functions::is_proto_authorized));
+		lua.set(
+			state,
+			&t,
+			"setTimeout",
+			&typed!(lua, state, functions::set_timeout, |callback, delay|),
+		);
+		lua.set(
+			state,
+			&t,
+			"setInterval",
+			&typed!(lua, state, functions::set_interval, |callback, delay|),
+		);
+		lua.set(state, &t, "cancel", &typed!(lua, state, functions::cancel, |id|));
+		lua.set(state, &t, "hash", wrap!(functions::hash));
+		lua.set(state, &t, "compress", wrap!(functions::compress));
+		lua.set(state, &t, "decompress", wrap!(functions::decompress));
 		lua.set(state, &t, "VERSION", env!("CARGO_PKG_VERSION"));
 
+		let json = lua.table(state);
+		lua.set(state, &json, "encode", wrap!(functions::json_encode));
+		lua.set(state, &json, "decode", wrap!(functions::json_decode));
+		lua.set(state, &t, "json", &json);
+
+		let toml = lua.table(state);
+		lua.set(state, &toml, "encode", wrap!(functions::toml_encode));
+		lua.set(state, &toml, "decode", &typed!(lua, state, functions::toml_decode, |src|));
+		lua.set(state, &t, "toml", &toml);
+
 		return t;
 	}
 
+	/// Loads and runs a chunk in `env`, reporting any error to [`errors`] on behalf of `plugin`.
+	fn run_chunk(
+		&self,
+		lua: &LuaApi,
+		state: *mut LuaState,
+		plugin: Option<&Plugin>,
+		name: &CStr,
+		src: &[u8],
+		env: &LuaTable,
+	) -> anyhow::Result<()> {
+		let owner = plugin.map(|plugin| plugin.config().plugin.name.clone());
+		let chunk_name = self.format_chunk_name(plugin, name)?;
+		let site = format!("chunk '{}'", name.to_string_lossy());
+
+		let chunk = match lua.load(state, src, &chunk_name) {
+			Ok(chunk) => chunk,
+			Err(why) => {
+				let why = errors::CallError::from(why);
+				let message = why.message.clone();
+				errors::report(lua, state, self.realm, owner, &site, why);
+				anyhow::bail!(message);
+			}
+		};
+
+		lua.setfenv(state, &chunk, env)?;
+		lua.raw.push(state, &chunk);
+		watchdog::exclude_from_jit(lua, state, owner.as_deref(), -1);
+
+		let result = profiler::measure(self.realm, owner.as_deref(), &site, || {
+			watchdog::run_plugin(lua, state, owner.as_deref(), || errors::pcall(lua, state, 0, 0))
+		});
+
+		result.map_err(|why| {
+			let message = why.message.clone();
+			errors::report(lua, state, self.realm, owner, &site, why);
+			anyhow::anyhow!(message)
+		})
+	}
+
 	pub fn execute(&self, lua: &LuaApi, state: *mut LuaState, name: &CStr, src: &[u8]) -> anyhow::Result<()> {
-		let name = self.format_chunk_name(name)?;
-		let chunk = lua.load(state, src, &name)?;
-		lua.setfenv(state, &chunk, &self.env)?;
-		lua.pcall(state, &chunk, ())?;
+		self.run_chunk(lua, state, None, name, src, &self.env)
+	}
 
-		Ok(())
+	/// Runs a chunk inside the environment of a plugin previously registered with [`EnvHandle::add_plugin`].
+	pub fn execute_plugin(
+		&self,
+		lua: &LuaApi,
+		state: *mut LuaState,
+		plugin: &Arc<Plugin>,
+		name: &CStr,
+		src: &[u8],
+	) -> anyhow::Result<()> {
+		let env = {
+			let plugins = self.plugins.lock().unwrap();
+			let entry = plugins
+				.iter()
+				.find(|p| Arc::ptr_eq(&p.plugin, plugin))
+				.ok_or_else(|| anyhow::anyhow!("Plugin '{plugin}' has no environment"))?;
+
+			entry.env.clone()
+		};
+
+		let previous = self.current_plugin.lock().unwrap().replace(plugin.clone());
+		let result = self.run_chunk(lua, state, Some(plugin), name, src, &env);
+		*self.current_plugin.lock().unwrap() = previous;
+
+		result
 	}
 
-	pub fn create(lua: &LuaApi, state: *mut LuaState, realm: Realm) -> anyhow::Result<Self> {
+	/// Creates the shared environment of a realm. Plugins asking for it with `shared = true` only get to run in it if they
+	/// are one of `shared_plugins`, as they can change what every other plugin sees.
+	pub fn create(
+		lua: &'static LuaApi,
+		state: *mut LuaState,
+		realm: Realm,
+		shared_plugins: Vec<String>,
+	) -> anyhow::Result<Self> {
 		let autorun = Self::create_autorun_table(lua, state);
 
 		let env = lua.table(state);
@@ -131,29 +406,31 @@ impl EnvHandle {
 
 		// todo: refactor luajit code to not depend on the stack
 		lua.raw.push(state, &env);
-
-		// Can unwrap since we are sure there is something on the stack
-		let lj_state = state as *mut LJState;
-		let lj_state = unsafe { lj_state.as_ref().context("Failed to dereference LJState")? };
-		let env_tvalue = index2adr(lj_state, -1).context("Failed to get TValue for environment")?;
-		let env_gcr = unsafe { (*env_tvalue).gcr };
-
-		// todo: refactor luajit code to not depend on the stack
+		let env_gcr = Self::gcr_at(state, -1);
 		lua.raw.pop(state, 1);
 
 		let chunk_nonce = rand::random::<u64>();
 		Ok(Self {
 			realm,
-			env_gcr,
+			env_gcr: env_gcr?,
 			chunk_nonce,
 			env,
 			autorun,
+			shared_plugins: shared_plugins.into(),
+			plugins: Arc::default(),
+			current_plugin: Arc::default(),
+			requiring: Arc::default(),
 		})
 	}
 
-	pub fn format_chunk_name(&self, base: &CStr) -> anyhow::Result<CString> {
-		let formatted = format!("{}-{}", self.chunk_nonce, base.to_str()?);
-		Ok(CString::new(formatted)?)
+	/// Returns the name to load a chunk of the given plugin with, which [`chunks::prettify`] turns back into
+	/// `plugin:base` in errors.
+	pub fn format_chunk_name(&self, plugin: Option<&Plugin>, base: &CStr) -> anyhow::Result<CString> {
+		let base = base.to_str()?;
+		match plugin {
+			Some(plugin) => chunks::register(self.chunk_nonce, &format!("{}:{base}", plugin.config().plugin.name)),
+			None => chunks::register(self.chunk_nonce, base),
+		}
 	}
 
 	pub fn is_chunk_name_authorized(&self, chunk_name: &CStr) -> bool {
@@ -163,21 +440,434 @@ impl EnvHandle {
 		}
 	}
 
-	pub fn set_plugin(&self, lua: &LuaApi, state: *mut LuaState, plugin: &Plugin) -> anyhow::Result<()> {
-		lua.set(state, &self.autorun, "PLUGIN", plugin.try_clone()?);
-		Ok(())
+	/// Registers a plugin, giving it its own environment unless it asked to run in the shared one and is allowed to.
+	/// Its own environment falls back to a read-only view of the shared one, so it can't change what other plugins see.
+	pub fn add_plugin(&self, lua: &LuaApi, state: *mut LuaState, plugin: Plugin) -> anyhow::Result<Arc<Plugin>> {
+		let plugin = Arc::new(plugin);
+		let config = &plugin.config().plugin;
+
+		if self.get_plugin(&config.name).is_some() {
+			anyhow::bail!("A plugin named '{}' is already loaded", config.name);
+		}
+
+		let shared = config.shared.unwrap_or(false);
+		if shared && !self.shared_plugins.contains(&config.name) {
+			warn!(
+				"Plugin '{}' asked to run in the shared environment without being in shared_plugins, giving it its own",
+				config.name
+			);
+		}
+
+		let shared = shared && self.shared_plugins.contains(&config.name);
+		let (env, env_gcr) = if shared {
+			(self.env.clone(), self.env_gcr)
+		} else {
+			// Autorun and what std defined in the shared environment, down to Autorun.json, are reached through views.
+			lua.raw.push(state, &self.env);
+			push_read_only(lua, state, 2);
+
+			lua.raw.createtable(state, 0, 0);
+			lua.raw.createtable(state, 0, 2);
+			lua.raw.push(state, c"__index");
+			lua.raw.pushvalue(state, -4);
+			lua.raw.rawset(state, -3);
+			lua.raw.push(state, c"__metatable");
+			lua.raw.pushboolean(state, false);
+			lua.raw.rawset(state, -3);
+			lua.raw.setmetatable(state, -2);
+			lua.raw.remove(state, -2);
+
+			let env_gcr = Self::gcr_at(state, -1);
+			let env = lua.raw.try_to::<LuaTable>(state, -1);
+			lua.raw.pop(state, 1);
+
+			(env?, env_gcr?)
+		};
+
+		watchdog::set_plugin_budget(
+			&config.name,
+			Budget {
+				instructions: config.budget_instructions,
+				time_ms: config.budget_ms,
+			},
+		);
+
+		let modules = lua.table(state);
+		self.plugins.lock().unwrap().push(PluginEnv {
+			plugin: plugin.clone(),
+			env,
+			env_gcr,
+			shared,
+			modules,
+		});
+
+		Ok(plugin)
+	}
+
+	/// Returns the loaded plugin with the given name.
+	pub fn get_plugin(&self, name: &str) -> Option<Arc<Plugin>> {
+		let plugins = self.plugins.lock().unwrap();
+		plugins
+			.iter()
+			.find(|p| p.plugin.config().plugin.name == name)
+			.map(|p| p.plugin.clone())
 	}
 
-	pub fn trigger(&self, lua: &LuaApi, state: *mut LuaState, args: impl IntoLuaArgs) -> anyhow::Result<()> {
-		let trigger: LuaFunction = lua.get(state, &self.autorun, "trigger")?;
-		lua.pcall(state, &trigger, args)?;
+	/// Sources of the Lua entrypoints of a plugin for the realm of this environment, followed by its shared one.
+	fn entrypoints(&self, plugin: &Plugin) -> Vec<(&'static CStr, Vec<u8>)> {
+		let realm_init = match self.realm {
+			Realm::Menu => (c"menu/init.lua", plugin.read_menu_init()),
+			Realm::Client => (c"client/init.lua", plugin.read_client_init()),
+			Realm::Server => (c"server/init.lua", plugin.read_server_init()),
+		};
+
+		[realm_init, (c"shared/init.lua", plugin.read_shared_init())]
+			.into_iter()
+			.filter_map(|(name, src)| Some((name, src.ok()?)))
+			.collect()
+	}
+
+	/// Runs the Lua entrypoint of a plugin for the realm of this environment, followed by its shared one.
+	pub fn run_entrypoints(&self, lua: &LuaApi, state: *mut LuaState, plugin: &Arc<Plugin>) -> anyhow::Result<()> {
+		for (name, src) in self.entrypoints(plugin) {
+			self.execute_plugin(lua, state, plugin, name, &src)?;
+		}
+
 		Ok(())
 	}
 
+	/// Takes a plugin down, calling the `shutdown` function it defined if any, then removing its listeners, remote
+	/// callbacks, detours, timers and environment along with the modules it required.
+	/// Plugins running in the shared environment can't be unloaded, as what they defined can't be told apart.
+	pub fn unload_plugin(&self, lua: &LuaApi, state: *mut LuaState, name: &str) -> anyhow::Result<Arc<Plugin>> {
+		let (plugin, env, shared) = {
+			let plugins = self.plugins.lock().unwrap();
+			let entry = plugins
+				.iter()
+				.find(|p| p.plugin.config().plugin.name == name)
+				.ok_or_else(|| anyhow::anyhow!("Plugin '{name}' is not loaded"))?;
+
+			(entry.plugin.clone(), entry.env.clone(), entry.shared)
+		};
+
+		if shared {
+			anyhow::bail!("Plugin '{name}' runs in the shared environment and can't be unloaded");
+		}
+
+		// Raw, so a shutdown function of the shared environment isn't mistaken for the plugin's.
+		lua.raw.push(state, &env);
+		lua.raw.push(state, c"shutdown");
+		lua.raw.rawget(state, -2);
+		lua.raw.remove(state, -2);
+
+		if lua.raw.typeid(state, -1) == LuaTypeId::Function {
+			let previous = self.current_plugin.lock().unwrap().replace(plugin.clone());
+			if let Err(why) = errors::pcall(lua, state, 0, 0) {
+				errors::report(lua, state, self.realm, Some(name.to_owned()), "shutdown", why);
+			}
+			*self.current_plugin.lock().unwrap() = previous;
+		} else {
+			lua.raw.pop(state, 1);
+		}
+
+		// Remote callbacks are kept by std, which might not be around.
+		if let Ok(remove_remote_callbacks) = lua.get::<LuaFunction>(state, &self.autorun, "removeRemoteCallbacks")
+			&& let Err(why) = lua.pcall(state, &remove_remote_callbacks, (&env,))
+		{
+			error!("Failed to remove remote callbacks of plugin '{name}': {why}");
+		}
+
+		events::remove_plugin(lua, state, self.realm, name);
+		detours::remove_plugin(lua, state, self.realm, name);
+		timers::cancel_plugin(lua, state, self.realm, name);
+		watchdog::set_plugin_budget(name, Budget::default());
+
+		self.plugins.lock().unwrap().retain(|p| !Arc::ptr_eq(&p.plugin, &plugin));
+
+		Ok(plugin)
+	}
+
+	/// Unloads every plugin, most recently loaded first. Called before its state closes, after which this handle must
+	/// not be used anymore.
+	pub fn close(&self, lua: &LuaApi, state: *mut LuaState) {
+		let names: Vec<String> = {
+			let plugins = self.plugins.lock().unwrap();
+			plugins
+				.iter()
+				.rev()
+				.filter(|p| !p.shared)
+				.map(|p| p.plugin.config().plugin.name.clone())
+				.collect()
+		};
+
+		for name in names {
+			if let Err(why) = self.unload_plugin(lua, state, &name) {
+				error!("Failed to unload plugin '{name}': {why}");
+			}
+		}
+
+		// Plugins left are the shared ones, whose environment is the shared one.
+		self.plugins.lock().unwrap().clear();
+		*self.current_plugin.lock().unwrap() = None;
+		chunks::forget(self.chunk_nonce);
+	}
+
+	/// Loads a Lua plugin again from disk, then swaps the loaded one for it and runs its entrypoints.
+	pub fn reload_plugin(&self, lua: &LuaApi, state: *mut LuaState, name: &str) -> anyhow::Result<Arc<Plugin>> {
+		let is_lua = self
+			.plugins
+			.lock()
+			.unwrap()
+			.iter()
+			.find(|p| p.plugin.config().plugin.name == name)
+			.is_some_and(|p| matches!(p.plugin.config().plugin.language, ConfigPluginLanguage::Lua));
+
+		if !is_lua {
+			anyhow::bail!("Plugin '{name}' is not a loaded Lua plugin");
+		}
+
+		// Check the new version before taking the old one down, so that a mistake in it leaves the old one running.
+		let dir = self.get_plugin(name).context("Plugin is not loaded")?.dir().try_clone()?;
+		let plugin = Plugin::from_dir(dir)?;
+		if plugin.config().plugin.name != name {
+			anyhow::bail!("Plugin '{name}' was renamed to '{}'", plugin.config().plugin.name);
+		}
+
+		for (entrypoint, src) in self.entrypoints(&plugin) {
+			let chunk_name = self.format_chunk_name(Some(&plugin), entrypoint)?;
+			if let Err(why) = lua.load(state, &src, &chunk_name) {
+				let why = errors::CallError::from(why);
+				anyhow::bail!("Failed to load {}: {}", entrypoint.to_string_lossy(), why.message);
+			}
+		}
+
+		self.unload_plugin(lua, state, name)?;
+		let plugin = self.add_plugin(lua, state, plugin)?;
+		self.run_entrypoints(lua, state, &plugin)?;
+
+		Ok(plugin)
+	}
+
+	/// Runs a module required by `plugin` in the environment of the plugin it belongs to, or reuses the results of its
+	/// first run. The results are left on the stack, returning how many there are.
+	pub fn require(&self, lua: &LuaApi, state: *mut LuaState, plugin: &Arc<Plugin>, name: &str) -> anyhow::Result<c_int> {
+		let module = modules::resolve(plugin, name, |dep| self.get_plugin(dep))?;
+		let owner = module.plugin.config().plugin.name.clone();
+
+		let (env, cache) = {
+			let plugins = self.plugins.lock().unwrap();
+			let entry = plugins
+				.iter()
+				.find(|p| Arc::ptr_eq(&p.plugin, &module.plugin))
+				.ok_or_else(|| anyhow::anyhow!("Plugin '{owner}' has no environment"))?;
+
+			(entry.env.clone(), entry.modules.clone())
+		};
+
+		lua.raw.push(state, &cache);
+		lua.raw.push(state, module.path.as_str());
+		lua.raw.rawget(state, -2);
+		lua.raw.remove(state, -2);
+
+		if lua.raw.typeid(state, -1) == LuaTypeId::Table {
+			let results = lua.raw.gettop(state);
+			lua.raw.getfield(state, results, c"n".as_ptr());
+			let n_results = lua.raw.tointeger(state, -1);
+			lua.raw.pop(state, 1);
+
+			if !lua.raw.checkstack(state, n_results) {
+				anyhow::bail!("Too many results in module '{}'", module.path);
+			}
+
+			for i in 1..=n_results {
+				lua.raw.rawgeti(state, results, i);
+			}
+
+			lua.raw.remove(state, results);
+			return Ok(n_results);
+		}
+
+		lua.raw.pop(state, 1);
+
+		let key = format!("{owner}:{}", module.path);
+		{
+			let mut requiring = self.requiring.lock().unwrap();
+			if let Some(start) = requiring.iter().position(|k| k == &key) {
+				let cycle = requiring[start..].join(" -> ");
+				anyhow::bail!("Circular require: {cycle} -> {key}");
+			}
+
+			requiring.push(key);
+		}
+
+		let result = self.run_module(lua, state, &module, &env, &cache);
+		self.requiring.lock().unwrap().pop();
+
+		result
+	}
+
+	fn run_module(
+		&self,
+		lua: &LuaApi,
+		state: *mut LuaState,
+		module: &modules::Module,
+		env: &LuaTable,
+		cache: &LuaTable,
+	) -> anyhow::Result<c_int> {
+		let source = module.plugin.dir().read(&module.path)?;
+		let base = module.path.strip_prefix("src/").unwrap_or(&module.path);
+		let chunk_name = self.format_chunk_name(Some(&module.plugin), &CString::new(base)?)?;
+
+		let chunk = modules::compile(lua, state, &module.plugin, &source, &chunk_name)?;
+		lua.setfenv(state, &chunk, env)?;
+
+		let top = lua.raw.gettop(state);
+		lua.raw.push(state, &chunk);
+		watchdog::exclude_from_jit(lua, state, Some(&module.plugin.config().plugin.name), -1);
+
+		let previous = self.current_plugin.lock().unwrap().replace(module.plugin.clone());
+		let result = errors::pcall(lua, state, 0, LUA_MULTRET);
+		*self.current_plugin.lock().unwrap() = previous;
+
+		if let Err(why) = result {
+			anyhow::bail!("Error in module '{}': {}", module.path, why.message);
+		}
+
+		let n_results = lua.raw.gettop(state) - top;
+		if !lua.raw.checkstack(state, 5) {
+			anyhow::bail!("Too many results in module '{}'", module.path);
+		}
+
+		lua.raw.push(state, cache);
+		lua.raw.push(state, module.path.as_str());
+		lua.raw.createtable(state, n_results, 1);
+
+		for i in 1..=n_results {
+			lua.raw.pushvalue(state, top + i);
+			lua.raw.rawseti(state, -2, i);
+		}
+
+		lua.raw.push(state, c"n");
+		lua.raw.push(state, n_results);
+		lua.raw.rawset(state, -3);
+		lua.raw.rawset(state, -3);
+		lua.raw.pop(state, 1);
+
+		Ok(n_results)
+	}
+
+	/// Triggers an event for every listener in the given state, passing the stack indices of its results to `results`.
+	/// They are only on the stack until it returns, so anything borrowed from them must be copied out.
+	pub fn trigger<R>(
+		&self,
+		lua: &LuaApi,
+		state: *mut LuaState,
+		event: &str,
+		args: impl IntoLuaArgs,
+		results: impl FnOnce(std::ops::Range<c_int>) -> R,
+	) -> R {
+		let n_args = args.push_args(&lua.raw, state);
+		let n_results = events::trigger(lua, state, event, n_args);
+
+		let top = lua.raw.gettop(state);
+		let result = results(top - n_results + 1..top + 1);
+		lua.raw.settop(state, top - n_results);
+
+		result
+	}
+
+	/// Runs the remote callbacks of this environment, reporting an error in one of them to [`errors`].
 	pub fn run_remote_callbacks(&self, lua: &LuaApi, state: *mut LuaState, args: impl IntoLuaArgs) -> anyhow::Result<()> {
-		let run_remote_callbacks = lua.get(state, &self.autorun, "runRemoteCallbacks")?;
-		lua.pcall(state, &run_remote_callbacks, args)?;
+		let run_remote_callbacks: LuaFunction = lua.get(state, &self.autorun, "runRemoteCallbacks")?;
 
-		Ok(())
+		lua.raw.push(state, &run_remote_callbacks);
+		let n_args = args.push_args(&lua.raw, state);
+
+		profiler::measure(self.realm, None, "remote callbacks", || errors::pcall(lua, state, n_args, 0)).map_err(|why| {
+			let message = why.message.clone();
+			errors::report(lua, state, self.realm, None, "remote callback", why);
+			anyhow::anyhow!(message)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Env;
+	use autorun_types::Realm;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn checks_the_caller_before_the_arguments_of_typed_functions() {
+		let env = Env::new();
+		env.run(
+			"
+			_G.loaded = Autorun.load('return ...', 'chunk')(1, 2)
+			_G.bad = select(2, pcall(function() Autorun.load({}) end))
+			_G.outside = select(2, pcall(Autorun.load, 'return'))
+		",
+		)
+		.unwrap();
+
+		assert_eq!(
+			env.results("loaded, bad, outside"),
+			"1,bad argument #1 (String expected, got Table),"
+		);
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn keeps_plugins_from_changing_what_others_see() {
+		let env = Env::new();
+		let writer = "
+			mine = 'a'
+			local function try(f) return select(2, pcall(f)) end
+			_G.assigned = try(function() Autorun.json = nil end)
+			_G.nested = try(function() rawset(Autorun, 'json', nil) Autorun.json.encode = nil end)
+		";
+		env.plugin("a", "", &[("src/menu/init.lua", writer)]).unwrap();
+
+		let reader = "_G.seen = tostring(mine) .. ',' .. type(Autorun.json.encode)";
+		env.plugin("b", "", &[("src/menu/init.lua", reader)]).unwrap();
+
+		let results = env.results("assigned, nested, seen");
+		assert!(
+			results.contains("Can't assign 'json' in a table shared by every plugin"),
+			"{results}"
+		);
+		assert!(
+			results.contains("Can't assign 'encode' in a table shared by every plugin"),
+			"{results}"
+		);
+		assert!(results.ends_with(",nil,function"), "{results}");
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn only_lets_listed_plugins_share_the_environment() {
+		let env = Env::new();
+		let menu = |code| [("src/menu/init.lua", code)];
+		let rogue = "helper = 'rogue' takeover = true";
+		let user = "_G.seen = helper .. ',' .. tostring(takeover)";
+		env.plugin("std", "shared = true", &menu("helper = 'std'")).unwrap();
+		env.plugin("rogue", "shared = true", &menu(rogue)).unwrap();
+		env.plugin("user", "", &menu(user)).unwrap();
+
+		assert_eq!(env.results("seen"), "std,nil");
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn runs_the_entrypoints_of_its_realm() {
+		let env = Env::with_realm(Realm::Server);
+		let files = [
+			("src/menu/init.lua", "_G.ran = (_G.ran or '') .. 'menu,'"),
+			("src/client/init.lua", "_G.ran = (_G.ran or '') .. 'client,'"),
+			("src/server/init.lua", "_G.ran = (_G.ran or '') .. 'server,'"),
+			("src/shared/init.lua", "_G.ran = (_G.ran or '') .. 'shared'"),
+		];
+		env.plugin("gamemode", "", &files).unwrap();
+
+		assert_eq!(env.results("ran"), "server,shared");
 	}
 }
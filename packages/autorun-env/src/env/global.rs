@@ -10,7 +10,11 @@ mod client {
 	}
 
 	pub fn get_env() -> Option<EnvHandle> {
-		*ENV.lock().unwrap()
+		ENV.lock().unwrap().clone()
+	}
+
+	pub fn take_env() -> Option<EnvHandle> {
+		ENV.lock().unwrap().take()
 	}
 }
 
@@ -24,15 +28,42 @@ mod menu {
 	}
 
 	pub fn get_env() -> Option<EnvHandle> {
-		*ENV.lock().unwrap()
+		ENV.lock().unwrap().clone()
+	}
+
+	pub fn take_env() -> Option<EnvHandle> {
+		ENV.lock().unwrap().take()
+	}
+}
+
+mod server {
+	use crate::EnvHandle;
+
+	static ENV: std::sync::Mutex<Option<EnvHandle>> = std::sync::Mutex::new(None);
+
+	pub fn set_env(env: EnvHandle) {
+		*ENV.lock().unwrap() = Some(env);
+	}
+
+	pub fn get_env() -> Option<EnvHandle> {
+		ENV.lock().unwrap().clone()
+	}
+
+	pub fn take_env() -> Option<EnvHandle> {
+		ENV.lock().unwrap().take()
 	}
 }
 
 pub fn get_realm(state: *mut LuaState) -> autorun_types::Realm {
-	let client_state = autorun_interfaces::lua::get_state(autorun_types::Realm::Client).unwrap();
+	// Without the Lua interface of the game, like in tests, every state is the menu's.
+	let get_state = |realm| autorun_interfaces::lua::get_state(realm).ok().flatten();
+	let client_state = get_state(autorun_types::Realm::Client);
+	let server_state = get_state(autorun_types::Realm::Server);
 
 	if Some(state) == client_state {
 		autorun_types::Realm::Client
+	} else if Some(state) == server_state {
+		autorun_types::Realm::Server
 	} else {
 		autorun_types::Realm::Menu
 	}
@@ -42,6 +73,7 @@ pub fn get_realm_env(realm: autorun_types::Realm) -> Option<crate::EnvHandle> {
 	match realm {
 		autorun_types::Realm::Client => client::get_env(),
 		autorun_types::Realm::Menu => menu::get_env(),
+		autorun_types::Realm::Server => server::get_env(),
 	}
 }
 
@@ -49,5 +81,14 @@ pub fn set_realm_env(realm: autorun_types::Realm, env: crate::EnvHandle) {
 	match realm {
 		autorun_types::Realm::Client => client::set_env(env),
 		autorun_types::Realm::Menu => menu::set_env(env),
+		autorun_types::Realm::Server => server::set_env(env),
+	}
+}
+
+pub fn take_realm_env(realm: autorun_types::Realm) -> Option<crate::EnvHandle> {
+	match realm {
+		autorun_types::Realm::Client => client::take_env(),
+		autorun_types::Realm::Menu => menu::take_env(),
+		autorun_types::Realm::Server => server::take_env(),
 	}
 }
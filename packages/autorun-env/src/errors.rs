@@ -0,0 +1,204 @@
+//! Sink for every Lua error Autorun catches, from plugin entrypoints to timers, events, detours and remote callbacks.
+//! Reports are logged, kept in a ring buffer the IPC server hands out, and passed to Autorun.onError listeners of the
+//! state they happened in. The same error happening again at the same site shortly after is only counted, so an error
+//! every frame doesn't flood the console.
+use std::collections::VecDeque;
+use std::ffi::c_int;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use autorun_log::*;
+use autorun_lua::{IntoLua, LuaApi, LuaError};
+use autorun_types::{ErrorReport, LuaState, Realm};
+
+use crate::{chunks, events};
+
+/// How many reports are kept around.
+const CAPACITY: usize = 256;
+
+/// How long the same error is folded into its previous report instead of being reported again.
+const REPEAT_WINDOW: Duration = Duration::from_secs(5);
+
+struct Sink {
+	/// Reports along with when they were first made, oldest first.
+	reports: VecDeque<(Instant, ErrorReport)>,
+}
+
+static SINK: Mutex<Sink> = Mutex::new(Sink {
+	reports: VecDeque::new(),
+});
+
+/// Set while onError listeners run, so an error inside one of them is reported without calling them again.
+static DISPATCHING: AtomicBool = AtomicBool::new(false);
+
+/// Error of a protected call made through [`pcall`], with chunk names already made readable.
+#[derive(Debug, Clone)]
+pub struct CallError {
+	pub message: String,
+	pub traceback: Option<String>,
+}
+
+impl From<LuaError> for CallError {
+	fn from(why: LuaError) -> Self {
+		let why = match why {
+			LuaError::Runtime(message) => chunks::prettify(&message),
+			other => other.to_string(),
+		};
+
+		match why.split_once("\nstack traceback:\n") {
+			Some((message, traceback)) => Self {
+				message: message.to_owned(),
+				traceback: Some(traceback.to_owned()),
+			},
+			None => Self {
+				message: why,
+				traceback: None,
+			},
+		}
+	}
+}
+
+impl std::fmt::Display for CallError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.message)
+	}
+}
+
+/// Like `lua_pcall`, but captures a traceback when the function on the stack below its `n_args` arguments errors.
+pub fn pcall(lua: &LuaApi, state: *mut LuaState, n_args: c_int, n_results: c_int) -> Result<(), CallError> {
+	let func = lua.raw.gettop(state) - n_args;
+	lua.raw.pushcfunction(state, chunks::traceback);
+	lua.raw.insert(state, func);
+
+	let result = lua.raw.pcall(state, n_args, n_results, func);
+	lua.raw.remove(state, func);
+
+	result.map_err(CallError::from)
+}
+
+/// Reports an error caught while running `site` in a state, on behalf of `plugin` if it is known.
+pub fn report(lua: &LuaApi, state: *mut LuaState, realm: Realm, plugin: Option<String>, site: &str, error: CallError) {
+	let report = {
+		let mut sink = SINK.lock().unwrap();
+		let now = Instant::now();
+
+		let previous = sink
+			.reports
+			.iter_mut()
+			.rev()
+			.find(|(_, r)| r.realm == realm && r.site == site && r.message == error.message);
+
+		if let Some((first, previous)) = previous
+			&& now.duration_since(*first) < REPEAT_WINDOW
+		{
+			previous.repeats += 1;
+			return;
+		}
+
+		let time = SystemTime::now()
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.map(|d| d.as_millis() as u64)
+			.unwrap_or_default();
+
+		let report = ErrorReport {
+			message: error.message,
+			traceback: error.traceback,
+			realm,
+			plugin,
+			site: site.to_owned(),
+			time,
+			repeats: 0,
+		};
+
+		if sink.reports.len() == CAPACITY {
+			sink.reports.pop_front();
+		}
+
+		sink.reports.push_back((now, report.clone()));
+		report
+	};
+
+	match &report.plugin {
+		Some(plugin) => error!("[{realm}] Error in {site} of plugin '{plugin}': {}", report.message),
+		None => error!("[{realm}] Error in {site}: {}", report.message),
+	}
+
+	if let Some(traceback) = &report.traceback {
+		debug!("stack traceback:\n{traceback}");
+	}
+
+	dispatch(lua, state, &report);
+}
+
+/// Returns the most recent reports, oldest first.
+pub fn recent() -> Vec<ErrorReport> {
+	SINK.lock().unwrap().reports.iter().map(|(_, r)| r.clone()).collect()
+}
+
+/// Passes a report to the Autorun.onError listeners of the state it happened in.
+fn dispatch(lua: &LuaApi, state: *mut LuaState, report: &ErrorReport) {
+	if DISPATCHING.swap(true, Ordering::Acquire) {
+		return;
+	}
+
+	if lua.raw.checkstack(state, 3) {
+		let top = lua.raw.gettop(state);
+
+		lua.raw.createtable(state, 0, 6);
+		set_field(lua, state, "message", report.message.as_str());
+		set_field(lua, state, "traceback", report.traceback.as_deref());
+		set_field(lua, state, "realm", report.realm.to_string());
+		set_field(lua, state, "plugin", report.plugin.as_deref());
+		set_field(lua, state, "site", report.site.as_str());
+		set_field(lua, state, "time", report.time as f64);
+
+		events::trigger(lua, state, "error", 1);
+		lua.raw.settop(state, top);
+	}
+
+	DISPATCHING.store(false, Ordering::Release);
+}
+
+/// Sets a field of the table on top of the stack.
+fn set_field(lua: &LuaApi, state: *mut LuaState, key: &str, value: impl IntoLua) {
+	lua.raw.push(state, key);
+	lua.raw.push(state, value);
+	lua.raw.rawset(state, -3);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::Env;
+	use crate::timers;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn passes_attributed_errors_to_listeners_once_per_window() {
+		let env = Env::new();
+		let init = "
+			Autorun.onError(function(report)
+				_G.seen = (_G.seen or '') .. report.plugin .. ' in ' .. report.site .. ': ' .. report.message .. '\\n'
+			end)
+			Autorun.on('ping', function() error('listener failed', 0) end)
+			Autorun.setTimeout(function() error('timer failed', 0) end, 0)
+		";
+		env.plugin("faulty", "", &[("src/menu/init.lua", init)]).unwrap();
+
+		let lua = env.state.lua();
+		env.env.trigger(lua, env.state.state, "ping", (), |_| ());
+		env.env.trigger(lua, env.state.state, "ping", (), |_| ());
+		timers::tick(lua);
+
+		assert_eq!(
+			env.results("seen"),
+			"faulty in 'ping' listener: listener failed\nfaulty in timer: timer failed\n"
+		);
+
+		let reports = recent();
+		let listener = reports.iter().rfind(|r| r.message == "listener failed").unwrap();
+		assert_eq!((listener.realm, listener.repeats), (Realm::Menu, 1));
+		assert!(listener.traceback.as_deref().unwrap().contains("faulty:menu/init.lua:5"));
+	}
+}
@@ -0,0 +1,270 @@
+//! Event bus of every state, backing Autorun.on/once/off/trigger so core events work without the std plugin.
+//! Listeners run by descending priority, and in subscription order within the same priority. Each one runs in its own
+//! protected call, so an erroring listener is reported and the rest still run. The first listener returning anything
+//! other than nil decides the result of the event, listeners after it still run but their results are ignored.
+//! Listeners belong to the state rather than the thread they were subscribed from, so an event triggered from any
+//! coroutine of a state reaches all of them.
+use std::ffi::c_int;
+use std::sync::Mutex;
+
+use autorun_log::*;
+use autorun_lua::{LUA_MULTRET, LuaApi, LuaTypeId, RawHandle};
+use autorun_types::{LuaState, Realm};
+
+use crate::{errors, lifecycle, profiler, watchdog};
+
+struct Listener {
+	id: u32,
+	event: String,
+	realm: Realm,
+	/// Main thread of the state, see [`lifecycle::main_state`].
+	state: usize,
+	priority: f64,
+	once: bool,
+	callback: RawHandle,
+	owner: Option<String>,
+}
+
+struct Bus {
+	next_id: u32,
+	/// Sorted in the order listeners run in.
+	listeners: Vec<Listener>,
+}
+
+static BUS: Mutex<Bus> = Mutex::new(Bus {
+	next_id: 1,
+	listeners: Vec::new(),
+});
+
+/// Subscribes `callback` to an event of the state whose main thread is `state`, see [`lifecycle::main_state`], removing
+/// it after its first call if `once` is set. Returns the id of the listener which can be passed to [`unsubscribe`].
+pub fn subscribe(
+	realm: Realm,
+	state: *mut LuaState,
+	event: String,
+	priority: f64,
+	once: bool,
+	callback: RawHandle,
+	owner: Option<String>,
+) -> u32 {
+	let mut bus = BUS.lock().unwrap();
+
+	let id = bus.next_id;
+	bus.next_id = bus.next_id.wrapping_add(1).max(1);
+
+	let pos = bus.listeners.partition_point(|l| l.priority >= priority);
+	bus.listeners.insert(
+		pos,
+		Listener {
+			id,
+			event,
+			realm,
+			state: state as usize,
+			priority,
+			once,
+			callback,
+			owner,
+		},
+	);
+
+	id
+}
+
+/// Unsubscribes a listener, returning whether it existed.
+/// Fails if it belongs to a plugin other than `caller`.
+pub fn unsubscribe(lua: &LuaApi, state: *mut LuaState, id: u32, caller: Option<&str>) -> anyhow::Result<bool> {
+	let main = lifecycle::main_state(lua, state) as usize;
+	let listener = {
+		let mut bus = BUS.lock().unwrap();
+		let Some(pos) = bus.listeners.iter().position(|l| l.id == id && l.state == main) else {
+			return Ok(false);
+		};
+
+		if let Some(owner) = &bus.listeners[pos].owner
+			&& caller != Some(owner.as_str())
+		{
+			anyhow::bail!("Listener {id} belongs to plugin '{owner}'");
+		}
+
+		bus.listeners.remove(pos)
+	};
+
+	let _ = listener.callback.free(lua, state);
+	Ok(true)
+}
+
+/// Unsubscribes every listener of the given plugin in a realm.
+pub fn remove_plugin(lua: &LuaApi, state: *mut LuaState, realm: Realm, plugin: &str) {
+	let removed = {
+		let mut bus = BUS.lock().unwrap();
+		let (removed, kept) = std::mem::take(&mut bus.listeners)
+			.into_iter()
+			.partition::<Vec<_>, _>(|l| l.realm == realm && l.owner.as_deref() == Some(plugin));
+
+		bus.listeners = kept;
+		removed
+	};
+
+	for listener in removed {
+		let _ = listener.callback.free(lua, state);
+	}
+}
+
+/// Drops every listener of a realm whose state has been torn down, along with its registry.
+pub fn remove_realm(realm: Realm) {
+	BUS.lock().unwrap().listeners.retain(|l| l.realm != realm);
+}
+
+/// Calls every listener of `event` in the given state with the `n_args` values on top of the stack, on the thread
+/// `state` is. Replaces the arguments with the result of the event and returns how many values it is.
+pub fn trigger(lua: &LuaApi, state: *mut LuaState, event: &str, n_args: c_int) -> c_int {
+	let main = lifecycle::main_state(lua, state) as usize;
+	let ids: Vec<u32> = {
+		let bus = BUS.lock().unwrap();
+		bus.listeners
+			.iter()
+			.filter(|l| l.state == main && l.event == event)
+			.map(|l| l.id)
+			.collect()
+	};
+
+	let base = lua.raw.gettop(state) - n_args;
+	let mut n_results = 0;
+
+	for id in ids {
+		// Look the listener up again, an earlier one may have unsubscribed it.
+		let (realm, callback, once, owner) = {
+			let mut bus = BUS.lock().unwrap();
+			let Some(pos) = bus.listeners.iter().position(|l| l.id == id) else {
+				continue;
+			};
+
+			// Removed before running, so triggering the event again from inside doesn't call it twice.
+			let listener = &bus.listeners[pos];
+			let fired = (listener.realm, listener.callback, listener.once, listener.owner.clone());
+			if listener.once {
+				bus.listeners.remove(pos);
+			}
+
+			fired
+		};
+
+		if !lua.raw.checkstack(state, n_args + 1) {
+			error!("Not enough stack space to call '{event}' listener");
+			break;
+		}
+
+		let top = lua.raw.gettop(state);
+		lua.raw.push(state, &callback);
+		for i in 1..=n_args {
+			lua.raw.pushvalue(state, base + i);
+		}
+
+		let site = format!("'{event}' listener");
+		let result = profiler::measure(realm, owner.as_deref(), &site, || {
+			watchdog::run_plugin(lua, state, owner.as_deref(), || {
+				errors::pcall(lua, state, n_args, LUA_MULTRET)
+			})
+		});
+		if once {
+			let _ = callback.free(lua, state);
+		}
+
+		match result {
+			Err(why) => errors::report(lua, state, realm, owner, &site, why),
+
+			Ok(()) if n_results == 0 => {
+				let returned = lua.raw.gettop(state) - top;
+				if (top + 1..=top + returned).any(|i| lua.raw.typeid(state, i) != LuaTypeId::Nil) {
+					n_results = returned;
+					continue;
+				}
+			}
+
+			Ok(()) => (),
+		}
+
+		lua.raw.settop(state, top);
+	}
+
+	for _ in 0..n_args {
+		lua.raw.remove(state, base + 1);
+	}
+
+	n_results
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autorun_test::State;
+
+	fn listen(state: &State, thread: *mut LuaState, priority: f64, code: &str) -> u32 {
+		let lua = state.lua();
+		state.eval(code);
+		let callback = RawHandle::from_stack(&lua.raw, state.state).unwrap();
+		let state = lifecycle::main_state(lua, thread);
+		subscribe(Realm::Menu, state, "test".into(), priority, false, callback, None)
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn reaches_listeners_from_any_thread_of_a_state() {
+		let _serial = autorun_test::serial();
+		let state = State::new();
+		let lua = state.lua();
+		assert!(lifecycle::open(lua, Realm::Menu, state.state));
+
+		state.eval("return coroutine.create(function() end)");
+		let thread = lua.raw.tothread(state.state, -1);
+		let id = listen(&state, thread, 0.0, "return function(n) fired = (fired or 0) + n end");
+
+		lua.raw.push(state.state, 1.0);
+		assert_eq!(trigger(lua, state.state, "test", 1), 0);
+		lua.raw.push(thread, 2.0);
+		assert_eq!(trigger(lua, thread, "test", 1), 0);
+		assert_eq!(state.results("fired"), "3");
+
+		assert!(unsubscribe(lua, state.state, id, None).unwrap());
+		assert!(!unsubscribe(lua, thread, id, None).unwrap());
+
+		lifecycle::close(lua, state.state);
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn returns_the_first_result_by_priority() {
+		let _serial = autorun_test::serial();
+		let state = State::new();
+		let lua = state.lua();
+		state.run("order = ''", 0);
+
+		listen(
+			&state,
+			state.state,
+			0.0,
+			"return function() order = order .. 'c' return 'low' end",
+		);
+		listen(&state, state.state, 1.0, "return function() order = order .. 'b' end");
+		listen(
+			&state,
+			state.state,
+			1.0,
+			"return function() order = order .. 'd' return 'same' end",
+		);
+		listen(
+			&state,
+			state.state,
+			2.0,
+			"return function() order = order .. 'a' error('oops') end",
+		);
+
+		let top = lua.raw.gettop(state.state);
+		assert_eq!(trigger(lua, state.state, "test", 0), 1);
+		assert_eq!(lua.raw.tostring(state.state, -1).unwrap(), "same");
+		lua.raw.settop(state.state, top);
+		assert_eq!(state.results("order"), "abdc");
+
+		remove_realm(Realm::Menu);
+	}
+}
@@ -1,9 +1,15 @@
 mod print;
 pub use print::*;
 
+mod inspect;
+pub use inspect::*;
+
 mod read;
 pub use read::*;
 
+mod read_async;
+pub use read_async::*;
+
 mod write;
 pub use write::*;
 
@@ -22,11 +28,41 @@ pub use detour::*;
 mod load;
 pub use load::*;
 
+mod require;
+pub use require::*;
+
 mod remote;
 pub use remote::*;
 
+mod event;
+pub use event::*;
+
 mod exists;
 pub use exists::*;
 
 mod auth;
 pub use auth::*;
+
+mod timer;
+pub use timer::*;
+
+mod json;
+pub use json::*;
+
+mod toml;
+pub use toml::*;
+
+mod hash;
+pub use hash::*;
+
+mod compress;
+pub use compress::*;
+
+mod profile;
+pub use profile::*;
+
+mod sampling;
+pub use sampling::*;
+
+mod plugin;
+pub use plugin::*;
@@ -1,20 +1,15 @@
 use std::io::Write;
 
-use autorun_lua::LuaApi;
+use autorun_lua::{LuaApi, LuaStr};
 use autorun_types::LuaState;
 
-pub fn append(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<()> {
-	let target_path = lua.raw.checkstring(state, 1);
-	let content = lua.raw.checkstring(state, 2);
-
+pub fn append(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, path: LuaStr, content: LuaStr) -> anyhow::Result<()> {
 	let plugin = env
 		.get_active_plugin(lua, state)
-		.ok_or(anyhow::anyhow!("dont delete autorun.plugin lil bro"))?;
+		.ok_or(anyhow::anyhow!("Could not determine the calling plugin."))?;
 
 	let data_dir = plugin.data_dir();
-	let target_path = target_path.to_string();
-
-	let mut f = data_dir.open_with(target_path, cap_std::fs::OpenOptions::new().append(true))?;
+	let mut f = data_dir.open_with(&*path, cap_std::fs::OpenOptions::new().append(true))?;
 	f.write_all(content.as_bytes())?;
 
 	Ok(())
@@ -0,0 +1,84 @@
+use autorun_lua::LuaApi;
+use autorun_types::LuaState;
+use std::io::{Read, Write};
+
+/// Upper bound on decompressed output, so a tiny malicious input can't exhaust memory.
+const MAX_DECOMPRESSED_SIZE: u64 = 512 * 1024 * 1024;
+
+fn read_limited(mut reader: impl Read) -> anyhow::Result<Vec<u8>> {
+	let mut out = Vec::new();
+	(&mut reader).take(MAX_DECOMPRESSED_SIZE + 1).read_to_end(&mut out)?;
+
+	if out.len() as u64 > MAX_DECOMPRESSED_SIZE {
+		anyhow::bail!("Decompressed data exceeds the limit of {MAX_DECOMPRESSED_SIZE} bytes");
+	}
+
+	Ok(out)
+}
+
+pub fn compress(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<Vec<u8>> {
+	let data = lua.raw.try_to::<&[u8]>(state, 1)?;
+	let algorithm = lua.raw.try_to::<Option<String>>(state, 2)?;
+	let level = lua.raw.try_to::<Option<i32>>(state, 3)?;
+
+	let compressed = match algorithm.as_deref().unwrap_or("zstd") {
+		"zstd" => zstd::bulk::compress(data, level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL))?,
+		"gzip" => {
+			let level = flate2::Compression::new(level.unwrap_or(6).clamp(0, 9) as u32);
+			let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+			encoder.write_all(data)?;
+			encoder.finish()?
+		}
+		"deflate" => {
+			let level = flate2::Compression::new(level.unwrap_or(6).clamp(0, 9) as u32);
+			let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), level);
+			encoder.write_all(data)?;
+			encoder.finish()?
+		}
+		other => anyhow::bail!("Unknown compression algorithm '{other}', expected 'zstd', 'gzip' or 'deflate'"),
+	};
+
+	Ok(compressed)
+}
+
+pub fn decompress(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<Vec<u8>> {
+	let data = lua.raw.try_to::<&[u8]>(state, 1)?;
+	let algorithm = lua.raw.try_to::<Option<String>>(state, 2)?;
+
+	let decompressed = match algorithm.as_deref().unwrap_or("zstd") {
+		"zstd" => read_limited(zstd::stream::read::Decoder::new(data)?)?,
+		"gzip" => read_limited(flate2::read::GzDecoder::new(data))?,
+		"deflate" => read_limited(flate2::read::DeflateDecoder::new(data))?,
+		other => anyhow::bail!("Unknown compression algorithm '{other}', expected 'zstd', 'gzip' or 'deflate'"),
+	};
+
+	Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Env;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn round_trips_binary_data() {
+		let env = Env::new();
+		env.run(
+			"
+			local data = string.char(0, 255, 1) .. string.rep('autorun', 1000)
+			local results = {}
+			for _, algorithm in ipairs({ 'zstd', 'gzip', 'deflate' }) do
+				local compressed = Autorun.compress(data, algorithm, 9)
+				local same = Autorun.decompress(compressed, algorithm) == data
+				results[#results + 1] = algorithm .. ':' .. tostring(same and #compressed < #data / 10)
+			end
+			_G.results = table.concat(results, ' ')
+			_G.garbage = select(2, pcall(function() Autorun.decompress('not compressed', 'gzip') end))
+		",
+		)
+		.unwrap();
+
+		assert_eq!(env.results("results"), "zstd:true gzip:true deflate:true");
+		assert_ne!(env.results("tostring(garbage)"), "nil");
+	}
+}
@@ -1,23 +1,47 @@
+mod fast_function;
 mod handlers;
+mod proto;
 mod raw;
 mod userdata;
 
-use crate::functions::detour::handlers::{detour_handler, retour_handler};
+use crate::detours::{self, HookKind, Target};
+use crate::functions::detour::handlers::{chain_handler, retour_handler};
 use crate::functions::detour::raw::{make_detour_trampoline, make_retour_lua_trampoline};
-use crate::functions::detour::userdata::Detour;
+use crate::functions::detour::userdata::DetourHandle;
 use anyhow::Context;
 use autorun_lua::{LuaApi, LuaCFunction, LuaTypeId, RawHandle, RawLuaReturn};
-use autorun_luajit::{GCfunc, LJState, get_gcobj, get_gcobj_mut};
+use autorun_luajit::{GCfunc, LJState, get_gcobj};
 use autorun_types::LuaState;
 use retour::GenericDetour;
-use std::ffi::c_int;
-pub use userdata::{detour_disable, detour_enable, detour_get_original, detour_remove};
+pub use userdata::{detour_disable, detour_enable, detour_get_original, detour_list, detour_remove};
 
-pub fn detour(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<Detour> {
-	if !lua.raw.iscfunction(state, 1) {
-		anyhow::bail!("First argument must be a C function to detour.");
-	}
+/// Creates the single retour detour of a target, whose trampoline runs the chain of hooks with the given id.
+fn create_target(lua: &LuaApi, target_function: LuaCFunction, target_id: u32) -> anyhow::Result<Target> {
+	// The chain handler reads the original function from the registry, so there is no pointer to pass.
+	let detour_trampoline = make_detour_trampoline(lua, target_id as i32, std::ptr::null(), chain_handler)?;
+
+	let detour = unsafe {
+		Box::new(GenericDetour::new(
+			target_function,
+			std::mem::transmute::<*const u8, LuaCFunction>(detour_trampoline.as_ptr()),
+		)?)
+	};
+
+	// create retour trampoline
+	let retour_trampoline = make_retour_lua_trampoline(detour.as_ref() as *const GenericDetour<LuaCFunction>, retour_handler)?;
+	let original = unsafe { std::mem::transmute::<*const u8, LuaCFunction>(retour_trampoline.as_ptr()) };
+
+	Ok(Target::native(
+		target_function as usize,
+		detour,
+		original,
+		detour_trampoline,
+		retour_trampoline,
+	))
+}
 
+/// Returns the id of the target for the C function at index 1, creating it if it isn't detoured yet.
+fn get_or_create_native_target(lua: &LuaApi, state: *mut LuaState) -> anyhow::Result<u32> {
 	let l = state as *mut LJState;
 	let l_ref = unsafe { l.as_ref().context("Failed to dereference lua_State.")? };
 
@@ -32,47 +56,59 @@ pub fn detour(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> any
 		anyhow::bail!("Target function pointer is null.");
 	}
 
+	detours::get_or_create_target(target_function as usize, |id| create_target(lua, target_function, id))
+}
+
+fn hook(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, kind: HookKind) -> anyhow::Result<DetourHandle> {
+	if lua.raw.typeid(state, 1) != LuaTypeId::Function {
+		anyhow::bail!("First argument must be a function to detour.");
+	}
+
 	if lua.raw.typeid(state, 2) != LuaTypeId::Function {
 		anyhow::bail!("Second argument must be a function to use as detour.");
 	}
 
-	let detour_callback = RawHandle::from_stack(&lua.raw, state).context("Failed to create raw handle for detour callback.")?;
-	let mut original_function_ptr = Box::new(0usize);
+	let target_id = match lua.raw.iscfunction(state, 1) {
+		true => get_or_create_native_target(lua, state)?,
+		false => proto::get_or_create_target(lua, state, env.realm())?,
+	};
 
-	// create the trampoline
-	let detour_trampoline = make_detour_trampoline(
-		lua,
-		detour_callback.id(),
-		original_function_ptr.as_ref() as *const usize,
-		detour_handler,
-	)?;
+	let owner = env
+		.get_active_plugin(lua, state)
+		.map(|plugin| plugin.config().plugin.name.clone());
 
-	let detour = unsafe {
-		Box::new(GenericDetour::new(
-			target_function,
-			std::mem::transmute::<*const u8, LuaCFunction>(detour_trampoline.as_ptr()),
-		)?)
+	lua.raw.settop(state, 2);
+	let callback = RawHandle::from_stack(&lua.raw, state).context("Failed to create raw handle for detour callback.")?;
+	let target = RawHandle::from_stack(&lua.raw, state).context("Failed to create raw handle for detour target.")?;
+
+	let hook = detours::NewHook {
+		kind,
+		realm: env.realm(),
+		owner,
+		callback,
+		target,
 	};
 
-	unsafe {
-		detour
-			.enable()
-			.map_err(|e| anyhow::anyhow!("Failed to enable detour: {}", e))?;
+	match detours::add_hook(lua, state, target_id, hook) {
+		Ok(id) => Ok(DetourHandle { id }),
+		Err(why) => {
+			let _ = callback.free(lua, state);
+			let _ = target.free(lua, state);
+			Err(why.context("Failed to enable detour"))
+		}
 	}
+}
 
-	// create retour trampoline
-	let retour_trampoline = make_retour_lua_trampoline(detour.as_ref() as *const GenericDetour<LuaCFunction>, retour_handler)?;
+pub fn detour(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<DetourHandle> {
+	hook(lua, state, env, HookKind::Detour)
+}
 
-	// link the original function pointer
-	*original_function_ptr = retour_trampoline.as_ptr() as usize;
+pub fn hook_before(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<DetourHandle> {
+	hook(lua, state, env, HookKind::Before)
+}
 
-	Ok(Detour {
-		detour,
-		_detour_callback: detour_callback,
-		_detour_trampoline: detour_trampoline,
-		_retour_trampoline: retour_trampoline,
-		original_function_ptr,
-	})
+pub fn hook_after(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<DetourHandle> {
+	hook(lua, state, env, HookKind::After)
 }
 
 pub fn copy_fast_function(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<RawLuaReturn> {
@@ -84,37 +120,161 @@ pub fn copy_fast_function(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHa
 		anyhow::bail!("Second argument must be a function to use.");
 	}
 
-	let lj_state = state as *mut LJState;
-	let lj_state = unsafe { lj_state.as_mut().context("Failed to dereference LJState.")? };
-	let gcfunc = get_gcobj::<GCfunc>(lj_state, 1).context("Failed to get GCfunc for target function.")?;
+	fast_function::push_copy(lua, state)?;
+
+	Ok(RawLuaReturn(1))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Env;
+
+	/// Big enough to be detoured through its prototype.
+	const TARGET: &str = "function(a, b) local sum = a + (b or 0) local doubled = sum * 2 return doubled end";
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn runs_hooks_of_any_thread_of_a_state() {
+		let env = Env::new();
+		env.run(&format!(
+			"
+			_G.target = {TARGET}
+			local co = coroutine.create(function()
+				_G.handle = Autorun.hookBefore(target, function(a, b) return a * 10, b end)
+				return target(1, 2)
+			end)
+			_G.inside = select(2, coroutine.resume(co))
+			_G.outside = target(1, 2)
+			_G.other = select(2, coroutine.resume(coroutine.create(function() return target(1, 2) end)))
+			_G.listed = #Autorun.listDetours()
+
+			Autorun.removeDetour(handle)
+			_G.removed = target(1, 2)
+		"
+		))
+		.unwrap();
+
+		assert_eq!(env.results("inside, outside, other, listed, removed"), "24,24,24,1,6");
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn runs_after_hooks_on_what_the_call_returned() {
+		let env = Env::new();
+		env.run(&format!(
+			"
+			local target = {TARGET}
+			local calls = {{}}
+			local function log(call) calls[#calls + 1] = call end
+
+			Autorun.hookBefore(target, function(a) log('before:' .. a) end)
+			Autorun.hookAfter(target, function(r) log('after:' .. r) return r + 100 end)
+			local detour = Autorun.detour(target, function(original, a) log('detour:' .. a) return a * 3 end)
+			Autorun.hookAfter(target, function(r) log('behind:' .. r) end)
+			Autorun.hookBefore(target, function(a) log('skipped') end)
+			_G.replaced = target(5)
 
-	if !gcfunc.is_fast_function() {
-		anyhow::bail!("Function is not a fast-function.");
+			Autorun.removeDetour(detour)
+			_G.original = target(5)
+			_G.calls = table.concat(calls, ' ')
+		"
+		))
+		.unwrap();
+
+		assert_eq!(
+			env.results("calls, replaced, original"),
+			"before:5 detour:5 after:15 behind:115 before:5 skipped after:10 behind:110,115,110"
+		);
 	}
 
-	let original_ffid = gcfunc.header().ffid;
-	let original_upvalues = gcfunc.header().nupvalues;
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn raises_errors_of_detours_to_the_caller() {
+		let env = Env::new();
+		env.run(&format!(
+			"
+			local target = {TARGET}
+			Autorun.hookAfter(target, function() _G.after = true end)
+			Autorun.detour(target, function() error('detour failed', 0) end)
+			_G.ok, _G.err = pcall(target, 1)
+		"
+		))
+		.unwrap();
 
-	let function_handle = RawHandle::from_stack(&lua.raw, state).context("Failed to create raw handle for function.")?;
+		assert_eq!(env.results("tostring(ok), err, tostring(after)"), "false,detour failed,nil");
+	}
 
-	let trampoline = make_detour_trampoline(lua, function_handle.id(), std::ptr::null(), detour_handler)?;
-	// Not a detour, but we can reuse the trampoline maker to create a fast-function trampoline
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn refuses_to_enable_detours_of_running_functions() {
+		let env = Env::new();
+		env.run(
+			"
+			local function target(a, b) local sum = a + (b or 0) if _G.inner then _G.inner() end return sum * 2 end
+			local detour = Autorun.detour(target, function(original, a, b) return original(a, b) + 1 end)
+			Autorun.disableDetour(detour)
 
-	// Push it as a closure to call
-	unsafe {
-		lua.raw.pushcclosure(
-			state,
-			std::mem::transmute::<*const u8, LuaCFunction>(trampoline.as_ptr()),
-			original_upvalues as c_int,
+			local function enable()
+				local ok, err = pcall(function() Autorun.enableDetour(detour) end)
+				return not ok and err:match('while it is running')
+			end
+			_G.inner = function() _G.direct = enable() end
+			target(1, 2)
+			_G.inner = function() _G.nested = select(2, coroutine.resume(coroutine.create(enable))) end
+			target(1, 2)
+
+			_G.inner = nil
+			Autorun.enableDetour(detour)
+			_G.enabled = target(1, 2)
+		",
+		)
+		.unwrap();
+
+		assert_eq!(
+			env.results("direct, nested, enabled"),
+			"while it is running,while it is running,7"
 		);
 	}
 
-	let new_gcfunc = get_gcobj_mut::<GCfunc>(lj_state, -1).context("Failed to get GCfunc for copied function.")?;
-	new_gcfunc.header_mut().ffid = original_ffid;
-	new_gcfunc.header_mut().nupvalues = original_upvalues;
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn removes_detours_along_with_their_plugin() {
+		let env = Env::new();
+		env.run(&format!("_G.target = {TARGET}")).unwrap();
 
-	// TODO: Handle garbage collection of the trampoline function?
-	std::mem::forget(trampoline);
+		let owner = "
+			_G.handle = Autorun.detour(target, function(original, a, b) return original(a, b) + 100 end)
+			Autorun.hookBefore(target, function(a, b) return a, b end)
+		";
+		let other = "_G.stolen = select(2, pcall(function() Autorun.removeDetour(handle) end))";
+		env.plugin("owner", "", &[("src/menu/init.lua", owner)]).unwrap();
+		env.plugin("other", "", &[("src/menu/init.lua", other)]).unwrap();
+		env.run(
+			"
+			local entries = {}
+			for _, entry in ipairs(Autorun.listDetours()) do
+				entries[#entries + 1] = entry.kind .. ':' .. entry.owner .. ':' .. tostring(entry.target == target)
+			end
+			_G.listed = table.concat(entries, ' ')
+			_G.detoured = target(1, 2)
+		",
+		)
+		.unwrap();
 
-	Ok(RawLuaReturn(1))
+		assert_eq!(env.results("listed, detoured"), "detour:owner:true before:owner:true,106");
+		assert!(env.results("stolen").contains("Detour belongs to plugin 'owner'"));
+
+		let (lua, state) = (env.state.lua(), env.state.state);
+		env.env.unload_plugin(lua, state, "owner").unwrap();
+		env.run(
+			"
+			_G.remaining = #Autorun.listDetours()
+			_G.restored = target(1, 2)
+			_G.removed = pcall(function() Autorun.removeDetour(handle) end)
+		",
+		)
+		.unwrap();
+
+		assert_eq!(env.results("remaining, restored, tostring(removed)"), "0,6,false");
+	}
 }
@@ -0,0 +1,166 @@
+//! Copies of fast functions made by copyFastFunction.
+//! Each copy owns its trampoline and the registry handle of its replacement through a userdata kept in a weak-keyed
+//! table, whose `__gc` frees both once Lua collects the copy.
+use crate::functions::detour::handlers::fast_function_handler;
+use crate::functions::detour::raw::make_detour_trampoline;
+use anyhow::Context;
+use autorun_jit::Function;
+use autorun_lua::{LuaApi, LuaCFunction, LuaTypeId, REGISTRY_INDEX, RawHandle};
+use autorun_luajit::{GCfunc, LJState, get_gcobj, get_gcobj_mut};
+use autorun_types::LuaState;
+use std::ffi::{CStr, c_int};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// Registry key of the weak-keyed table mapping each copy to its [`FastFunction`].
+const OWNERS: &CStr = c"autorun.fastfunctions";
+
+/// Registry key of the metatable given to every [`FastFunction`].
+const METATABLE: &CStr = c"autorun.fastfunction";
+
+/// What a copied fast function needs to stay callable.
+struct FastFunction {
+	_trampoline: Function,
+	callback: RawHandle,
+}
+
+/// The API trampolines were made with, which like them has to outlive every copy.
+static API: AtomicPtr<LuaApi> = AtomicPtr::new(std::ptr::null_mut());
+
+/// `__gc` of [`FastFunction`].
+extern "C-unwind" fn collect(state: *mut LuaState) -> c_int {
+	let Some(lua) = (unsafe { API.load(Ordering::Acquire).as_ref() }) else {
+		return 0;
+	};
+
+	let fast_function = lua.raw.touserdata(state, 1) as *mut FastFunction;
+
+	if let Some(fast_function) = unsafe { fast_function.as_mut() } {
+		let _ = fast_function.callback.free(lua, state);
+		unsafe { std::ptr::drop_in_place(fast_function) };
+	}
+
+	0
+}
+
+/// Pushes the registry table at `key`, creating it with `init` the first time.
+fn push_registry_table(lua: &LuaApi, state: *mut LuaState, key: &CStr, init: impl FnOnce(&LuaApi, *mut LuaState)) {
+	lua.raw.pushstring(state, key.as_ptr());
+	lua.raw.rawget(state, REGISTRY_INDEX);
+	if lua.raw.typeid(state, -1) == LuaTypeId::Table {
+		return;
+	}
+	lua.raw.pop(state, 1);
+
+	lua.raw.createtable(state, 0, 1);
+	init(lua, state);
+
+	lua.raw.pushstring(state, key.as_ptr());
+	lua.raw.pushvalue(state, -2);
+	lua.raw.rawset(state, REGISTRY_INDEX);
+}
+
+/// Pushes a copy of the fast function at index 1 calling the function at index 2 instead.
+pub fn push_copy(lua: &LuaApi, state: *mut LuaState) -> anyhow::Result<()> {
+	let lj_state = state as *mut LJState;
+	let lj_state = unsafe { lj_state.as_mut().context("Failed to dereference LJState.")? };
+	let gcfunc = get_gcobj::<GCfunc>(lj_state, 1).context("Failed to get GCfunc for target function.")?;
+
+	if !gcfunc.is_fast_function() {
+		anyhow::bail!("Function is not a fast-function.");
+	}
+
+	let original_ffid = gcfunc.header().ffid;
+	let original_upvalues = gcfunc.header().nupvalues;
+
+	API.store(lua as *const LuaApi as *mut LuaApi, Ordering::Release);
+
+	lua.raw.settop(state, 2);
+	let callback = RawHandle::from_stack(&lua.raw, state).context("Failed to create raw handle for function.")?;
+
+	// Not a detour, but we can reuse the trampoline maker to create a fast-function trampoline
+	let trampoline = match make_detour_trampoline(lua, callback.id(), std::ptr::null(), fast_function_handler) {
+		Ok(trampoline) => trampoline,
+		Err(why) => {
+			let _ = callback.free(lua, state);
+			return Err(why);
+		}
+	};
+
+	// Push it as a closure to call
+	unsafe {
+		lua.raw.pushcclosure(
+			state,
+			std::mem::transmute::<*const u8, LuaCFunction>(trampoline.as_ptr()),
+			original_upvalues as c_int,
+		);
+	}
+
+	let new_gcfunc = get_gcobj_mut::<GCfunc>(lj_state, -1).context("Failed to get GCfunc for copied function.")?;
+	new_gcfunc.header_mut().ffid = original_ffid;
+	new_gcfunc.header_mut().nupvalues = original_upvalues;
+
+	// The copy is the key, so the owner only becomes garbage along with it.
+	push_registry_table(lua, state, OWNERS, |lua, state| {
+		lua.raw.createtable(state, 0, 1);
+		lua.raw.pushstring(state, c"__mode".as_ptr());
+		lua.raw.pushstring(state, c"k".as_ptr());
+		lua.raw.rawset(state, -3);
+		lua.raw.setmetatable(state, -2);
+	});
+	lua.raw.pushvalue(state, -2);
+
+	lua.raw.newuserdata(
+		state,
+		FastFunction {
+			_trampoline: trampoline,
+			callback,
+		},
+	);
+	push_registry_table(lua, state, METATABLE, |lua, state| {
+		lua.raw.pushstring(state, c"__gc".as_ptr());
+		lua.raw.pushcfunction(state, collect);
+		lua.raw.rawset(state, -3);
+	});
+	lua.raw.setmetatable(state, -2);
+
+	lua.raw.rawset(state, -3);
+	lua.raw.pop(state, 1);
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autorun_test::State;
+
+	extern "C-unwind" fn copy(state: *mut LuaState) -> c_int {
+		push_copy(autorun_test::lua(), state).unwrap();
+		1
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn frees_copies_once_collected() {
+		let state = State::new();
+		state.lua().raw.pushcfunction(state.state, copy);
+		state.pop_global("copy");
+
+		// Replacements are only referenced by their copy, so they die with them unless something leaks.
+		state.eval(
+			"
+			local replacements = setmetatable({}, { __mode = 'v' })
+			for i = 1, 1000 do
+				local replacement = function(x) return x * 2 end
+				replacements[i] = replacement
+				assert(copy(math.floor, replacement)(i) == i * 2)
+			end
+
+			-- The copies, then their owners, then the replacements
+			for _ = 1, 3 do collectgarbage() end
+			return next(replacements) == nil
+		",
+		);
+		assert!(state.lua().raw.toboolean(state.state, -1));
+	}
+}
@@ -1,52 +1,218 @@
-use crate::functions::detour::raw;
+use crate::detours::{self, ChainHook, HookKind};
+use crate::functions::detour::{proto, raw};
+use crate::{errors, global, profiler, watchdog};
 use autorun_log::*;
-use autorun_lua::{LUA_MULTRET, LuaApi, LuaCFunction, RawHandle};
+use autorun_lua::{LUA_MULTRET, LUA_OK, LuaApi, LuaCFunction, RawHandle, upvalueindex};
+use autorun_luajit::MRef;
 use autorun_types::LuaState;
 
 pub extern "C-unwind" fn retour_handler(state: *mut LuaState, detour: *const retour::GenericDetour<LuaCFunction>) -> i32 {
 	unsafe { (*detour).call(state) }
 }
 
-pub extern "C-unwind" fn detour_handler(
+/// Handler behind functions created by copyFastFunction, which just calls the replacement with the arguments.
+pub extern "C-unwind" fn fast_function_handler(
 	state: *mut LuaState,
 	metadata: i32,
 	lua_api: *const LuaApi,
-	original_function: *const LuaCFunction,
+	_original_function: *const LuaCFunction,
 ) -> i32 {
-	let detour_metadata = raw::DetourMetadata::from_packed(metadata);
-	let callback_id = detour_metadata.callback_ref();
+	let callback_id = raw::DetourMetadata::from_packed(metadata).id();
 	let lua = unsafe { &*lua_api };
 
-	let callback_handle = RawHandle::from_id(callback_id);
-	lua.raw.push(state, &callback_handle);
+	let num_arguments = lua.raw.gettop(state);
+	lua.raw.push(state, &RawHandle::from_id(callback_id));
 	lua.raw.insert(state, 1);
 
-	let num_arguments = lua.raw.gettop(state) - 1;
+	let realm = global::get_realm(state);
+	if let Err(why) = profiler::measure(realm, None, "fast function", || {
+		errors::pcall(lua, state, num_arguments, LUA_MULTRET)
+	}) {
+		errors::report(lua, state, realm, None, "fast function", why);
+		return 0;
+	}
+
+	lua.raw.gettop(state)
+}
+
+/// Handler behind every detoured function, runs the whole hook chain of the target.
+pub extern "C-unwind" fn chain_handler(
+	state: *mut LuaState,
+	metadata: i32,
+	lua_api: *const LuaApi,
+	_original_function: *const LuaCFunction,
+) -> i32 {
+	let target_id = raw::DetourMetadata::from_packed(metadata).id() as u32;
+	let lua = unsafe { &*lua_api };
+
+	let push_original = |lua: &LuaApi, state: *mut LuaState| match detours::native_original(target_id) {
+		Some(original) => lua.raw.pushcfunction(state, original),
+		None => lua.raw.pushnil(state),
+	};
+
+	match run_chain(lua, state, target_id, 0, &push_original) {
+		Some(n_results) => n_results,
+		None => lua.raw.error(state),
+	}
+}
+
+/// Called by the stub of a detoured Lua function, returning the function it should tail call to run the chain.
+/// Upvalues are the clones table, the factory and original pc of the target, and the target id.
+pub extern "C-unwind" fn prepare_proto_chain(state: *mut LuaState) -> i32 {
+	let lua = autorun_lua::get_api().expect("Failed to get Lua API");
+
+	// The stub calls this without a tail call, so the closure that was called is still on the stack right above.
+	let pc = MRef {
+		ptr64: lua.raw.touserdata(state, upvalueindex(3)) as u64,
+	};
+	let pushed = lua.raw.getinfo(state, 1, c"f").is_some()
+		&& proto::push_original(lua, state, lua.raw.gettop(state), upvalueindex(1), upvalueindex(2), pc)
+			.inspect_err(|why| error!("Failed to create original of detoured function: {why}"))
+			.is_ok();
+
+	if !pushed {
+		lua.raw.pushstring(state, c"Failed to run detour chain".as_ptr());
+		lua.raw.error(state);
+	}
+
+	lua.raw.pushvalue(state, upvalueindex(4));
+	lua.raw.insert(state, -2);
+	lua.raw.pushcclosure(state, proto_chain, 2);
+
+	1
+}
+
+/// Runs the chain of a detoured Lua function, with the target id and the original closure as upvalues.
+extern "C-unwind" fn proto_chain(state: *mut LuaState) -> i32 {
+	let lua = autorun_lua::get_api().expect("Failed to get Lua API");
+	let target_id = lua.raw.tonumber(state, upvalueindex(1)) as u32;
+
+	let push_original = |lua: &LuaApi, state: *mut LuaState| lua.raw.pushvalue(state, upvalueindex(2));
+
+	match run_chain(lua, state, target_id, 0, &push_original) {
+		Some(n_results) => n_results,
+		None => lua.raw.error(state),
+	}
+}
 
-	let original_function_included = if original_function as usize != 0 {
-		// add the original function as the first argument
-		unsafe {
-			lua.raw.pushcfunction(state, *original_function);
-			lua.raw.insert(state, 2);
+/// Passed to detour callbacks as `original`, runs the rest of the chain after the hook that received it.
+/// Upvalues are the target id, the position to continue from and the original function.
+extern "C-unwind" fn continue_chain(state: *mut LuaState) -> i32 {
+	let lua = autorun_lua::get_api().expect("Failed to get Lua API");
+	let target_id = lua.raw.tonumber(state, upvalueindex(1)) as u32;
+	let position = lua.raw.tonumber(state, upvalueindex(2)) as usize;
+
+	let push_original = |lua: &LuaApi, state: *mut LuaState| lua.raw.pushvalue(state, upvalueindex(3));
+
+	match run_chain(lua, state, target_id, position, &push_original) {
+		Some(n_results) => n_results,
+		None => lua.raw.error(state),
+	}
+}
+
+/// Calls a before or after hook with every value on the stack, replacing them with what it returns, if anything.
+fn call_rewriting(lua: &LuaApi, state: *mut LuaState, hook: &ChainHook) {
+	let num_values = lua.raw.gettop(state);
+	if !lua.raw.checkstack(state, num_values + 1) {
+		error!("Not enough stack space to call {} hook", hook.kind.name());
+		return;
+	}
+
+	lua.raw.push(state, &hook.callback);
+	for i in 1..=num_values {
+		lua.raw.pushvalue(state, i);
+	}
+
+	let site = format!("{} hook", hook.kind.name());
+	let result = profiler::measure(hook.realm, hook.owner.as_deref(), &site, || {
+		watchdog::run_plugin(lua, state, hook.owner.as_deref(), || {
+			errors::pcall(lua, state, num_values, LUA_MULTRET)
+		})
+	});
+
+	if let Err(why) = result {
+		errors::report(lua, state, hook.realm, hook.owner.clone(), &site, why);
+		return;
+	}
+
+	if lua.raw.gettop(state) > num_values {
+		for _ in 0..num_values {
+			lua.raw.remove(state, 1);
 		}
+	}
+}
 
-		true
-	} else {
-		false
+/// Runs the chain of a target starting at `start`, with the arguments being the whole stack.
+/// `push_original` pushes the function to call once the chain ends.
+/// After hooks run once the call returns, on whatever it returned, so only the run starting the chain calls them.
+/// Returns the amount of results left on the stack, or None if a detour callback or the original function errored, with
+/// the error on top of the stack to be rethrown once nothing needs to be dropped anymore.
+fn run_chain(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	target_id: u32,
+	start: usize,
+	push_original: &dyn Fn(&LuaApi, *mut LuaState),
+) -> Option<i32> {
+	let Some(hooks) = detours::chain(lua, target_id, state) else {
+		lua.raw.pushstring(state, c"Detour target does not exist".as_ptr());
+		return None;
 	};
 
-	let num_arguments = if original_function_included {
-		num_arguments + 1
-	} else {
-		num_arguments
+	let after: Vec<&ChainHook> = match start {
+		0 => hooks.iter().filter(|h| h.kind == HookKind::After).collect(),
+		_ => Vec::new(),
 	};
 
-	let base = lua.raw.gettop(state) - num_arguments;
+	let mut replaced = false;
+	for (position, hook) in hooks.iter().enumerate().skip(start) {
+		match hook.kind {
+			HookKind::Before => call_rewriting(lua, state, hook),
+			HookKind::After => (),
+			HookKind::Detour => {
+				let num_arguments = lua.raw.gettop(state);
 
-	if let Err(why) = lua.raw.pcall(state, num_arguments, LUA_MULTRET, 0) {
-		error!("Error calling detour callback: {why}");
-		return 0;
+				lua.raw.push(state, &hook.callback);
+				lua.raw.insert(state, 1);
+
+				// The rest of the chain is given to the callback as the original function
+				lua.raw.pushnumber(state, target_id as f64);
+				lua.raw.pushnumber(state, (position + 1) as f64);
+				push_original(lua, state);
+				lua.raw.pushcclosure(state, continue_chain, 3);
+				lua.raw.insert(state, 2);
+
+				// Errors are the caller's to handle, as if the function it called errored itself.
+				let status = profiler::measure(hook.realm, hook.owner.as_deref(), "detour callback", || {
+					watchdog::run_plugin(lua, state, hook.owner.as_deref(), || {
+						lua.raw._pcall(state, num_arguments + 1, LUA_MULTRET, 0)
+					})
+				});
+
+				if status != LUA_OK {
+					return None;
+				}
+
+				replaced = true;
+				break;
+			}
+		}
+	}
+
+	if !replaced {
+		// Every hook ran without replacing the function, so call the original ourselves.
+		let num_arguments = lua.raw.gettop(state);
+		push_original(lua, state);
+		lua.raw.insert(state, 1);
+
+		if lua.raw._pcall(state, num_arguments, LUA_MULTRET, 0) != LUA_OK {
+			return None;
+		}
+	}
+
+	for hook in after {
+		call_rewriting(lua, state, hook);
 	}
 
-	lua.raw.gettop(state) - base + 1
+	Some(lua.raw.gettop(state))
 }
@@ -0,0 +1,172 @@
+//! Detours of Lua functions, made by patching their prototype so every closure sharing it runs a stub.
+//! The stub asks [`prepare_proto_chain`] for a function to tail call with its arguments, which runs the chain with a
+//! clone of the called closure running the original code as the original function.
+use crate::detours::{self, Target};
+use crate::functions::detour::handlers::prepare_proto_chain;
+use anyhow::Context;
+use autorun_lua::{LUAJIT_MODE_ENGINE, LUAJIT_MODE_FLUSH, LuaApi, LuaTypeId};
+use autorun_luajit::{
+	GCHeader, GCfunc, GCfuncL, LJ_TTAB, LJState, MRef, ProtoDetour, TValue, get_gcobj, get_gcobj_mut, push_tvalue,
+	retarget_closure,
+};
+use autorun_types::{LuaState, Realm};
+use std::ffi::{CStr, c_int, c_void};
+
+/// Compiled once per target, the first entry of its template table is set to the function preparing the chain.
+const STUB: &str = "return function(...) return ({true})[1]()(...) end";
+
+// Entries of the anchors table of a target
+const ANCHOR_STUB: c_int = 1;
+const ANCHOR_CLONES: c_int = 2;
+const ANCHOR_FACTORY: c_int = 3;
+const ANCHOR_CONSTANTS: c_int = 4;
+
+fn load(lua: &LuaApi, state: *mut LuaState, name: &CStr, code: &str) -> anyhow::Result<()> {
+	lua.raw
+		.loadbufferx(state, code.as_bytes(), name, c"t")
+		.context("Failed to compile detour code.")
+}
+
+/// Source of a chunk returning a new closure with `n` upvalues whenever called.
+fn factory_source(n: u8) -> String {
+	let names = (1..=n).map(|i| format!("u{i}")).collect::<Vec<_>>().join(", ");
+	match n {
+		0 => "return function() end".to_owned(),
+		_ => format!("local {names} return function() return {names} end"),
+	}
+}
+
+/// Returns the id of the target for the Lua function at index 1, patching its prototype if it isn't detoured yet.
+pub fn get_or_create_target(lua: &LuaApi, state: *mut LuaState, realm: Realm) -> anyhow::Result<u32> {
+	let l = unsafe { (state as *mut LJState).as_mut().context("Failed to dereference lua_State.")? };
+
+	let gcfunc = get_gcobj::<GCfunc>(l, 1).context("Failed to get GCfunc for target function.")?;
+	let closure = gcfunc.as_l().context("Target function is not a Lua function.")?;
+	let proto = closure.proto_ptr();
+
+	if let Some(id) = detours::find_target(proto as usize) {
+		return Ok(id);
+	}
+
+	if detours::is_running(lua, state, proto) {
+		anyhow::bail!("Cannot detour a function while it is running.");
+	}
+
+	// Traces compiled so far may have inlined the function, and would keep running its original code.
+	lua.raw.jit_setmode(state, 0, LUAJIT_MODE_ENGINE | LUAJIT_MODE_FLUSH);
+
+	lua.raw.createtable(state, 4, 0);
+	let anchors = lua.raw.gettop(state);
+
+	load(lua, state, c"=detour stub", STUB)?;
+	lua.raw.pcall(state, 0, 1, 0).context("Failed to create detour stub.")?;
+
+	let stub = get_gcobj::<GCfunc>(l, -1)?
+		.as_l()
+		.context("Detour stub is not a Lua function.")?
+		.proto_ptr();
+	lua.raw.rawseti(state, anchors, ANCHOR_STUB);
+
+	let template = unsafe { (*stub).kgc(-1) };
+	if unsafe { (*stub).sizekgc != 1 || (*template.as_ptr::<GCHeader>()).gct != !LJ_TTAB as u8 } {
+		anyhow::bail!("Detour stub has no template table.");
+	}
+
+	let patch = unsafe { ProtoDetour::new(proto, stub)? };
+
+	// Clones of the detoured closures running the original code, so each gets the same original every time.
+	lua.raw.createtable(state, 0, 0);
+	lua.raw.createtable(state, 0, 1);
+	lua.raw.pushstring(state, c"__mode".as_ptr());
+	lua.raw.pushstring(state, c"k".as_ptr());
+	lua.raw.rawset(state, -3);
+	lua.raw.setmetatable(state, -2);
+	lua.raw.rawseti(state, anchors, ANCHOR_CLONES);
+
+	load(lua, state, c"=detour factory", &factory_source(closure.header.nupvalues))?;
+	lua.raw.rawseti(state, anchors, ANCHOR_FACTORY);
+
+	let constants = patch.constants();
+	lua.raw.createtable(state, constants.len() as c_int, 0);
+	for (i, constant) in constants.iter().enumerate() {
+		push_tvalue(l, constant);
+		lua.raw.rawseti(state, -2, i as c_int + 1);
+	}
+	lua.raw.rawseti(state, anchors, ANCHOR_CONSTANTS);
+
+	let original_pc = patch.original_pc();
+
+	lua.raw.pushvalue(state, anchors);
+	let handle = autorun_lua::RawHandle::from_stack(&lua.raw, state).context("Failed to anchor detour stub.")?;
+	let id = detours::insert_target(Target::proto(patch, realm, handle));
+
+	// Only now that the id is known can the stub be pointed at the chain.
+	push_tvalue(l, &TValue::from_gcref(template, LJ_TTAB));
+	lua.raw.rawgeti(state, anchors, ANCHOR_CLONES);
+	lua.raw.rawgeti(state, anchors, ANCHOR_FACTORY);
+	lua.raw.pushlightuserdata(state, original_pc.ptr64 as *mut c_void);
+	lua.raw.pushnumber(state, id as f64);
+	lua.raw.pushcclosure(state, prepare_proto_chain, 4);
+	lua.raw.rawseti(state, -2, 1);
+	lua.raw.pop(state, 2);
+
+	Ok(id)
+}
+
+/// Pushes the clone of the Lua function at the absolute index `closure` running the original code at `pc`, creating it
+/// the first time. `clones` and `factory` are the entries of the anchors table of its target.
+pub fn push_original(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	closure: c_int,
+	clones: c_int,
+	factory: c_int,
+	pc: MRef,
+) -> anyhow::Result<()> {
+	lua.raw.pushvalue(state, closure);
+	lua.raw.rawget(state, clones);
+	if lua.raw.typeid(state, -1) == LuaTypeId::Function {
+		return Ok(());
+	}
+	lua.raw.pop(state, 1);
+
+	lua.raw.pushvalue(state, factory);
+	lua.raw.pcall(state, 0, 1, 0)?;
+
+	let l = unsafe { (state as *mut LJState).as_mut().context("Failed to dereference lua_State.")? };
+	let from = get_gcobj_mut::<GCfunc>(l, closure)?
+		.as_l_mut()
+		.context("Detoured function is not a Lua function.")? as *mut GCfuncL;
+	let clone = get_gcobj_mut::<GCfunc>(l, -1)?
+		.as_l_mut()
+		.context("Factory did not return a Lua function.")?;
+
+	unsafe { retarget_closure(clone, &mut *from, pc)? };
+
+	lua.raw.pushvalue(state, closure);
+	lua.raw.pushvalue(state, -2);
+	lua.raw.rawset(state, clones);
+
+	Ok(())
+}
+
+/// Pushes the original of the Lua function detoured by a hook, for getOriginalFunction.
+pub fn push_hook_original(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	target: autorun_lua::RawHandle,
+	anchors: autorun_lua::RawHandle,
+	pc: MRef,
+) -> anyhow::Result<()> {
+	lua.raw.push(state, &anchors);
+	let anchors = lua.raw.gettop(state);
+	lua.raw.rawgeti(state, anchors, ANCHOR_CLONES);
+	lua.raw.rawgeti(state, anchors, ANCHOR_FACTORY);
+	lua.raw.push(state, &target);
+
+	push_original(lua, state, anchors + 3, anchors + 1, anchors + 2, pc)?;
+	lua.raw.insert(state, anchors);
+	lua.raw.settop(state, anchors);
+
+	Ok(())
+}
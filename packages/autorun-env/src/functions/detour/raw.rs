@@ -3,7 +3,7 @@ use autorun_jit::{Arg, CallingConvention, Jump};
 use autorun_lua::{LuaApi, LuaCFunction, LuaState};
 use std::ffi::c_int;
 
-const CALLBACK_REF_BITS: u32 = 24;
+const ID_BITS: u32 = 24;
 const RESERVED_BITS: u32 = 8;
 
 #[cfg(target_os = "windows")]
@@ -15,12 +15,12 @@ const CALLING_CONVENTION: CallingConvention = CallingConvention::SysV64;
 #[derive(Debug, Clone, Copy)]
 pub struct DetourMetadata(i32);
 
-// Register-sized metadata passed to the detour handler, encodes a callback reference and reserved bits
-// In the future, we can use these for flags or other data, but for now they are unused.
+// Register-sized metadata passed to the detour handler, encodes an id and reserved bits.
+// The id is the callback reference for fast functions, and the target id for detour chains.
+// In the future, we can use the reserved bits for flags or other data, but for now they are unused.
 impl DetourMetadata {
-	pub fn new(callback_ref: i32, reserved: i32) -> Self {
-		let packed =
-			((callback_ref & ((1 << CALLBACK_REF_BITS) - 1)) << RESERVED_BITS) | (reserved & ((1 << RESERVED_BITS) - 1));
+	pub fn new(id: i32, reserved: i32) -> Self {
+		let packed = ((id & ((1 << ID_BITS) - 1)) << RESERVED_BITS) | (reserved & ((1 << RESERVED_BITS) - 1));
 		Self(packed)
 	}
 
@@ -28,8 +28,8 @@ impl DetourMetadata {
 		Self(packed)
 	}
 
-	pub fn callback_ref(&self) -> i32 {
-		(self.0 >> RESERVED_BITS) & ((1 << CALLBACK_REF_BITS) - 1)
+	pub fn id(&self) -> i32 {
+		(self.0 >> RESERVED_BITS) & ((1 << ID_BITS) - 1)
 	}
 
 	#[allow(unused)]
@@ -51,12 +51,12 @@ const TRAMPOLINE_SIZE: usize = 64;
 
 pub fn make_detour_trampoline(
 	lua: &LuaApi,
-	callback_ref: i32,
+	id: i32,
 	original_function_ptr: *const usize,
 	handler: HandlerType,
 ) -> anyhow::Result<Function> {
 	let mut trampoline = Function::allocate(TRAMPOLINE_SIZE);
-	let metadata = DetourMetadata::new(callback_ref, 0).0;
+	let metadata = DetourMetadata::new(id, 0).0;
 	let lua_ptr = lua as *const LuaApi as usize;
 
 	CALLING_CONVENTION.setup_arguments(
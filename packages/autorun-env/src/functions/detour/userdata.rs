@@ -1,55 +1,91 @@
-use anyhow::anyhow;
-use autorun_jit::Function;
-use autorun_lua::{LuaApi, LuaCFunction, LuaUserdata, RawHandle};
+use crate::detours::{self, Original};
+use crate::functions::detour::proto;
+use autorun_lua::{LuaApi, LuaUserdata, RawLuaReturn};
 use autorun_types::LuaState;
 
-pub struct Detour {
-	pub detour: Box<retour::GenericDetour<LuaCFunction>>,
-	pub _detour_callback: RawHandle,
-	pub _detour_trampoline: Function,
-	pub _retour_trampoline: Function,
-	pub original_function_ptr: Box<usize>,
+/// What Lua holds on to for a detour or hook. The hook itself lives in [`detours`], so this is just its id.
+pub struct DetourHandle {
+	pub id: u32,
 }
 
-impl LuaUserdata for Detour {}
+impl LuaUserdata for DetourHandle {}
 
-pub fn detour_enable(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<()> {
-	let detour = lua.raw.try_to::<*mut Detour>(state, 1)?;
-	let detour = unsafe { detour.as_mut() }.ok_or(anyhow!("Null detour"))?;
+fn handle_id(lua: &LuaApi, state: *mut LuaState) -> anyhow::Result<u32> {
+	let handle = lua.raw.try_to::<*mut DetourHandle>(state, 1)?;
+	let handle = unsafe { handle.as_ref() }.ok_or(anyhow::anyhow!("Null detour"))?;
 
-	unsafe {
-		detour.detour.enable()?;
-	}
+	Ok(handle.id)
+}
+
+fn caller(lua: &LuaApi, state: *mut LuaState, env: &crate::EnvHandle) -> Option<String> {
+	env.get_active_plugin(lua, state)
+		.map(|plugin| plugin.config().plugin.name.clone())
+}
+
+pub fn detour_enable(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<()> {
+	let id = handle_id(lua, state)?;
+	let caller = caller(lua, state, &env);
+
+	detours::set_enabled(lua, state, id, caller.as_deref(), true)
+}
 
-	Ok(())
+pub fn detour_disable(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<()> {
+	let id = handle_id(lua, state)?;
+	let caller = caller(lua, state, &env);
+
+	detours::set_enabled(lua, state, id, caller.as_deref(), false)
 }
 
-pub fn detour_disable(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<()> {
-	let detour = lua.raw.try_to::<*mut Detour>(state, 1)?;
-	let detour = unsafe { detour.as_mut() }.ok_or(anyhow!("Null detour"))?;
+pub fn detour_get_original(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<RawLuaReturn> {
+	let id = handle_id(lua, state)?;
+	let caller = caller(lua, state, &env);
 
-	unsafe {
-		detour.detour.disable()?;
+	match detours::original(lua, state, id, caller.as_deref())? {
+		Original::Native(original) => lua.raw.pushcfunction(state, original),
+		Original::Proto { target, anchors, pc } => proto::push_hook_original(lua, state, target, anchors, pc)?,
 	}
 
-	Ok(())
+	Ok(RawLuaReturn(1))
 }
 
-pub fn detour_get_original(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<LuaCFunction> {
-	let detour = lua.raw.try_to::<*mut Detour>(state, 1)?;
-	let detour = unsafe { detour.as_mut() }.ok_or(anyhow!("Null detour"))?;
+pub fn detour_remove(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<()> {
+	let id = handle_id(lua, state)?;
+	let caller = caller(lua, state, &env);
 
-	Ok(unsafe { std::mem::transmute::<usize, LuaCFunction>(*detour.original_function_ptr) })
+	detours::remove(lua, state, id, caller.as_deref())
 }
 
-pub fn detour_remove(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<()> {
-	let detour = lua.raw.try_to::<*mut Detour>(state, 1)?;
-	let detour = unsafe { detour.as_mut() }.ok_or(anyhow!("Null detour"))?;
+pub fn detour_list(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<RawLuaReturn> {
+	let detours = detours::list(lua, state);
+
+	lua.raw.createtable(state, detours.len() as i32, 0);
+	for (i, info) in detours.into_iter().enumerate() {
+		lua.raw.createtable(state, 0, 5);
+
+		lua.raw.push(state, c"detour");
+		lua.raw.push(state, DetourHandle { id: info.id });
+		lua.raw.settable(state, -3);
+
+		lua.raw.push(state, c"kind");
+		lua.raw.push(state, info.kind.name());
+		lua.raw.settable(state, -3);
+
+		lua.raw.push(state, c"target");
+		lua.raw.push(state, &info.target);
+		lua.raw.settable(state, -3);
+
+		if let Some(owner) = info.owner {
+			lua.raw.push(state, c"owner");
+			lua.raw.push(state, owner);
+			lua.raw.settable(state, -3);
+		}
+
+		lua.raw.push(state, c"enabled");
+		lua.raw.push(state, info.enabled);
+		lua.raw.settable(state, -3);
 
-	unsafe {
-		detour.detour.disable()?;
-		std::ptr::drop_in_place(detour);
+		lua.raw.rawseti(state, -2, i as i32 + 1);
 	}
 
-	Ok(())
+	Ok(RawLuaReturn(1))
 }
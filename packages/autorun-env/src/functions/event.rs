@@ -0,0 +1,78 @@
+use autorun_lua::{LuaApi, LuaFunction, LuaStr, RawHandle, RawLuaReturn};
+use autorun_types::LuaState;
+
+use crate::events;
+
+fn subscribe(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	event: String,
+	callback: LuaFunction,
+	priority: Option<f64>,
+	once: bool,
+) -> anyhow::Result<u32> {
+	let priority = priority.unwrap_or(0.0);
+	if priority.is_nan() {
+		anyhow::bail!("Priority must be a number.");
+	}
+
+	let owner = env
+		.get_active_plugin(lua, state)
+		.map(|plugin| plugin.config().plugin.name.clone());
+
+	lua.raw.push(state, &callback);
+	let callback = RawHandle::from_stack(&lua.raw, state).ok_or(anyhow::anyhow!("Failed to store event listener"))?;
+
+	let main = crate::lifecycle::main_state(lua, state);
+	Ok(events::subscribe(env.realm(), main, event, priority, once, callback, owner))
+}
+
+pub fn on(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	event: String,
+	callback: LuaFunction,
+	priority: Option<f64>,
+) -> anyhow::Result<u32> {
+	subscribe(lua, state, env, event, callback, priority, false)
+}
+
+pub fn once(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	event: String,
+	callback: LuaFunction,
+	priority: Option<f64>,
+) -> anyhow::Result<u32> {
+	subscribe(lua, state, env, event, callback, priority, true)
+}
+
+/// Subscribes to errors reported in this state, see [`crate::errors`].
+pub fn on_error(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	callback: LuaFunction,
+	priority: Option<f64>,
+) -> anyhow::Result<u32> {
+	subscribe(lua, state, env, "error".to_owned(), callback, priority, false)
+}
+
+pub fn off(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, id: f64) -> anyhow::Result<bool> {
+	let caller = env
+		.get_active_plugin(lua, state)
+		.map(|plugin| plugin.config().plugin.name.clone());
+
+	events::unsubscribe(lua, state, id as u32, caller.as_deref())
+}
+
+/// Passes the rest of the arguments on to the listeners, as they are on the stack.
+pub fn trigger(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle, event: LuaStr) -> anyhow::Result<RawLuaReturn> {
+	lua.raw.remove(state, 1);
+
+	let n_args = lua.raw.gettop(state);
+	Ok(RawLuaReturn(events::trigger(lua, state, &event, n_args)))
+}
@@ -1,14 +1,10 @@
-use autorun_lua::LuaApi;
+use autorun_lua::{LuaApi, LuaStr};
 use autorun_types::LuaState;
 
-pub fn exists(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<bool> {
-	let target_path = lua.raw.checkstring(state, 1);
+pub fn exists(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, path: LuaStr) -> anyhow::Result<bool> {
 	let plugin = env
 		.get_active_plugin(lua, state)
-		.ok_or(anyhow::anyhow!("dont delete autorun.plugin lil bro."))?;
+		.ok_or(anyhow::anyhow!("Could not determine the calling plugin."))?;
 
-	let dir = plugin.dir();
-	let target_path = target_path.to_string();
-
-	Ok(dir.exists(target_path))
+	Ok(plugin.dir().exists(&*path))
 }
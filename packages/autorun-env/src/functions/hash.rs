@@ -0,0 +1,44 @@
+use autorun_lua::LuaApi;
+use autorun_types::LuaState;
+use sha2::Digest;
+
+pub fn hash(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<String> {
+	let data = lua.raw.try_to::<&[u8]>(state, 1)?;
+	let algorithm = lua.raw.try_to::<Option<String>>(state, 2)?;
+
+	let digest = match algorithm.as_deref().unwrap_or("sha256") {
+		"sha256" => sha2::Sha256::digest(data).to_vec(),
+		"xxhash" => xxhash_rust::xxh3::xxh3_64(data).to_be_bytes().to_vec(),
+		"crc32" => crc32fast::hash(data).to_be_bytes().to_vec(),
+		other => anyhow::bail!("Unknown hash algorithm '{other}', expected 'sha256', 'xxhash' or 'crc32'"),
+	};
+
+	Ok(digest.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Env;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn hashes_strings_as_bytes() {
+		let env = Env::new();
+		env.run(
+			"
+			_G.sha256 = Autorun.hash('abc')
+			_G.xxhash = Autorun.hash('abc', 'xxhash')
+			_G.crc32 = Autorun.hash('abc', 'crc32')
+			_G.binary = Autorun.hash('\\0\\255') ~= Autorun.hash('\\0\\254')
+			_G.unknown = select(2, pcall(function() Autorun.hash('abc', 'md5') end))
+		",
+		)
+		.unwrap();
+
+		assert_eq!(
+			env.results("sha256, xxhash, crc32, tostring(binary)"),
+			"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad,78af5f94892f3950,352441c2,true"
+		);
+		assert!(env.results("unknown").contains("Unknown hash algorithm 'md5'"));
+	}
+}
@@ -0,0 +1,9 @@
+use autorun_lua::LuaApi;
+use autorun_types::LuaState;
+
+use crate::inspect::{InspectOptions, inspect as inspect_value};
+
+pub fn inspect(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<String> {
+	let opts = InspectOptions::from_lua(lua, state, 2);
+	Ok(inspect_value(lua, state, 1, &opts))
+}
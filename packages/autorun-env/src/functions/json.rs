@@ -0,0 +1,26 @@
+use autorun_lua::{LuaApi, RawLuaReturn};
+use autorun_types::LuaState;
+
+use crate::codec::{self, EncodeOptions};
+
+pub fn json_encode(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<String> {
+	let opts = EncodeOptions::from_lua(lua, state, 2)?;
+	lua.raw.settop(state, 1);
+
+	let value = codec::to_json(lua, state, 1, &opts)?;
+	let encoded = if opts.pretty {
+		serde_json::to_string_pretty(&value)?
+	} else {
+		serde_json::to_string(&value)?
+	};
+
+	Ok(encoded)
+}
+
+pub fn json_decode(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<RawLuaReturn> {
+	let src = lua.raw.try_to::<&[u8]>(state, 1)?;
+	let value: serde_json::Value = serde_json::from_slice(src)?;
+
+	codec::push_json(lua, state, &value)?;
+	Ok(RawLuaReturn(1))
+}
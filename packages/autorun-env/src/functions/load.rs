@@ -1,20 +1,27 @@
-use autorun_lua::{LuaApi, RawLuaReturn};
+use autorun_lua::{LuaApi, LuaStr, RawLuaReturn};
 use autorun_types::LuaState;
 
-pub fn load(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<RawLuaReturn> {
-	let source = lua.raw.checkstring(state, 1);
-	let chunk_name = lua.raw.try_to::<Option<&[u8]>>(state, 2)?.unwrap_or(b"loadstring");
-	let chunk_name = std::ffi::CString::new(chunk_name)?;
-	let chunk_name = env.format_chunk_name(&chunk_name)?;
+pub fn load(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	source: LuaStr,
+	chunk_name: Option<LuaStr>,
+) -> anyhow::Result<RawLuaReturn> {
+	let chunk_name = std::ffi::CString::new(chunk_name.as_deref().unwrap_or("loadstring"))?;
+	let plugin = env.get_active_plugin(lua, state);
+	let chunk_name = env.format_chunk_name(plugin.as_deref(), &chunk_name)?;
 
 	match lua.load(state, source.as_bytes(), &chunk_name) {
 		Err(why) => {
 			lua.raw.pushnil(state);
-			lua.raw.push(state, why.to_string());
+			lua.raw.push(state, crate::errors::CallError::from(why).message);
 			Ok(RawLuaReturn(2))
 		}
 		Ok(chunk) => {
 			lua.raw.push(state, &chunk);
+			let owner = plugin.as_ref().map(|plugin| plugin.config().plugin.name.as_str());
+			crate::watchdog::exclude_from_jit(lua, state, owner, -1);
 			Ok(RawLuaReturn(1))
 		}
 	}
@@ -1,21 +1,20 @@
-use autorun_lua::LuaApi;
+use autorun_lua::{LuaApi, LuaStr};
 use autorun_types::LuaState;
 
-pub fn mkdir(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<bool> {
-	let target_path = lua.raw.checkstring(state, 1);
+pub fn mkdir(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, path: LuaStr) -> anyhow::Result<bool> {
 	let plugin = env
 		.get_active_plugin(lua, state)
-		.ok_or(anyhow::anyhow!("dont delete autorun.plugin lil bro."))?;
+		.ok_or(anyhow::anyhow!("Could not determine the calling plugin."))?;
 
 	let data_dir = plugin.data_dir();
-	let target_path = target_path.to_string();
+	let target_path = &*path;
 
-	if data_dir.is_file(&target_path) {
+	if data_dir.is_file(target_path) {
 		anyhow::bail!("Cannot create directory '{target_path}': A file with the same name already exists");
 	}
 
-	if !data_dir.exists(&target_path) {
-		data_dir.create_dir_all(&target_path)?;
+	if !data_dir.exists(target_path) {
+		data_dir.create_dir_all(target_path)?;
 
 		return Ok(true);
 	}
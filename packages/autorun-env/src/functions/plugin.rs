@@ -0,0 +1,63 @@
+use autorun_lua::{LuaApi, LuaStr};
+use autorun_types::LuaState;
+
+/// Plugins may only unload or reload themselves. Code Autorun runs itself, like from its console, isn't any plugin's.
+fn check_caller(lua: &LuaApi, state: *mut LuaState, env: &crate::EnvHandle, name: &str) -> anyhow::Result<()> {
+	match env.get_active_plugin(lua, state) {
+		Some(caller) if caller.config().plugin.name != name => {
+			anyhow::bail!("Plugin '{}' can't manage plugin '{name}'", caller.config().plugin.name)
+		}
+		_ => Ok(()),
+	}
+}
+
+pub fn unload(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, name: LuaStr) -> anyhow::Result<()> {
+	check_caller(lua, state, &env, &name)?;
+	env.unload_plugin(lua, state, &name)?;
+
+	Ok(())
+}
+
+pub fn reload(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, name: LuaStr) -> anyhow::Result<()> {
+	check_caller(lua, state, &env, &name)?;
+	env.reload_plugin(lua, state, &name)?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Env;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn lets_plugins_unload_and_reload_only_themselves() {
+		let env = Env::new();
+		let victim = "
+			_G.loads = (_G.loads or 0) + 1
+			Autorun.on('ping', function() _G.pings = (_G.pings or 0) + 1 end)
+			Autorun.on('reload', function() Autorun.reload('victim') end)
+			function shutdown() _G.shutdowns = (_G.shutdowns or 0) + 1 end
+		";
+		let manager = "
+			local function try(f) return select(2, pcall(f)) end
+			_G.denied = try(function() Autorun.unload('victim') end) .. '; ' .. try(function() Autorun.reload('victim') end)
+			Autorun.on('unload', function() Autorun.unload('manager') end)
+		";
+		env.plugin("victim", "", &[("src/menu/init.lua", victim)]).unwrap();
+		env.plugin("manager", "", &[("src/menu/init.lua", manager)]).unwrap();
+
+		let (lua, state) = (env.state.lua(), env.state.state);
+		for event in ["ping", "reload", "ping", "unload"] {
+			env.env.trigger(lua, state, event, (), |_| ());
+		}
+
+		assert_eq!(
+			env.results("denied"),
+			"Plugin 'manager' can't manage plugin 'victim'; Plugin 'manager' can't manage plugin 'victim'"
+		);
+		assert_eq!(env.results("loads, shutdowns, pings"), "2,1,2");
+		assert!(env.env.get_plugin("victim").is_some());
+		assert!(env.env.get_plugin("manager").is_none());
+	}
+}
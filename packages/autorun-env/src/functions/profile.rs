@@ -0,0 +1,43 @@
+use autorun_lua::{LuaApi, RawLuaReturn};
+use autorun_types::LuaState;
+
+use crate::profiler;
+
+pub fn get_profile(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<RawLuaReturn> {
+	let entries = profiler::snapshot();
+
+	lua.raw.createtable(state, entries.len() as i32, 0);
+	for (i, entry) in entries.into_iter().enumerate() {
+		lua.raw.createtable(state, 0, 6);
+
+		lua.raw.push(state, c"realm");
+		lua.raw.push(state, entry.realm.to_string());
+		lua.raw.settable(state, -3);
+
+		if let Some(plugin) = entry.plugin {
+			lua.raw.push(state, c"plugin");
+			lua.raw.push(state, plugin);
+			lua.raw.settable(state, -3);
+		}
+
+		lua.raw.push(state, c"site");
+		lua.raw.push(state, entry.site);
+		lua.raw.settable(state, -3);
+
+		lua.raw.push(state, c"calls");
+		lua.raw.push(state, entry.calls as f64);
+		lua.raw.settable(state, -3);
+
+		lua.raw.push(state, c"total");
+		lua.raw.push(state, entry.total_us as f64 / 1000.0);
+		lua.raw.settable(state, -3);
+
+		lua.raw.push(state, c"max");
+		lua.raw.push(state, entry.max_us as f64 / 1000.0);
+		lua.raw.settable(state, -3);
+
+		lua.raw.rawseti(state, -2, i as i32 + 1);
+	}
+
+	Ok(RawLuaReturn(1))
+}
@@ -1,12 +1,11 @@
-use autorun_lua::LuaApi;
+use autorun_lua::{LuaApi, LuaStr};
 use autorun_types::LuaState;
 
-pub fn read(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<Option<String>> {
-	let target_path = lua.raw.checkstring(state, 1);
+pub fn read(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, path: LuaStr) -> anyhow::Result<Option<String>> {
 	let plugin = env
 		.get_active_plugin(lua, state)
-		.ok_or(anyhow::anyhow!("dont delete autorun.plugin lil bro."))?;
+		.ok_or(anyhow::anyhow!("Could not determine the calling plugin."))?;
 
-	let content = plugin.dir().read_to_string(target_path.to_string())?;
+	let content = plugin.dir().read_to_string(&*path)?;
 	Ok(Some(content))
 }
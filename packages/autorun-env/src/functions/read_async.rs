@@ -0,0 +1,41 @@
+use autorun_lua::{LuaApi, LuaFunction, LuaStr, RawHandle};
+use autorun_types::LuaState;
+
+pub fn read_async(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	path: LuaStr,
+	callback: LuaFunction,
+) -> anyhow::Result<()> {
+	let target_path = path.to_string();
+	let plugin = env
+		.get_active_plugin(lua, state)
+		.ok_or(anyhow::anyhow!("Could not determine the calling plugin."))?;
+
+	let dir = (*plugin.dir()).try_clone()?;
+
+	lua.raw.push(state, &callback);
+	let callback = RawHandle::from_stack(&lua.raw, state).ok_or(anyhow::anyhow!("Failed to store callback"))?;
+
+	let realm = env.realm();
+	// Coroutines may be gone by the time it finishes, so the callback runs on the main thread.
+	let main = crate::lifecycle::main_state(lua, state);
+	let generation = crate::lifecycle::generation(main);
+	let state_ptr = main as usize;
+	let submitted = crate::workers::submit(move || {
+		let args = match dir.read(&target_path) {
+			Ok(content) => (Some(content), None),
+			Err(why) => (None, Some(format!("Failed to read '{target_path}': {why}"))),
+		};
+
+		crate::lua_queue::push_callback(realm, state_ptr as *mut LuaState, generation, callback, args);
+	});
+
+	if let Err(why) = submitted {
+		let _ = callback.free(lua, state);
+		return Err(why);
+	}
+
+	Ok(())
+}
@@ -1,4 +1,4 @@
-use autorun_lua::{IntoLua, LuaApi, LuaValue, RawLuaApi};
+use autorun_lua::{IntoLua, LuaApi, LuaStr, LuaValue, RawLuaApi};
 use autorun_types::{LuaState, Realm};
 
 #[derive(Debug)]
@@ -36,14 +36,15 @@ fn serialize_value(
 	}
 }
 
-pub fn trigger_remote(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<()> {
-	let event_name = lua.raw.checkstring(state, 1);
+/// The value is taken from the stack as is, since it's serialized rather than converted.
+pub fn trigger_remote(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, event_name: LuaStr) -> anyhow::Result<()> {
 	let event_name = std::ffi::CString::new(event_name.as_bytes())?;
-	let value = serialize_value(lua, state, env, 2)?;
+	let value = serialize_value(lua, state, env.clone(), 2)?;
 
 	let opposite_realm = match env.realm() {
 		Realm::Client => Realm::Menu,
 		Realm::Menu => Realm::Client,
+		Realm::Server => anyhow::bail!("Remote events are not supported in the server realm"),
 	};
 
 	let opposite_state =
@@ -0,0 +1,11 @@
+use autorun_lua::{LuaApi, LuaStr, RawLuaReturn};
+use autorun_types::LuaState;
+
+pub fn require(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, name: LuaStr) -> anyhow::Result<RawLuaReturn> {
+	let plugin = env
+		.get_active_plugin(lua, state)
+		.ok_or(anyhow::anyhow!("Could not determine the calling plugin."))?;
+
+	let n_results = env.require(lua, state, &plugin, &name)?;
+	Ok(RawLuaReturn(n_results))
+}
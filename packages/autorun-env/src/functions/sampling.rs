@@ -0,0 +1,39 @@
+use autorun_lua::{LuaApi, LuaTypeId, LuaValue};
+use autorun_types::{LuaState, SamplingOptions};
+
+use crate::sampler;
+
+pub fn start_sampling(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<()> {
+	let mut options = SamplingOptions::default();
+
+	if lua.raw.typeid(state, 1) == LuaTypeId::Table {
+		let field = |name: &std::ffi::CStr| -> LuaValue<'_> {
+			lua.raw.getfield(state, 1, name.as_ptr());
+			let value = lua.raw.to::<LuaValue>(state, -1);
+			lua.raw.pop(state, 1);
+			value
+		};
+
+		if let LuaValue::Number(duration) = field(c"duration") {
+			if !duration.is_finite() || duration < 0.0 {
+				anyhow::bail!("Duration must be a non-negative number of milliseconds.");
+			}
+
+			options.duration_ms = Some(duration as u64);
+		}
+
+		if let LuaValue::Number(interval) = field(c"interval") {
+			options.interval = interval.max(0.0) as u32;
+		}
+
+		if let LuaValue::Boolean(include_autorun) = field(c"includeAutorun") {
+			options.include_autorun = include_autorun;
+		}
+	}
+
+	sampler::start(lua, state, env.realm(), &options)
+}
+
+pub fn stop_sampling(lua: &LuaApi, _state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<String> {
+	sampler::stop(lua, env.realm())
+}
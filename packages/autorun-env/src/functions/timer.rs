@@ -0,0 +1,63 @@
+use autorun_lua::{LuaApi, LuaFunction, RawHandle};
+use autorun_types::LuaState;
+use std::time::Duration;
+
+use crate::timers;
+
+fn schedule(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	callback: LuaFunction,
+	delay: f64,
+	repeat: bool,
+) -> anyhow::Result<u32> {
+	if !delay.is_finite() || delay < 0.0 {
+		anyhow::bail!("Delay must be a non-negative number of milliseconds.");
+	}
+
+	if repeat && delay == 0.0 {
+		anyhow::bail!("Interval must be greater than zero.");
+	}
+
+	let owner = env
+		.get_active_plugin(lua, state)
+		.map(|plugin| plugin.config().plugin.name.clone());
+
+	lua.raw.push(state, &callback);
+	let callback = RawHandle::from_stack(&lua.raw, state).ok_or(anyhow::anyhow!("Failed to store timer callback"))?;
+
+	Ok(timers::schedule(
+		lua,
+		env.realm(),
+		state,
+		Duration::from_secs_f64(delay / 1000.0),
+		repeat,
+		callback,
+		owner,
+	))
+}
+
+pub fn set_timeout(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	callback: LuaFunction,
+	delay: f64,
+) -> anyhow::Result<u32> {
+	schedule(lua, state, env, callback, delay, false)
+}
+
+pub fn set_interval(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	callback: LuaFunction,
+	delay: f64,
+) -> anyhow::Result<u32> {
+	schedule(lua, state, env, callback, delay, true)
+}
+
+pub fn cancel(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle, id: f64) -> anyhow::Result<bool> {
+	Ok(timers::cancel(lua, state, id as u32))
+}
@@ -0,0 +1,25 @@
+use autorun_lua::{LuaApi, LuaStr, RawLuaReturn};
+use autorun_types::LuaState;
+
+use crate::codec::{self, EncodeOptions};
+
+pub fn toml_encode(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle) -> anyhow::Result<String> {
+	let opts = EncodeOptions::from_lua(lua, state, 2)?;
+	lua.raw.settop(state, 1);
+
+	let table = codec::json_to_toml(codec::to_json(lua, state, 1, &opts)?)?;
+	let encoded = if opts.pretty {
+		toml::to_string_pretty(&table)?
+	} else {
+		toml::to_string(&table)?
+	};
+
+	Ok(encoded)
+}
+
+pub fn toml_decode(lua: &LuaApi, state: *mut LuaState, _env: crate::EnvHandle, src: LuaStr) -> anyhow::Result<RawLuaReturn> {
+	let table: toml::Table = toml::from_str(&src)?;
+
+	codec::push_toml(lua, state, &toml::Value::Table(table))?;
+	Ok(RawLuaReturn(1))
+}
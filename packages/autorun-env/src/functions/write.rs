@@ -1,22 +1,17 @@
-use autorun_lua::LuaApi;
+use autorun_lua::{LuaApi, LuaStr};
 use autorun_types::LuaState;
 
-pub fn write(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<()> {
-	let target_path = lua.raw.checkstring(state, 1);
-	let content = lua.raw.checkstring(state, 2);
-
+pub fn write(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle, path: LuaStr, content: LuaStr) -> anyhow::Result<()> {
 	let plugin = env
 		.get_active_plugin(lua, state)
-		.ok_or(anyhow::anyhow!("dont delete autorun.plugin lil bro"))?;
+		.ok_or(anyhow::anyhow!("Could not determine the calling plugin."))?;
 
 	let data_dir = plugin.data_dir();
-	let target_path = target_path.to_string();
-
-	if !data_dir.exists(&target_path) {
-		data_dir.create(&target_path)?;
+	if !data_dir.exists(&*path) {
+		data_dir.create(&*path)?;
 	}
 
-	data_dir.write(target_path, content.to_string())?;
+	data_dir.write(&*path, content.as_bytes())?;
 
 	Ok(())
 }
@@ -1,31 +1,68 @@
 use autorun_log::*;
-use autorun_lua::LuaApi;
+use autorun_lua::{LuaApi, LuaFunction, LuaStr, RawHandle};
 use autorun_types::LuaState;
 
-pub fn write_async(lua: &LuaApi, state: *mut LuaState, env: crate::EnvHandle) -> anyhow::Result<()> {
-	let target_path = lua.raw.checkstring(state, 1);
-	let content = lua.raw.checkstring(state, 2);
+pub fn write_async(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	env: crate::EnvHandle,
+	path: LuaStr,
+	content: LuaStr,
+	callback: Option<LuaFunction>,
+) -> anyhow::Result<()> {
+	let target_path = path.to_string();
+	let content = content.as_bytes().to_vec();
 
 	let plugin = env
 		.get_active_plugin(lua, state)
-		.ok_or(anyhow::anyhow!("dont delete autorun.plugin lil bro."))?;
+		.ok_or(anyhow::anyhow!("Could not determine the calling plugin."))?;
 
 	let data_dir = (*plugin.data_dir()).try_clone()?;
-	let target_path = target_path.to_string();
-	let content = content.to_string();
 
-	std::thread::spawn(move || {
-		if !data_dir.exists(&target_path)
+	let callback = match callback {
+		Some(callback) => {
+			lua.raw.push(state, &callback);
+			Some(RawHandle::from_stack(&lua.raw, state).ok_or(anyhow::anyhow!("Failed to store callback"))?)
+		}
+		None => None,
+	};
+
+	let realm = env.realm();
+	// Coroutines may be gone by the time it finishes, so the callback runs on the main thread.
+	let main = crate::lifecycle::main_state(lua, state);
+	let generation = crate::lifecycle::generation(main);
+	let state_ptr = main as usize;
+	let submitted = crate::workers::submit(move || {
+		let result = if !data_dir.exists(&target_path)
 			&& let Err(why) = data_dir.create(&target_path)
 		{
-			error!("Failed to create file '{target_path}' asynchronously: {why}");
-			return;
-		}
+			Err(format!("Failed to create file '{target_path}' asynchronously: {why}"))
+		} else {
+			data_dir
+				.write(&target_path, content)
+				.map_err(|why| format!("Failed to write to file '{target_path}' asynchronously: {why}"))
+		};
 
-		if let Err(why) = data_dir.write(&target_path, content) {
-			error!("Failed to write to file '{target_path}' asynchronously: {why}");
+		let state = state_ptr as *mut LuaState;
+		match (callback, result) {
+			(Some(callback), Ok(())) => {
+				crate::lua_queue::push_callback(realm, state, generation, callback, (true, None::<String>))
+			}
+			(Some(callback), Err(why)) => {
+				crate::lua_queue::push_callback(realm, state, generation, callback, (None::<bool>, Some(why)))
+			}
+			(None, Err(why)) => error!("{why}"),
+			(None, Ok(())) => (),
 		}
 	});
 
+	if let Err(why) = submitted {
+		if let Some(callback) = callback {
+			let _ = callback.free(lua, state);
+		}
+
+		return Err(why);
+	}
+
 	Ok(())
 }
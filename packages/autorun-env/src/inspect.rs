@@ -0,0 +1,357 @@
+//! Rust-side pretty printer for Lua values, used by `Autorun.print` and `Autorun.inspect`.
+use autorun_lua::{LuaApi, LuaTypeId, LuaValue, REGISTRY_INDEX};
+use autorun_types::LuaState;
+use std::ffi::{CStr, c_int, c_void};
+use std::fmt::Write;
+
+const RESET: &str = "\x1b[0m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+const GRAY: &str = "\x1b[90m";
+
+/// Deepest `depth` accepted from Lua, each level takes a Rust frame and Lua stack space.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct InspectOptions {
+	/// How many levels of nested tables to expand before printing a placeholder.
+	pub depth: usize,
+	/// Maximum amount of entries to print per table.
+	pub width: usize,
+	/// Whether to emit ANSI color codes.
+	pub colors: bool,
+}
+
+impl Default for InspectOptions {
+	fn default() -> Self {
+		Self {
+			depth: 3,
+			width: 32,
+			colors: false,
+		}
+	}
+}
+
+impl InspectOptions {
+	/// Reads options from a Lua table at `index`, keeping defaults for missing fields.
+	pub fn from_lua(lua: &LuaApi, state: *mut LuaState, index: c_int) -> Self {
+		let mut opts = Self::default();
+		if lua.raw.typeid(state, index) != LuaTypeId::Table {
+			return opts;
+		}
+
+		let field = |name: &CStr| -> LuaValue<'_> {
+			lua.raw.getfield(state, index, name.as_ptr());
+			let value = lua.raw.to::<LuaValue>(state, -1);
+			lua.raw.pop(state, 1);
+			value
+		};
+
+		if let LuaValue::Number(depth) = field(c"depth") {
+			opts.depth = (depth.max(0.0) as usize).min(MAX_DEPTH);
+		}
+
+		if let LuaValue::Number(width) = field(c"width") {
+			opts.width = width.max(0.0) as usize;
+		}
+
+		if let LuaValue::Boolean(colors) = field(c"colors") {
+			opts.colors = colors;
+		}
+
+		opts
+	}
+}
+
+/// Renders the value at `index` into a human readable string.
+pub fn inspect(lua: &LuaApi, state: *mut LuaState, index: c_int, opts: &InspectOptions) -> String {
+	let index = absolute_index(lua, state, index);
+	let mut inspector = Inspector {
+		lua,
+		state,
+		opts,
+		path: Vec::new(),
+		out: String::new(),
+	};
+
+	inspector.write_value(index, 0);
+	inspector.out
+}
+
+fn absolute_index(lua: &LuaApi, state: *mut LuaState, index: c_int) -> c_int {
+	if index < 0 && index > REGISTRY_INDEX {
+		lua.raw.gettop(state) + index + 1
+	} else {
+		index
+	}
+}
+
+fn is_identifier(s: &[u8]) -> bool {
+	match s.first() {
+		Some(c) if c.is_ascii_alphabetic() || *c == b'_' => s.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_'),
+		_ => false,
+	}
+}
+
+struct Inspector<'a> {
+	lua: &'a LuaApi,
+	state: *mut LuaState,
+	opts: &'a InspectOptions,
+	/// Tables currently being printed, used to detect cycles.
+	path: Vec<*const c_void>,
+	out: String,
+}
+
+impl Inspector<'_> {
+	fn colored(&mut self, color: &str, text: impl std::fmt::Display) {
+		if self.opts.colors {
+			let _ = write!(self.out, "{color}{text}{RESET}");
+		} else {
+			let _ = write!(self.out, "{text}");
+		}
+	}
+
+	fn typename(&self, index: c_int) -> String {
+		let id = self.lua.raw._typeid(self.state, index);
+		self.lua
+			.raw
+			.typename(self.state, id)
+			.map(|name| name.to_string_lossy().into_owned())
+			.unwrap_or_else(|| String::from("?"))
+	}
+
+	/// Calls the `__tostring` metamethod of the value at `index`, if there is one.
+	fn metamethod_tostring(&self, index: c_int) -> Option<String> {
+		let lua = &self.lua.raw;
+		if lua.getmetatable(self.state, index) == 0 {
+			return None;
+		}
+
+		lua.pushstring(self.state, c"__tostring".as_ptr());
+		lua.rawget(self.state, -2);
+		if lua.typeid(self.state, -1) != LuaTypeId::Function {
+			lua.pop(self.state, 2);
+			return None;
+		}
+
+		lua.pushvalue(self.state, index);
+		let result = match lua.pcall(self.state, 1, 1, 0) {
+			Ok(()) => {
+				let s = lua.tostring(self.state, -1).map(|s| s.into_owned());
+				lua.pop(self.state, 1);
+				s
+			}
+			Err(e) => Some(format!("<__tostring error: {e}>")),
+		};
+
+		lua.pop(self.state, 1);
+		result
+	}
+
+	fn write_value(&mut self, index: c_int, depth: usize) {
+		// Dispatch on the type id rather than converting to LuaValue, which would allocate registry refs.
+		let lua = &self.lua.raw;
+		match lua.typeid(self.state, index) {
+			LuaTypeId::Nil => self.colored(MAGENTA, "nil"),
+			LuaTypeId::Boolean => self.colored(MAGENTA, lua.toboolean(self.state, index)),
+			LuaTypeId::Number => self.colored(YELLOW, lua.tonumber(self.state, index)),
+			LuaTypeId::String => {
+				let s = self.string(index);
+				self.colored(GREEN, format_args!("{s:?}"));
+			}
+			LuaTypeId::Table => self.write_table(index, depth),
+			LuaTypeId::Function => self.write_function(index),
+			LuaTypeId::Userdata => match self.metamethod_tostring(index) {
+				Some(s) => self.out.push_str(&s),
+				None => self.write_pointer(index),
+			},
+			_ => self.write_pointer(index),
+		}
+	}
+
+	fn string(&self, index: c_int) -> String {
+		match self.lua.raw.to::<LuaValue>(self.state, index) {
+			LuaValue::String(s) => String::from_utf8_lossy(s).into_owned(),
+			_ => String::new(),
+		}
+	}
+
+	fn write_pointer(&mut self, index: c_int) {
+		let ptr = self.lua.raw.topointer(self.state, index);
+		let ty = self.typename(index);
+		self.colored(GRAY, format_args!("{ty}: {ptr:p}"));
+	}
+
+	fn write_function(&mut self, index: c_int) {
+		let ptr = self.lua.raw.topointer(self.state, index);
+
+		let location = self.lua.raw.getfuncinfo(self.state, index, c"S").map(|info| {
+			let short_src = unsafe { CStr::from_ptr(info.short_src.as_ptr()) }.to_string_lossy();
+			if info.linedefined > 0 {
+				format!("{short_src}:{}", info.linedefined)
+			} else {
+				short_src.into_owned()
+			}
+		});
+
+		match location {
+			Some(location) => self.colored(CYAN, format_args!("function: {ptr:p} ({location})")),
+			None => self.colored(CYAN, format_args!("function: {ptr:p}")),
+		}
+	}
+
+	fn write_key(&mut self, index: c_int) {
+		// Never use tostring on keys here, it would convert numbers in place and break lua_next.
+		if self.lua.raw.typeid(self.state, index) == LuaTypeId::String {
+			let name = self.string(index);
+			if is_identifier(name.as_bytes()) {
+				self.out.push_str(&name);
+				return;
+			}
+		}
+
+		self.out.push('[');
+		self.write_value(index, usize::MAX);
+		self.out.push(']');
+	}
+
+	fn write_table(&mut self, index: c_int, depth: usize) {
+		// Room for the key and value of an entry, or a metatable, metamethod and its argument.
+		if !self.lua.raw.checkstack(self.state, 3) {
+			let ptr = self.lua.raw.topointer(self.state, index);
+			self.colored(GRAY, format_args!("{{...}} --[[table: {ptr:p}, out of stack space]]"));
+			return;
+		}
+
+		if let Some(s) = self.metamethod_tostring(index) {
+			self.out.push_str(&s);
+			return;
+		}
+
+		let ptr = self.lua.raw.topointer(self.state, index);
+		if self.path.contains(&ptr) {
+			self.colored(GRAY, format_args!("<cycle: table: {ptr:p}>"));
+			return;
+		}
+
+		if depth >= self.opts.depth {
+			self.colored(GRAY, format_args!("{{...}} --[[table: {ptr:p}]]"));
+			return;
+		}
+
+		self.path.push(ptr);
+		self.out.push('{');
+
+		let indent = "\t".repeat(depth + 1);
+		let mut written = 0usize;
+		let mut skipped = 0usize;
+		let mut next_array_index = 1.0;
+
+		let lua = self.lua;
+		let lua = &lua.raw;
+		lua.pushnil(self.state);
+		while lua.next(self.state, index) != 0 {
+			if written >= self.opts.width {
+				skipped += 1;
+				lua.pop(self.state, 1);
+				continue;
+			}
+
+			let top = lua.gettop(self.state);
+			let _ = write!(self.out, "\n{indent}");
+
+			// Sequential integer keys are implied, like in a table constructor.
+			if lua.typeid(self.state, top - 1) == LuaTypeId::Number && lua.tonumber(self.state, top - 1) == next_array_index {
+				next_array_index += 1.0;
+			} else {
+				self.write_key(top - 1);
+				self.out.push_str(" = ");
+			}
+
+			self.write_value(top, depth + 1);
+			self.out.push(',');
+			written += 1;
+
+			lua.pop(self.state, 1);
+		}
+
+		if skipped > 0 {
+			let _ = write!(self.out, "\n{indent}");
+			self.colored(GRAY, format_args!("... ({skipped} more)"));
+		}
+
+		if written > 0 || skipped > 0 {
+			let _ = write!(self.out, "\n{}", "\t".repeat(depth));
+		}
+
+		self.out.push('}');
+		self.path.pop();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autorun_test::State;
+
+	fn inspect_code(state: &State, code: &str, opts: &InspectOptions) -> String {
+		state.eval(code);
+		let out = inspect(state.lua(), state.state, -1, opts);
+		state.lua().raw.pop(state.state, 1);
+		out
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn prints_nested_tables() {
+		let state = State::new();
+		let opts = InspectOptions::default();
+
+		let out = inspect_code(
+			&state,
+			"return { 1, 'two', nested = { ok = true }, ['not ident'] = false }",
+			&opts,
+		);
+		assert!(out.starts_with("{\n\t1,\n\t\"two\","), "{out}");
+		assert!(out.contains("nested = {\n\t\tok = true,\n\t},"), "{out}");
+		assert!(out.contains("[\"not ident\"] = false,"), "{out}");
+
+		let out = inspect_code(&state, "local t = {} t.self = t return t", &opts);
+		assert!(out.contains("self = <cycle: table: "), "{out}");
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn stops_once_out_of_stack_space() {
+		let state = State::new();
+		let lua = &state.lua().raw;
+		state.eval("local root = {} local t = root for _ = 1, 20 do t.next = {} t = t.next end return root");
+
+		// Leave only a few slots of the 8000 a C frame may grow to.
+		assert!(lua.checkstack(state.state, 7980));
+		while lua.checkstack(state.state, 8) {
+			lua.pushnil(state.state);
+		}
+
+		let top = lua.gettop(state.state);
+		let opts = InspectOptions {
+			depth: usize::MAX,
+			..Default::default()
+		};
+		let out = inspect(state.lua(), state.state, 1, &opts);
+
+		assert!(out.contains("\t\t\tnext = {...} --[[table: "), "{out}");
+		assert!(out.ends_with(", out of stack space]],\n\t\t},\n\t},\n}"), "{out}");
+		assert_eq!(lua.gettop(state.state), top);
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn caps_depth_from_lua() {
+		let state = State::new();
+		state.eval("return { depth = math.huge }");
+		assert_eq!(InspectOptions::from_lua(state.lua(), state.state, -1).depth, MAX_DEPTH);
+	}
+}
@@ -1,4 +1,22 @@
+mod codec;
 mod functions;
+pub mod chunks;
+pub mod count_hook;
+pub mod detours;
+pub mod errors;
+pub mod events;
+pub mod inspect;
+pub mod lifecycle;
+pub mod lua_queue;
+pub mod modules;
+pub mod profiler;
+pub mod sampler;
+pub mod timers;
+pub mod watchdog;
+pub mod workers;
+
+#[cfg(test)]
+mod testing;
 
 mod env;
 pub use env::*;
@@ -0,0 +1,191 @@
+//! Lifecycle of the Lua state of each realm, from the moment Autorun first sees it to the moment it closes.
+//! Every state gets a generation when it is opened, so a new state allocated at the address of a closed one isn't
+//! mistaken for it. Closing a state delivers `disconnect` or `mapchange` to its plugins, then tears down everything
+//! Autorun tied to it: plugins, listeners, detours, timers and the environment itself.
+use std::sync::Mutex;
+
+use autorun_log::*;
+use autorun_lua::{LuaApi, REGISTRY_INDEX};
+use autorun_types::{LuaState, Realm};
+
+use crate::{count_hook, detours, events, global, timers};
+
+struct Tracked {
+	realm: Realm,
+	state: usize,
+	/// Address of the registry of the state, to forget about handles into it once the state is gone.
+	registry: usize,
+	generation: u64,
+}
+
+struct Lifecycle {
+	next_generation: u64,
+	states: Vec<Tracked>,
+}
+
+static LIFECYCLE: Mutex<Lifecycle> = Mutex::new(Lifecycle {
+	next_generation: 1,
+	states: Vec::new(),
+});
+
+/// Starts tracking `state`, the main thread of a state, as the state of a realm, returning false if it already was.
+/// A previous state of the realm that closed without Autorun noticing is forgotten along with what was tied to it.
+pub fn open(lua: &LuaApi, realm: Realm, state: *mut LuaState) -> bool {
+	let registry = lua.raw.topointer(state, REGISTRY_INDEX) as usize;
+	let stale = {
+		let mut lifecycle = LIFECYCLE.lock().unwrap();
+		if lifecycle.states.iter().any(|t| t.realm == realm && t.state == state as usize) {
+			return false;
+		}
+
+		let generation = lifecycle.next_generation;
+		lifecycle.next_generation += 1;
+
+		let stale = lifecycle.states.iter().position(|t| t.realm == realm);
+		let stale = stale.map(|pos| lifecycle.states.remove(pos));
+
+		lifecycle.states.push(Tracked {
+			realm,
+			state: state as usize,
+			registry,
+			generation,
+		});
+
+		stale
+	};
+
+	if let Some(stale) = stale {
+		warn!("The previous {realm} state closed unnoticed, dropping what was left of it");
+		// Its handles are left alone, the state they would be freed through is gone.
+		autorun_lua::forget_registry_at(stale.registry);
+		count_hook::forget_registry(stale.registry);
+		release(realm);
+	}
+
+	true
+}
+
+/// Returns the generation of a state if it is the tracked state of its realm.
+pub fn generation(state: *mut LuaState) -> Option<u64> {
+	let lifecycle = LIFECYCLE.lock().unwrap();
+	lifecycle
+		.states
+		.iter()
+		.find(|t| t.state == state as usize)
+		.map(|t| t.generation)
+}
+
+/// Returns the main thread of a tracked state from any of its threads, or `state` itself if it isn't tracked.
+/// Coroutines share everything Autorun ties to their state, so that is keyed by the main thread.
+pub fn main_state(lua: &LuaApi, state: *mut LuaState) -> *mut LuaState {
+	let registry = lua.raw.topointer(state, REGISTRY_INDEX) as usize;
+	let lifecycle = LIFECYCLE.lock().unwrap();
+	lifecycle
+		.states
+		.iter()
+		.find(|t| t.registry == registry)
+		.map_or(state, |t| t.state as *mut LuaState)
+}
+
+/// Tears down everything tied to a tracked state that is about to close, after letting its plugins know.
+/// Must be called while the state is still usable. Does nothing for states that aren't tracked.
+pub fn close(lua: &LuaApi, state: *mut LuaState) {
+	let realm = {
+		let mut lifecycle = LIFECYCLE.lock().unwrap();
+		let Some(pos) = lifecycle.states.iter().position(|t| t.state == state as usize) else {
+			return;
+		};
+
+		lifecycle.states.remove(pos).realm
+	};
+
+	if let Some(env) = global::get_realm_env(realm) {
+		if let Some(event) = closing_event(realm) {
+			env.trigger(lua, state, event, (), |_| ());
+		}
+
+		env.close(lua, state);
+	}
+
+	count_hook::forget(lua, state);
+	// Handles to tables and functions of the state may outlive it, and must not be freed once it is gone.
+	autorun_lua::forget_registry(&lua.raw, state);
+	release(realm);
+	info!("Tore down the {realm} realm");
+}
+
+/// Event telling plugins why the state of a realm is closing. The game stays connected across a map change.
+fn closing_event(realm: Realm) -> Option<&'static str> {
+	if realm == Realm::Menu {
+		return None;
+	}
+
+	match autorun_interfaces::engine_client::get_api() {
+		Ok(engine) if engine.is_connected() => Some("mapchange"),
+		_ => Some("disconnect"),
+	}
+}
+
+/// Drops the environment of a realm and everything registered from its state, without touching the state.
+fn release(realm: Realm) {
+	global::take_realm_env(realm);
+	events::remove_realm(realm);
+	detours::remove_realm(realm);
+	timers::remove_realm(realm);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autorun_test::State;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn leaves_handles_into_a_stale_state_alone() {
+		let _serial = autorun_test::serial();
+		let (old, new) = (State::new(), State::new());
+		let lua = old.lua();
+
+		assert!(open(lua, Realm::Client, old.state));
+		let table = lua.table(old.state);
+		let before = old.registry_len();
+
+		// The old state closing unnoticed, as far as the handle is concerned.
+		assert!(open(lua, Realm::Client, new.state));
+		drop(table);
+		assert_eq!(old.registry_len(), before);
+		assert_eq!(generation(old.state), None);
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn resolves_coroutines_to_their_main_thread() {
+		let _serial = autorun_test::serial();
+		let state = State::new();
+		let lua = state.lua();
+		state.eval("return coroutine.create(function() end)");
+		let thread = lua.raw.tothread(state.state, -1);
+		assert_eq!(main_state(lua, thread), thread);
+
+		assert!(open(lua, Realm::Menu, state.state));
+		assert_eq!(main_state(lua, thread), state.state);
+		assert_eq!(main_state(lua, state.state), state.state);
+		close(lua, state.state);
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn forgets_the_count_hook_of_a_stale_state() {
+		let _serial = autorun_test::serial();
+		let (old, new) = (State::new(), State::new());
+		let lua = old.lua();
+
+		assert!(open(lua, Realm::Server, old.state));
+		count_hook::set(lua, old.state, count_hook::Listener::Sampler, Some(1000));
+		assert!(open(lua, Realm::Server, new.state));
+
+		// Nothing is left of the old state to put back, so its hook is left as it is.
+		count_hook::set(lua, old.state, count_hook::Listener::Sampler, None);
+		assert!(!lua.raw.gethook(old.state).is_null());
+	}
+}
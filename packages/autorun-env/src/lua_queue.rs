@@ -0,0 +1,53 @@
+//! The purpose of the Lua Queue is to allow other threads to schedule code to run on the main lua thread.
+//! Otherwise, if you try to run lua outside the main thread, you'll most definitely crash the game.
+use std::sync::{Arc, LazyLock, Mutex};
+
+use autorun_lua::{IntoLuaArgs, LuaApi, RawHandle};
+use autorun_types::{LuaState, Realm};
+
+use crate::{errors, lifecycle, profiler};
+
+type LuaCallback = Box<dyn FnOnce(&LuaApi) -> anyhow::Result<()> + Send + 'static>;
+
+static LUA_QUEUE: LazyLock<Arc<Mutex<Vec<LuaCallback>>>> = LazyLock::new(|| Arc::new(Mutex::new(vec![])));
+
+pub fn push(f: impl FnOnce(&LuaApi) -> anyhow::Result<()> + Send + 'static) {
+	let mut queue = LUA_QUEUE.lock().unwrap();
+	queue.push(Box::new(f));
+}
+
+pub fn pop() -> Option<LuaCallback> {
+	let mut queue = LUA_QUEUE.lock().unwrap();
+	if queue.is_empty() { None } else { Some(queue.remove(0)) }
+}
+
+/// Schedules a Lua function stored in the registry to be called with `args` on the game thread, freeing it afterwards.
+/// `state` must be the main thread, see [`lifecycle::main_state`], and `generation` its generation when the callback was
+/// stored. Nothing is called if the state no longer exists by then, even if a new one took its address.
+pub fn push_callback(
+	realm: Realm,
+	state: *mut LuaState,
+	generation: Option<u64>,
+	callback: RawHandle,
+	args: impl IntoLuaArgs + Send + 'static,
+) {
+	let state = state as usize;
+
+	push(move |lua| {
+		let state = state as *mut LuaState;
+		let live = matches!(autorun_interfaces::lua::get_state(realm), Ok(Some(current)) if current == state);
+		if !live || lifecycle::generation(state) != generation {
+			// The callback went away with the state it was stored in.
+			return Ok(());
+		}
+
+		lua.raw.push(state, &callback);
+		let nargs = args.push_args(&lua.raw, state);
+		if let Err(why) = profiler::measure(realm, None, "async callback", || errors::pcall(lua, state, nargs, 0)) {
+			errors::report(lua, state, realm, None, "async callback", why);
+		}
+
+		callback.free(lua, state)?;
+		Ok(())
+	});
+}
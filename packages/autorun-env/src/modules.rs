@@ -0,0 +1,311 @@
+//! Module resolution and bytecode caching behind Autorun.require.
+//! Module names are dotted, like `util.strings`, and looked up as `util/strings.lua` then `util/strings/init.lua` in each
+//! search path of the plugin, `src` unless its config says otherwise. Names ending in `.lua` are taken as file paths.
+//! A name like `other/util.strings` refers to a module of `other`, provided it is a dependency of the requiring plugin.
+//!
+//! Compiled modules are kept as bytecode in the cache directory of the workspace, outside of any plugin, by plugin name
+//! and a hash of their source. Bytecode is loaded without any verification, so each entry starts with an HMAC of it
+//! under a key of the workspace, and only entries Autorun wrote itself are ever loaded.
+use std::ffi::CStr;
+use std::sync::{Arc, OnceLock};
+
+use autorun_core::plugins::Plugin;
+use autorun_log::*;
+use autorun_lua::{LuaApi, LuaFunction};
+use autorun_types::LuaState;
+use cap_std::fs::Dir;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::errors;
+
+/// File of the cache directory holding the key entries are signed with.
+const KEY_FILE: &str = "key";
+
+/// Directory of the cache holding compiled modules.
+const MODULES_DIR: &str = "modules";
+
+const TAG_LEN: usize = 32;
+
+const BCDUMP_HEAD: &[u8] = b"\x1bLJ";
+const BCDUMP_F_STRIP: u32 = 0x02;
+
+struct Cache {
+	dir: Dir,
+	key: [u8; 32],
+}
+
+static CACHE: OnceLock<Cache> = OnceLock::new();
+
+/// Caches compiled modules in `dir` from now on, creating the key to sign them with if it doesn't have one yet.
+/// Without it, modules are compiled every time they are required.
+pub fn set_cache_dir(dir: Dir) -> anyhow::Result<()> {
+	let key = match dir.read(KEY_FILE).ok().and_then(|key| <[u8; 32]>::try_from(key).ok()) {
+		Some(key) => key,
+		None => {
+			let key = rand::random::<[u8; 32]>();
+			dir.write(KEY_FILE, key)?;
+			key
+		}
+	};
+
+	CACHE
+		.set(Cache { dir, key })
+		.map_err(|_| anyhow::anyhow!("Cache directory is already set"))
+}
+
+/// A module file, found in a plugin through [`resolve`].
+pub struct Module {
+	pub plugin: Arc<Plugin>,
+	/// Path of the file, relative to the plugin.
+	pub path: String,
+}
+
+/// Finds the module `name` refers to when required by `plugin`, looking up its dependencies through `get_plugin`.
+pub fn resolve(plugin: &Arc<Plugin>, name: &str, get_plugin: impl Fn(&str) -> Option<Arc<Plugin>>) -> anyhow::Result<Module> {
+	let config = &plugin.config().plugin;
+	let dependency = name
+		.split_once('/')
+		.filter(|(dep, _)| config.dependencies.iter().flatten().any(|d| d == dep));
+
+	let (owner, module) = match dependency {
+		Some((dep, module)) => {
+			let owner = get_plugin(dep)
+				.ok_or_else(|| anyhow::anyhow!("Dependency '{dep}' of plugin '{}' is not loaded", config.name))?;
+
+			(owner, module)
+		}
+		None => (plugin.clone(), name),
+	};
+
+	let candidates = candidates(module)?;
+	let search_paths = match &owner.config().plugin.search_paths {
+		Some(search_paths) => search_paths.iter().map(String::as_str).collect(),
+		None => vec!["src"],
+	};
+
+	for search_path in search_paths {
+		for candidate in &candidates {
+			let path = format!("{}/{candidate}", search_path.trim_end_matches('/'));
+			if owner.dir().is_file(&path) {
+				return Ok(Module { plugin: owner, path });
+			}
+		}
+	}
+
+	if dependency.is_none()
+		&& let Some((other, _)) = name.split_once('/')
+		&& get_plugin(other).is_some()
+	{
+		anyhow::bail!(
+			"Module '{name}' not found, add '{other}' to the dependencies of plugin '{}' to require its modules",
+			config.name
+		);
+	}
+
+	anyhow::bail!("Module '{module}' not found in plugin '{}'", owner.config().plugin.name)
+}
+
+/// Files a module name can refer to, in the order they are looked up.
+fn candidates(name: &str) -> anyhow::Result<Vec<String>> {
+	if let Some(path) = name.strip_suffix(".lua") {
+		check_segments(name, path.split('/'))?;
+		return Ok(vec![name.to_owned()]);
+	}
+
+	check_segments(name, name.split(['/', '.']))?;
+
+	let path = name.replace('.', "/");
+	Ok(vec![format!("{path}.lua"), format!("{path}/init.lua")])
+}
+
+fn check_segments<'a>(name: &str, mut segments: impl Iterator<Item = &'a str>) -> anyhow::Result<()> {
+	if segments.any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+		anyhow::bail!("Invalid module name '{name}'");
+	}
+
+	Ok(())
+}
+
+/// Compiles the source of a module of `plugin`, reusing the bytecode cached for the same source when there is some.
+pub fn compile(
+	lua: &LuaApi,
+	state: *mut LuaState,
+	plugin: &Plugin,
+	source: &[u8],
+	chunk_name: &CStr,
+) -> anyhow::Result<LuaFunction> {
+	let name = &plugin.config().plugin.name;
+	let hash = xxhash_rust::xxh3::xxh3_64(source);
+
+	// Bytecode from another LuaJIT build is rejected when loading, falling back to compiling it again.
+	if let Some(bytecode) = read_cached(name, hash)
+		&& let Ok(bytecode) = set_chunk_name(&bytecode, chunk_name.to_bytes())
+		&& let Ok(chunk) = lua.load_bytecode(state, &bytecode, chunk_name)
+	{
+		return Ok(chunk);
+	}
+
+	let chunk = lua
+		.load(state, source, chunk_name)
+		.map_err(|why| anyhow::anyhow!(errors::CallError::from(why).message))?;
+
+	lua.raw.push(state, &chunk);
+	let bytecode = lua.raw.dump(state);
+	lua.raw.pop(state, 1);
+
+	// The chunk name changes with every environment, so it is left out and put back when loading.
+	let cached = bytecode
+		.map_err(anyhow::Error::from)
+		.and_then(|bytecode| set_chunk_name(&bytecode, b""))
+		.and_then(|bytecode| write_cached(name, hash, &bytecode));
+
+	if let Err(why) = cached {
+		warn!("Failed to cache bytecode of plugin '{name}': {why}");
+	}
+
+	Ok(chunk)
+}
+
+/// Path of the cached bytecode of a module, if its plugin has a name that can be used as a directory.
+fn cache_path(plugin: &str, hash: u64) -> Option<String> {
+	let valid = !plugin.is_empty() && plugin.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+	valid.then(|| format!("{MODULES_DIR}/{plugin}/{hash:016x}.luac"))
+}
+
+/// Tag of an entry, tying its bytecode to the plugin and source it was compiled from.
+fn sign(key: &[u8; 32], plugin: &str, hash: u64, bytecode: &[u8]) -> Hmac<Sha256> {
+	let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+	mac.update(plugin.as_bytes());
+	mac.update(&[0]);
+	mac.update(&hash.to_le_bytes());
+	mac.update(bytecode);
+	mac
+}
+
+fn read_cached(plugin: &str, hash: u64) -> Option<Vec<u8>> {
+	let cache = CACHE.get()?;
+	let entry = cache.dir.read(cache_path(plugin, hash)?).ok()?;
+	let (tag, bytecode) = entry.split_at_checked(TAG_LEN)?;
+
+	match sign(&cache.key, plugin, hash, bytecode).verify_slice(tag) {
+		Ok(()) => Some(bytecode.to_vec()),
+		Err(_) => {
+			warn!("Ignoring cached bytecode of plugin '{plugin}' that Autorun didn't write");
+			None
+		}
+	}
+}
+
+fn write_cached(plugin: &str, hash: u64, bytecode: &[u8]) -> anyhow::Result<()> {
+	let Some(cache) = CACHE.get() else {
+		return Ok(());
+	};
+
+	let Some(path) = cache_path(plugin, hash) else {
+		return Ok(());
+	};
+
+	let mut entry = sign(&cache.key, plugin, hash, bytecode).finalize().into_bytes().to_vec();
+	entry.extend_from_slice(bytecode);
+
+	cache.dir.create_dir_all(format!("{MODULES_DIR}/{plugin}"))?;
+	cache.dir.write(path, entry)?;
+	Ok(())
+}
+
+/// Replaces the chunk name stored in the header of LuaJIT bytecode.
+fn set_chunk_name(bytecode: &[u8], name: &[u8]) -> anyhow::Result<Vec<u8>> {
+	let header = bytecode.get(..BCDUMP_HEAD.len() + 1).filter(|h| h.starts_with(BCDUMP_HEAD));
+	let Some(header) = header else {
+		anyhow::bail!("Not LuaJIT bytecode");
+	};
+
+	let (flags, rest) = read_uleb128(&bytecode[header.len()..])?;
+	if flags & BCDUMP_F_STRIP != 0 {
+		anyhow::bail!("Bytecode without debug info has no chunk name");
+	}
+
+	let (len, rest) = read_uleb128(rest)?;
+	let Some(rest) = rest.get(len as usize..) else {
+		anyhow::bail!("Truncated bytecode");
+	};
+
+	let mut out = header.to_vec();
+	write_uleb128(&mut out, flags);
+	write_uleb128(&mut out, name.len() as u32);
+	out.extend_from_slice(name);
+	out.extend_from_slice(rest);
+
+	Ok(out)
+}
+
+fn read_uleb128(bytes: &[u8]) -> anyhow::Result<(u32, &[u8])> {
+	let mut value = 0u32;
+
+	for (i, byte) in bytes.iter().enumerate().take(5) {
+		value |= ((byte & 0x7f) as u32) << (7 * i);
+		if byte & 0x80 == 0 {
+			return Ok((value, &bytes[i + 1..]));
+		}
+	}
+
+	anyhow::bail!("Truncated bytecode")
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u32) {
+	while value >= 0x80 {
+		out.push((value & 0x7f) as u8 | 0x80);
+		value >>= 7;
+	}
+
+	out.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn replaces_chunk_name() {
+		let bytecode = b"\x1bLJ\x02\x08\x05@name\x00\x01\x02";
+		let renamed = set_chunk_name(bytecode, b"123:plugin:mod.lua").unwrap();
+
+		assert_eq!(renamed, b"\x1bLJ\x02\x08\x12123:plugin:mod.lua\x00\x01\x02");
+		assert_eq!(set_chunk_name(&renamed, b"@name").unwrap(), bytecode);
+	}
+
+	#[test]
+	fn rejects_module_names_leaving_the_plugin() {
+		assert!(candidates("../other/mod").is_err());
+		assert!(candidates("util..strings").is_err());
+		assert_eq!(
+			candidates("util.strings").unwrap(),
+			["util/strings.lua", "util/strings/init.lua"]
+		);
+		assert_eq!(candidates("shared/util.lua").unwrap(), ["shared/util.lua"]);
+	}
+
+	#[test]
+	fn signs_entries_for_their_plugin_and_source() {
+		let key = [7; 32];
+		let tag = sign(&key, "app", 1, b"bytecode").finalize().into_bytes();
+
+		assert!(sign(&key, "app", 1, b"bytecode").verify_slice(&tag).is_ok());
+		assert!(sign(&key, "app", 1, b"bytecodf").verify_slice(&tag).is_err());
+		assert!(sign(&key, "other", 1, b"bytecode").verify_slice(&tag).is_err());
+		assert!(sign(&key, "app", 2, b"bytecode").verify_slice(&tag).is_err());
+		assert!(sign(&[8; 32], "app", 1, b"bytecode").verify_slice(&tag).is_err());
+	}
+
+	#[test]
+	fn keeps_plugin_names_to_one_directory() {
+		assert_eq!(
+			cache_path("my-plugin_2", 0xab).unwrap(),
+			"modules/my-plugin_2/00000000000000ab.luac"
+		);
+		assert!(cache_path("../app", 1).is_none());
+		assert!(cache_path("a/b", 1).is_none());
+		assert!(cache_path("", 1).is_none());
+	}
+}
@@ -0,0 +1,106 @@
+//! Wall time spent in Lua called from Autorun, aggregated per realm, plugin and call site.
+//! Time is measured around each call into Lua, so a site that runs another one, like an event listener calling a
+//! detoured function, also counts the time of the inner site.
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use autorun_types::{ProfileEntry, Realm};
+
+struct Stats {
+	realm: Realm,
+	plugin: Option<String>,
+	site: String,
+	calls: u64,
+	total: Duration,
+	max: Duration,
+}
+
+static PROFILE: Mutex<Vec<Stats>> = Mutex::new(Vec::new());
+
+/// Runs `f`, adding the time it took to the stats of `site`.
+pub fn measure<R>(realm: Realm, plugin: Option<&str>, site: &str, f: impl FnOnce() -> R) -> R {
+	let start = Instant::now();
+	let result = f();
+	record(realm, plugin, site, start.elapsed());
+	result
+}
+
+/// Adds a call that took `elapsed` to the stats of `site`.
+pub fn record(realm: Realm, plugin: Option<&str>, site: &str, elapsed: Duration) {
+	let mut profile = PROFILE.lock().unwrap();
+
+	let stats = match profile
+		.iter()
+		.position(|s| s.realm == realm && s.plugin.as_deref() == plugin && s.site == site)
+	{
+		Some(pos) => &mut profile[pos],
+		None => {
+			profile.push(Stats {
+				realm,
+				plugin: plugin.map(str::to_owned),
+				site: site.to_owned(),
+				calls: 0,
+				total: Duration::ZERO,
+				max: Duration::ZERO,
+			});
+
+			profile.last_mut().unwrap()
+		}
+	};
+
+	stats.calls += 1;
+	stats.total += elapsed;
+	stats.max = stats.max.max(elapsed);
+}
+
+/// Returns the stats of every site, slowest in total first.
+pub fn snapshot() -> Vec<ProfileEntry> {
+	let profile = PROFILE.lock().unwrap();
+	let mut entries: Vec<ProfileEntry> = profile
+		.iter()
+		.map(|s| ProfileEntry {
+			realm: s.realm,
+			plugin: s.plugin.clone(),
+			site: s.site.clone(),
+			calls: s.calls,
+			total_us: s.total.as_micros() as u64,
+			max_us: s.max.as_micros() as u64,
+		})
+		.collect();
+
+	entries.sort_by_key(|e| std::cmp::Reverse(e.total_us));
+	entries
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Env;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn accounts_calls_per_plugin_and_site() {
+		let env = Env::new();
+		let init = "Autorun.on('tick', function() for _ = 1, 1e5 do end end)";
+		env.plugin("profiled", "", &[("src/menu/init.lua", init)]).unwrap();
+
+		let lua = env.state.lua();
+		for _ in 0..3 {
+			env.env.trigger(lua, env.state.state, "tick", (), |_| ());
+		}
+
+		let reader = "
+			local sites = {}
+			for _, entry in ipairs(Autorun.getProfile()) do
+				if entry.plugin == 'profiled' then
+					assert(entry.total >= entry.max)
+					sites[#sites + 1] = entry.realm .. ' ' .. entry.site .. ' x' .. entry.calls
+				end
+			end
+			table.sort(sites)
+			_G.sites = table.concat(sites, ', ')
+		";
+		env.plugin("reader", "", &[("src/menu/init.lua", reader)]).unwrap();
+
+		assert_eq!(env.results("sites"), "Menu 'tick' listener x3, Menu chunk 'menu/init.lua' x1");
+	}
+}
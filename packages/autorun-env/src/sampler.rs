@@ -0,0 +1,204 @@
+//! Sampling profiler, recording the Lua stack of a realm every few instructions through a count hook.
+//! Samples are aggregated as folded stacks, outermost frame first, which is what flamegraph tools take.
+//! Hooks are shared by every coroutine of a state, so they all get sampled. The [`count_hook`] turns the JIT off while
+//! sampling, and traces compiled before are flushed once when it starts, as compiled code doesn't run hooks.
+use std::collections::HashMap;
+use std::ffi::{CStr, c_char, c_int};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use autorun_lua::{LUAJIT_MODE_ENGINE, LUAJIT_MODE_FLUSH, LuaApi, RawDebugInfo};
+use autorun_types::{LuaState, Realm, SamplingOptions};
+
+use crate::count_hook::{self, Listener};
+use crate::{chunks, lifecycle};
+
+/// Deepest a sampled stack goes, frames past it are dropped.
+const MAX_DEPTH: c_int = 128;
+
+struct Session {
+	realm: Realm,
+	/// Main thread of the sampled state, along with its generation to tell it apart from a state opened in its place.
+	state: usize,
+	generation: Option<u64>,
+	until: Option<Instant>,
+	include_autorun: bool,
+	running: bool,
+	stacks: HashMap<String, u64>,
+}
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+/// Starts sampling the state of a realm, discarding the samples of a previous session.
+pub fn start(lua: &LuaApi, state: *mut LuaState, realm: Realm, options: &SamplingOptions) -> anyhow::Result<()> {
+	let mut session = SESSION.lock().unwrap();
+	if let Some(running) = session.as_ref().filter(|s| s.running) {
+		anyhow::bail!("Already sampling the {} realm", running.realm);
+	}
+
+	if options.interval == 0 {
+		anyhow::bail!("Sampling interval must be at least one instruction");
+	}
+
+	// Hooks are shared by every coroutine, which may be gone once sampling stops.
+	let state = lifecycle::main_state(lua, state);
+	*session = Some(Session {
+		realm,
+		state: state as usize,
+		generation: lifecycle::generation(state),
+		until: options.duration_ms.map(|ms| Instant::now() + Duration::from_millis(ms)),
+		include_autorun: options.include_autorun,
+		running: true,
+		stacks: HashMap::new(),
+	});
+
+	// Code running in traces compiled before would never be sampled.
+	lua.raw.jit_setmode(state, 0, LUAJIT_MODE_ENGINE | LUAJIT_MODE_FLUSH);
+	count_hook::set(lua, state, Listener::Sampler, Some(options.interval as c_int));
+
+	Ok(())
+}
+
+/// Stops sampling a realm if it still is, returning its samples as folded stacks.
+pub fn stop(lua: &LuaApi, realm: Realm) -> anyhow::Result<String> {
+	let mut session = SESSION.lock().unwrap();
+	let Some(current) = session.as_mut().filter(|s| s.realm == realm) else {
+		anyhow::bail!("The {realm} realm hasn't been sampled");
+	};
+
+	if current.running {
+		finish(lua, current);
+	}
+
+	let session = session.take().unwrap();
+	let mut lines: Vec<String> = session
+		.stacks
+		.into_iter()
+		.map(|(stack, count)| format!("{stack} {count}"))
+		.collect();
+
+	lines.sort();
+	Ok(lines.join("\n"))
+}
+
+/// Puts back what sampling changed, unless the state it ran in is gone.
+fn finish(lua: &LuaApi, session: &mut Session) {
+	session.running = false;
+
+	let state = session.state as *mut LuaState;
+	if session.generation.is_none() || lifecycle::generation(state) != session.generation {
+		return;
+	}
+
+	count_hook::set(lua, state, Listener::Sampler, None);
+}
+
+/// Called by the [`count_hook`] once the sampling interval passed.
+pub(crate) fn sample_now(lua: &LuaApi, state: *mut LuaState) {
+	let mut session = SESSION.lock().unwrap();
+	let Some(session) = session.as_mut().filter(|s| s.running) else {
+		return;
+	};
+
+	if session.until.is_some_and(|until| Instant::now() >= until) {
+		finish(lua, session);
+		return;
+	}
+
+	let stack = sample(lua, state, session.include_autorun);
+	if !stack.is_empty() {
+		*session.stacks.entry(stack).or_default() += 1;
+	}
+}
+
+/// Returns the stack of a state as a folded line, without its count.
+fn sample(lua: &LuaApi, state: *mut LuaState, include_autorun: bool) -> String {
+	let mut frames = Vec::new();
+
+	for level in 0..MAX_DEPTH {
+		let Some(info) = lua.raw.getinfo(state, level, c"Sn") else {
+			break;
+		};
+
+		let source = to_string(info.source);
+		let is_autorun = chunks::readable(&source).is_some();
+		if is_autorun && !include_autorun {
+			continue;
+		}
+
+		frames.push(frame(&info).replace(';', ","));
+	}
+
+	frames.reverse();
+	frames.join(";")
+}
+
+/// Names a frame like `name (source:line)`, with the readable names of Autorun's chunks.
+fn frame(info: &RawDebugInfo) -> String {
+	let name = match to_string(info.name) {
+		name if name.is_empty() => None,
+		name => Some(name),
+	};
+
+	match to_string(info.what).as_str() {
+		"C" => format!("{} [C]", name.as_deref().unwrap_or("?")),
+		"main" => format!("main chunk ({})", chunks::prettify(&to_string(info.short_src.as_ptr()))),
+		_ => format!(
+			"{} ({}:{})",
+			name.as_deref().unwrap_or("?"),
+			chunks::prettify(&to_string(info.short_src.as_ptr())),
+			info.linedefined
+		),
+	}
+}
+
+fn to_string(ptr: *const c_char) -> String {
+	if ptr.is_null() {
+		return String::new();
+	}
+
+	unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::testing::Env;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn folds_sampled_stacks_of_code_outside_autorun() {
+		let env = Env::new();
+		env.state.run(
+			"function gamemode() local x = 0 for i = 1, 2e5 do x = x + i end return x end",
+			0,
+		);
+
+		let init = "
+			local function busy() local x = 0 for i = 1, 2e5 do x = x + i end return x end
+			for _, includeAutorun in ipairs({ false, true }) do
+				Autorun.startSampling({ interval = 100, includeAutorun = includeAutorun })
+				_G.gamemode()
+				busy()
+				_G[tostring(includeAutorun)] = Autorun.stopSampling()
+			end
+		";
+		env.plugin("sampled", "", &[("src/menu/init.lua", init)]).unwrap();
+		assert!(env.state.lua().raw.gethook(env.state.state).is_null());
+
+		let folded = env.results("_G['false']");
+		assert!(folded.lines().count() >= 1, "{folded}");
+		for line in folded.lines() {
+			assert!(line.starts_with("gamemode ([string \"function gamemode()"), "{line}");
+		}
+
+		let folded = env.results("_G['true']");
+		assert!(
+			folded.contains("main chunk (sampled:menu/init.lua);gamemode ([string"),
+			"{folded}"
+		);
+		assert!(
+			folded.contains("main chunk (sampled:menu/init.lua);busy (sampled:menu/init.lua:2) "),
+			"{folded}"
+		);
+	}
+}
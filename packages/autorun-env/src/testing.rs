@@ -0,0 +1,100 @@
+//! Environments for tests, set up in LuaJIT states from autorun_test the way Autorun sets up realms.
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, MutexGuard};
+
+use autorun_core::plugins::Plugin;
+use autorun_test::State;
+use autorun_types::Realm;
+use cap_std::ambient_authority;
+use cap_std::fs::Dir;
+
+use crate::{EnvHandle, global, lifecycle};
+
+/// The environment of a realm in a fresh state, torn down along with everything tied to it once dropped.
+/// Only one exists at a time, as realms are process wide.
+pub struct Env {
+	pub state: State,
+	pub env: EnvHandle,
+	dirs: RefCell<Vec<PathBuf>>,
+	_serial: MutexGuard<'static, ()>,
+}
+
+impl Env {
+	pub fn new() -> Self {
+		Self::with_realm(Realm::Menu)
+	}
+
+	/// Sets up the environment of `realm` instead. Authorization still looks up the menu's, so only plugin entrypoints
+	/// and code run through [`Env::run`] get to run in others.
+	pub fn with_realm(realm: Realm) -> Self {
+		let serial = autorun_test::serial();
+		let state = State::new();
+		let lua = state.lua();
+
+		lifecycle::open(lua, realm, state.state);
+		let env = EnvHandle::create(lua, state.state, realm, vec!["std".into()]).unwrap();
+		global::set_realm_env(realm, env.clone());
+
+		let env = Self {
+			state,
+			env,
+			dirs: RefCell::default(),
+			_serial: serial,
+		};
+
+		// Standing in for the builtins the std plugin would define.
+		env.run("_G.setmetatable(_G.getfenv(1), { __index = _G })").unwrap();
+		env
+	}
+
+	/// Runs `code` in the shared environment, like Autorun runs code it is given.
+	pub fn run(&self, code: &str) -> anyhow::Result<()> {
+		self.env.execute(self.state.lua(), self.state.state, c"test", code.as_bytes())
+	}
+
+	/// Loads a plugin and runs its entrypoints. `config` is added to the `[plugin]` table of its plugin.toml, and `files`
+	/// are the paths of its other files with their contents, such as `src/menu/init.lua`.
+	pub fn plugin(&self, name: &str, config: &str, files: &[(&str, &str)]) -> anyhow::Result<Arc<Plugin>> {
+		static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+		let n = NEXT.fetch_add(1, Ordering::Relaxed);
+		let path = std::env::temp_dir().join(format!("autorun-test-{}-{n}-{name}", std::process::id()));
+		std::fs::create_dir_all(&path)?;
+		self.dirs.borrow_mut().push(path.clone());
+
+		let toml = format!(
+			"[plugin]\nname = \"{name}\"\nauthor = \"test\"\nversion = \"0.1.0\"\ndescription = \"test\"\nlanguage = \"lua\"\n{config}\n"
+		);
+		std::fs::write(path.join("plugin.toml"), toml)?;
+
+		for (file, content) in files {
+			let file = path.join(file);
+			std::fs::create_dir_all(file.parent().unwrap())?;
+			std::fs::write(file, content)?;
+		}
+
+		let plugin = Plugin::from_dir(Dir::open_ambient_dir(&path, ambient_authority())?)?;
+		let (lua, state) = (self.state.lua(), self.state.state);
+		let plugin = self.env.add_plugin(lua, state, plugin)?;
+		self.env.run_entrypoints(lua, state, &plugin)?;
+
+		Ok(plugin)
+	}
+
+	/// Evaluates the comma separated expressions in `code` in the globals of the state, see [`State::results`].
+	pub fn results(&self, code: &str) -> String {
+		self.state.results(code)
+	}
+}
+
+impl Drop for Env {
+	fn drop(&mut self) {
+		lifecycle::close(self.state.lua(), self.state.state);
+
+		for dir in self.dirs.take() {
+			let _ = std::fs::remove_dir_all(dir);
+		}
+	}
+}
@@ -0,0 +1,214 @@
+//! Timers scheduled from Lua, driven by the game thread tick in autorun-lib.
+//! Every timer keeps its callback in the registry of the state it was created in and remembers the plugin that
+//! created it, so they can all be cancelled when that plugin goes away. Timers run on the main thread of their state,
+//! as the coroutine that created one may be dead by the time it is due.
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use autorun_lua::{LuaApi, RawHandle};
+use autorun_types::{LuaState, Realm};
+
+use crate::{errors, lifecycle, profiler, watchdog};
+
+struct Timer {
+	id: u32,
+	realm: Realm,
+	/// Main thread of the state, see [`lifecycle::main_state`].
+	state: usize,
+	due: Instant,
+	interval: Option<Duration>,
+	callback: RawHandle,
+	owner: Option<String>,
+}
+
+struct Scheduler {
+	next_id: u32,
+	timers: Vec<Timer>,
+}
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler {
+	next_id: 1,
+	timers: Vec::new(),
+});
+
+/// Schedules `callback` to be called on the main thread of `state` after `delay`, and then every `delay` again if
+/// `repeat` is set. Returns the id of the timer which can be passed to [`cancel`].
+pub fn schedule(
+	lua: &LuaApi,
+	realm: Realm,
+	state: *mut LuaState,
+	delay: Duration,
+	repeat: bool,
+	callback: RawHandle,
+	owner: Option<String>,
+) -> u32 {
+	let state = lifecycle::main_state(lua, state);
+	let mut scheduler = SCHEDULER.lock().unwrap();
+
+	let id = scheduler.next_id;
+	scheduler.next_id = scheduler.next_id.wrapping_add(1).max(1);
+
+	scheduler.timers.push(Timer {
+		id,
+		realm,
+		state: state as usize,
+		due: Instant::now() + delay,
+		interval: repeat.then_some(delay),
+		callback,
+		owner,
+	});
+
+	id
+}
+
+/// Cancels a timer created from any thread of `state`, returning whether it existed.
+pub fn cancel(lua: &LuaApi, state: *mut LuaState, id: u32) -> bool {
+	let main = lifecycle::main_state(lua, state) as usize;
+	let timer = {
+		let mut scheduler = SCHEDULER.lock().unwrap();
+		let Some(pos) = scheduler.timers.iter().position(|t| t.id == id && t.state == main) else {
+			return false;
+		};
+
+		scheduler.timers.remove(pos)
+	};
+
+	let _ = timer.callback.free(lua, state);
+	true
+}
+
+/// Cancels every timer created by the given plugin in a realm.
+pub fn cancel_plugin(lua: &LuaApi, state: *mut LuaState, realm: Realm, plugin: &str) {
+	let cancelled = {
+		let mut scheduler = SCHEDULER.lock().unwrap();
+		let (cancelled, kept) = std::mem::take(&mut scheduler.timers)
+			.into_iter()
+			.partition(|t| t.realm == realm && t.owner.as_deref() == Some(plugin));
+
+		scheduler.timers = kept;
+		cancelled
+	};
+
+	for timer in cancelled {
+		let _ = timer.callback.free(lua, state);
+	}
+}
+
+/// Drops every timer of a realm whose state has been torn down, along with its registry.
+/// The lifecycle calls this for every state that goes away, so [`tick`] never runs a timer of a closed state.
+pub fn remove_realm(realm: Realm) {
+	SCHEDULER.lock().unwrap().timers.retain(|t| t.realm != realm);
+}
+
+/// Runs every timer that is due. Must be called on the game thread.
+pub fn tick(lua: &LuaApi) {
+	let now = Instant::now();
+	let due: Vec<u32> = {
+		let scheduler = SCHEDULER.lock().unwrap();
+		scheduler.timers.iter().filter(|t| t.due <= now).map(|t| t.id).collect()
+	};
+
+	for id in due {
+		// Look the timer up again, an earlier callback this tick may have cancelled it.
+		let (realm, state, callback, repeating, owner) = {
+			let mut scheduler = SCHEDULER.lock().unwrap();
+			let Some(pos) = scheduler.timers.iter().position(|t| t.id == id) else {
+				continue;
+			};
+
+			let timer = &mut scheduler.timers[pos];
+			let fired = (
+				timer.realm,
+				timer.state as *mut LuaState,
+				timer.callback,
+				timer.interval.is_some(),
+				timer.owner.clone(),
+			);
+			match timer.interval {
+				Some(interval) => timer.due = now + interval,
+				None => {
+					scheduler.timers.remove(pos);
+				}
+			}
+
+			fired
+		};
+
+		lua.raw.push(state, &callback);
+		let result = profiler::measure(realm, owner.as_deref(), "timer", || {
+			watchdog::run_plugin(lua, state, owner.as_deref(), || errors::pcall(lua, state, 0, 0))
+		});
+
+		if let Err(why) = result {
+			errors::report(lua, state, realm, owner, "timer", why);
+		}
+
+		if !repeating {
+			let _ = callback.free(lua, state);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autorun_test::State;
+
+	fn callback(state: &State, code: &str) -> RawHandle {
+		state.eval(code);
+		RawHandle::from_stack(&state.lua().raw, state.state).unwrap()
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn runs_timers_once_due() {
+		let _serial = autorun_test::serial();
+		let state = State::new();
+		let lua = state.lua();
+		state.run("fired = {}", 0);
+
+		let timeout = callback(&state, "return function() fired.timeout = (fired.timeout or 0) + 1 end");
+		schedule(lua, Realm::Menu, state.state, Duration::ZERO, false, timeout, None);
+		let interval = callback(&state, "return function() fired.interval = (fired.interval or 0) + 1 end");
+		schedule(lua, Realm::Menu, state.state, Duration::ZERO, true, interval, None);
+		let later = callback(&state, "return function() fired.later = true end");
+		schedule(lua, Realm::Menu, state.state, Duration::from_secs(3600), false, later, None);
+
+		tick(lua);
+		tick(lua);
+		assert_eq!(
+			state.results("fired.timeout, fired.interval, tostring(fired.later)"),
+			"1,2,nil"
+		);
+
+		remove_realm(Realm::Menu);
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn keeps_timers_of_coroutines_on_their_state() {
+		let _serial = autorun_test::serial();
+		let (state, other) = (State::new(), State::new());
+		let lua = state.lua();
+		assert!(lifecycle::open(lua, Realm::Menu, state.state));
+
+		state.eval("return coroutine.create(function() end)");
+		let thread = lua.raw.tothread(state.state, -1);
+		let before = state.registry_len();
+
+		let kept = callback(&state, "return function() fired = (fired or 0) + 1 end");
+		schedule(lua, Realm::Menu, thread, Duration::ZERO, false, kept, None);
+		let cancelled = callback(&state, "return function() fired = 'cancelled' end");
+		let id = schedule(lua, Realm::Menu, thread, Duration::ZERO, false, cancelled, None);
+
+		assert!(!cancel(lua, other.state, id));
+		assert!(cancel(lua, state.state, id));
+		assert!(!cancel(lua, state.state, id));
+
+		tick(lua);
+		assert_eq!(state.results("fired"), "1");
+		assert_eq!(state.registry_len(), before);
+
+		lifecycle::close(lua, state.state);
+	}
+}
@@ -0,0 +1,140 @@
+//! Watchdog stopping runaway Lua code, like a typo'd `while true do end` that would otherwise freeze the game.
+//! Calls made with a budget run under the [`count_hook`], raising an error once the budget is exceeded, which is caught
+//! like any other error. Compiled code doesn't run hooks, so the JIT is off during the call, and chunks of plugins with a
+//! budget are never compiled, see [`exclude_from_jit`]. Budgets of nested calls are ignored in favor of the outermost one.
+use std::ffi::c_int;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use autorun_lua::{LUAJIT_MODE_ALLFUNC, LUAJIT_MODE_OFF, LuaApi};
+use autorun_types::{Budget, LuaState};
+
+use crate::count_hook::{self, Listener};
+
+/// Number of instructions between two checks of the budget.
+const CHECK_INTERVAL: c_int = 1000;
+
+struct Watch {
+	started: Instant,
+	instructions: u64,
+	budget: Budget,
+}
+
+static WATCH: Mutex<Option<Watch>> = Mutex::new(None);
+
+/// Budgets given to plugins in their config, by plugin name.
+static PLUGIN_BUDGETS: Mutex<Vec<(String, Budget)>> = Mutex::new(Vec::new());
+
+/// Sets the budget of every call into a plugin made through [`run_plugin`].
+pub fn set_plugin_budget(plugin: &str, budget: Budget) {
+	let mut budgets = PLUGIN_BUDGETS.lock().unwrap();
+	budgets.retain(|(name, _)| name != plugin);
+
+	if budget.is_limited() {
+		budgets.push((plugin.to_owned(), budget));
+	}
+}
+
+fn plugin_budget(plugin: Option<&str>) -> Option<Budget> {
+	let plugin = plugin?;
+	let budgets = PLUGIN_BUDGETS.lock().unwrap();
+	budgets.iter().find(|(name, _)| name == plugin).map(|(_, budget)| *budget)
+}
+
+/// Runs `f` under the budget of `plugin`, if it has one.
+pub fn run_plugin<R>(lua: &LuaApi, state: *mut LuaState, plugin: Option<&str>, f: impl FnOnce() -> R) -> R {
+	run(lua, state, plugin_budget(plugin).as_ref(), f)
+}
+
+/// Keeps the JIT from ever compiling the chunk at `index` if it belongs to a plugin with a budget, or is loaded while
+/// one is enforced. Traces compiled while nothing is enforced would otherwise run past the hook later on.
+pub fn exclude_from_jit(lua: &LuaApi, state: *mut LuaState, plugin: Option<&str>, index: c_int) {
+	if plugin_budget(plugin).is_some() || WATCH.lock().unwrap().is_some() {
+		lua.raw.jit_setmode(state, index, LUAJIT_MODE_ALLFUNC | LUAJIT_MODE_OFF);
+	}
+}
+
+/// Runs `f`, which is expected to make a protected call, erroring inside of it once `budget` is exceeded.
+pub fn run<R>(lua: &LuaApi, state: *mut LuaState, budget: Option<&Budget>, f: impl FnOnce() -> R) -> R {
+	let Some(budget) = budget.filter(|budget| budget.is_limited()) else {
+		return f();
+	};
+
+	{
+		let mut watch = WATCH.lock().unwrap();
+		if watch.is_some() {
+			drop(watch);
+			return f();
+		}
+
+		*watch = Some(Watch {
+			started: Instant::now(),
+			instructions: 0,
+			budget: *budget,
+		});
+	}
+
+	count_hook::set(lua, state, Listener::Watchdog, Some(CHECK_INTERVAL));
+	let result = f();
+	count_hook::set(lua, state, Listener::Watchdog, None);
+
+	*WATCH.lock().unwrap() = None;
+	result
+}
+
+/// Called by the [`count_hook`] after `instructions` ran, erroring if the budget is exceeded.
+pub(crate) fn check(lua: &LuaApi, state: *mut LuaState, instructions: c_int) {
+	let exceeded = {
+		let mut watch = WATCH.lock().unwrap();
+		let Some(watch) = watch.as_mut() else {
+			return;
+		};
+
+		watch.instructions += instructions as u64;
+
+		let budget = watch.budget;
+		let elapsed = watch.started.elapsed();
+
+		let instructions = budget.instructions.filter(|&limit| watch.instructions > limit);
+		let time = budget.time_ms.filter(|&limit| elapsed > Duration::from_millis(limit));
+
+		match (instructions, time) {
+			(Some(limit), _) => Some(format!("Exceeded budget of {limit} instructions")),
+			(None, Some(limit)) => Some(format!("Exceeded budget of {limit}ms")),
+			(None, None) => None,
+		}
+	};
+
+	if let Some(message) = exceeded {
+		lua.error(state, message);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::errors;
+	use crate::testing::Env;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn stops_runaway_calls_of_plugins_with_a_budget() {
+		let env = Env::new();
+		let init = "
+			_G.during = jit.status()
+			Autorun.on('spin', function() while true do end end, 1)
+			Autorun.on('spin', function() _G.after = true end)
+		";
+		env.plugin("runaway", "budget_instructions = 100000", &[("src/menu/init.lua", init)])
+			.unwrap();
+		env.env.trigger(env.state.lua(), env.state.state, "spin", (), |_| ());
+
+		let report = errors::recent().pop().unwrap();
+		assert_eq!(report.message, "Exceeded budget of 100000 instructions");
+		assert_eq!(report.site, "'spin' listener");
+		assert_eq!(
+			env.results("tostring(during), tostring(after), tostring(jit.status())"),
+			"false,true,true"
+		);
+		assert!(env.state.lua().raw.gethook(env.state.state).is_null());
+	}
+}
@@ -0,0 +1,52 @@
+//! A small bounded pool of worker threads for blocking work like file io.
+//! Results that need to touch Lua should be sent back to the game thread with [`crate::lua_queue::push`].
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use autorun_log::*;
+
+/// Amount of threads in the pool.
+const WORKER_COUNT: usize = 2;
+
+/// Maximum amount of jobs waiting to be picked up before [`submit`] starts refusing new ones.
+const QUEUE_CAPACITY: usize = 64;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+static POOL: LazyLock<SyncSender<Job>> = LazyLock::new(|| {
+	let (sender, receiver) = sync_channel::<Job>(QUEUE_CAPACITY);
+	let receiver = Arc::new(Mutex::new(receiver));
+
+	for i in 0..WORKER_COUNT {
+		let receiver = receiver.clone();
+		let spawned = std::thread::Builder::new()
+			.name(format!("autorun-worker-{i}"))
+			.spawn(move || work(receiver));
+
+		if let Err(why) = spawned {
+			error!("Failed to spawn worker thread: {why}");
+		}
+	}
+
+	sender
+});
+
+fn work(receiver: Arc<Mutex<Receiver<Job>>>) {
+	loop {
+		let job = match receiver.lock().unwrap().recv() {
+			Ok(job) => job,
+			Err(_) => break,
+		};
+
+		job();
+	}
+}
+
+/// Queues a job on the worker pool, failing if the queue is full.
+pub fn submit(job: impl FnOnce() + Send + 'static) -> anyhow::Result<()> {
+	match POOL.try_send(Box::new(job)) {
+		Ok(()) => Ok(()),
+		Err(TrySendError::Full(_)) => anyhow::bail!("Too many pending async operations, try again later"),
+		Err(TrySendError::Disconnected(_)) => anyhow::bail!("Worker pool is not running"),
+	}
+}
@@ -90,6 +90,7 @@ pub fn get_state(realm: autorun_types::Realm) -> Result<Option<*mut LuaState>, G
 		match realm {
 			autorun_types::Realm::Client => STATE_CLIENT,
 			autorun_types::Realm::Menu => STATE_MENU,
+			autorun_types::Realm::Server => STATE_SERVER,
 		},
 	);
 
@@ -5,7 +5,18 @@ pub enum Message {
 	Ping,
 	Pong,
 	Print(String),
-	RunCode(autorun_types::Realm, String),
+	/// Runs code in a realm, stopping it with an error once it exceeds the budget.
+	RunCode(autorun_types::Realm, String, autorun_types::Budget),
 	Shutdown,
 	SetWorkspacePath(String),
+	/// Asks for the most recent Lua errors, answered with [`Message::Errors`].
+	GetErrors,
+	Errors(Vec<autorun_types::ErrorReport>),
+	/// Asks for the time spent in each plugin, answered with [`Message::Profile`].
+	GetProfile,
+	Profile(Vec<autorun_types::ProfileEntry>),
+	StartSampling(autorun_types::Realm, autorun_types::SamplingOptions),
+	/// Stops sampling a realm, answered with [`Message::Samples`] holding folded stacks.
+	StopSampling(autorun_types::Realm),
+	Samples(autorun_types::Realm, String),
 }
@@ -0,0 +1,153 @@
+use syn::ext::IdentExt;
+
+/// `#[lua(...)]` attributes of a struct or enum.
+#[derive(Default)]
+pub struct ContainerAttrs {
+	pub rename_all: Option<Case>,
+	pub tag: Option<String>,
+}
+
+impl ContainerAttrs {
+	pub fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+		let mut out = Self::default();
+
+		for attr in attrs.iter().filter(|attr| attr.path().is_ident("lua")) {
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("rename_all") {
+					let case: syn::LitStr = meta.value()?.parse()?;
+					out.rename_all = Some(Case::parse(&case)?);
+				} else if meta.path.is_ident("tag") {
+					let tag: syn::LitStr = meta.value()?.parse()?;
+					out.tag = Some(tag.value());
+				} else {
+					return Err(meta.error("expected `rename_all` or `tag`"));
+				}
+
+				Ok(())
+			})?;
+		}
+
+		Ok(out)
+	}
+}
+
+/// `#[lua(...)]` attributes of a field or variant.
+#[derive(Default)]
+pub struct ItemAttrs {
+	pub rename: Option<String>,
+}
+
+impl ItemAttrs {
+	pub fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+		let mut out = Self::default();
+
+		for attr in attrs.iter().filter(|attr| attr.path().is_ident("lua")) {
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("rename") {
+					let name: syn::LitStr = meta.value()?.parse()?;
+					out.rename = Some(name.value());
+				} else {
+					return Err(meta.error("expected `rename`"));
+				}
+
+				Ok(())
+			})?;
+		}
+
+		Ok(out)
+	}
+
+	/// Name of a field or variant in Lua.
+	pub fn name(&self, ident: &syn::Ident, case: Option<Case>) -> String {
+		if let Some(rename) = &self.rename {
+			return rename.clone();
+		}
+
+		let ident = ident.unraw().to_string();
+		match case {
+			Some(case) => case.apply(&ident),
+			None => ident,
+		}
+	}
+}
+
+#[derive(Clone, Copy)]
+pub enum Case {
+	Lower,
+	Upper,
+	Camel,
+	Pascal,
+	Snake,
+	ScreamingSnake,
+}
+
+impl Case {
+	fn parse(lit: &syn::LitStr) -> syn::Result<Self> {
+		Ok(match lit.value().as_str() {
+			"lowercase" => Case::Lower,
+			"UPPERCASE" => Case::Upper,
+			"camelCase" => Case::Camel,
+			"PascalCase" => Case::Pascal,
+			"snake_case" => Case::Snake,
+			"SCREAMING_SNAKE_CASE" => Case::ScreamingSnake,
+			_ => {
+				return Err(syn::Error::new_spanned(
+					lit,
+					"expected one of lowercase, UPPERCASE, camelCase, PascalCase, snake_case or SCREAMING_SNAKE_CASE",
+				));
+			}
+		})
+	}
+
+	/// Converts a snake_case field or PascalCase variant name.
+	pub fn apply(self, ident: &str) -> String {
+		let words = words(ident);
+
+		match self {
+			Case::Lower => words.concat(),
+			Case::Upper => words.concat().to_uppercase(),
+			Case::Snake => words.join("_"),
+			Case::ScreamingSnake => words.join("_").to_uppercase(),
+			Case::Pascal => words.iter().map(|word| capitalize(word)).collect(),
+			Case::Camel => {
+				let mut words = words.iter();
+				let first = words.next().cloned().unwrap_or_default();
+				words.fold(first, |acc, word| acc + &capitalize(word))
+			}
+		}
+	}
+}
+
+/// Lowercase words of an identifier, split at underscores and uppercase letters.
+fn words(ident: &str) -> Vec<String> {
+	let mut words = Vec::new();
+	let mut word = String::new();
+
+	for c in ident.chars() {
+		if c == '_' || (c.is_uppercase() && !word.is_empty()) {
+			if !word.is_empty() {
+				words.push(std::mem::take(&mut word));
+			}
+
+			if c == '_' {
+				continue;
+			}
+		}
+
+		word.extend(c.to_lowercase());
+	}
+
+	if !word.is_empty() {
+		words.push(word);
+	}
+
+	words
+}
+
+fn capitalize(word: &str) -> String {
+	let mut chars = word.chars();
+	match chars.next() {
+		Some(first) => first.to_uppercase().chain(chars).collect(),
+		None => String::new(),
+	}
+}
@@ -0,0 +1,305 @@
+//! Derive macros for autorun-lua, re-exported by it with the `derive` feature.
+//!
+//! Structs with named fields become tables keyed by field name. Enums whose variants are all units become strings,
+//! others become tables naming their variant in a tag field, `type` unless set with `#[lua(tag = "...")]`, along with
+//! the fields of struct variants or the `value` of newtype variants.
+//!
+//! Names can be changed with `#[lua(rename = "...")]` on fields and variants, or `#[lua(rename_all = "...")]` on the
+//! type itself, which applies to the fields of a struct and the variants of an enum.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Data, DeriveInput, Fields, parse_macro_input, parse_quote};
+
+mod attrs;
+use attrs::{ContainerAttrs, ItemAttrs};
+
+const DEFAULT_TAG: &str = "type";
+
+/// Field of newtype variants in their table.
+const NEWTYPE_FIELD: &str = "value";
+
+#[proc_macro_derive(IntoLua, attributes(lua))]
+pub fn derive_into_lua(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	into_lua(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(TryFromLua, attributes(lua))]
+pub fn derive_try_from_lua(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	try_from_lua(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+struct Field {
+	ident: syn::Ident,
+	name: String,
+}
+
+struct Variant {
+	ident: syn::Ident,
+	name: String,
+	kind: VariantKind,
+}
+
+enum VariantKind {
+	Unit,
+	Struct(Vec<Field>),
+	Newtype,
+}
+
+enum Shape {
+	Struct(Vec<Field>),
+	Strings(Vec<Variant>),
+	Tagged { tag: String, variants: Vec<Variant> },
+}
+
+impl Shape {
+	fn of(input: &DeriveInput) -> syn::Result<Self> {
+		let attrs = ContainerAttrs::parse(&input.attrs)?;
+
+		match &input.data {
+			Data::Struct(data) => match &data.fields {
+				Fields::Named(fields) => Ok(Shape::Struct(named_fields(fields, attrs.rename_all)?)),
+				_ => Err(syn::Error::new_spanned(
+					&input.ident,
+					"only structs with named fields are supported",
+				)),
+			},
+
+			Data::Enum(data) => {
+				if data.variants.is_empty() {
+					return Err(syn::Error::new_spanned(
+						&input.ident,
+						"enums without variants are not supported",
+					));
+				}
+
+				let variants = data
+					.variants
+					.iter()
+					.map(|variant| {
+						let kind = match &variant.fields {
+							Fields::Unit => VariantKind::Unit,
+							Fields::Named(fields) => VariantKind::Struct(named_fields(fields, None)?),
+							Fields::Unnamed(fields) if fields.unnamed.len() == 1 => VariantKind::Newtype,
+							Fields::Unnamed(_) => {
+								return Err(syn::Error::new_spanned(
+									variant,
+									"only tuple variants with a single field are supported",
+								));
+							}
+						};
+
+						Ok(Variant {
+							ident: variant.ident.clone(),
+							name: ItemAttrs::parse(&variant.attrs)?.name(&variant.ident, attrs.rename_all),
+							kind,
+						})
+					})
+					.collect::<syn::Result<Vec<_>>>()?;
+
+				let all_units = variants.iter().all(|v| matches!(v.kind, VariantKind::Unit));
+				match attrs.tag {
+					None if all_units => Ok(Shape::Strings(variants)),
+					tag => Ok(Shape::Tagged {
+						tag: tag.unwrap_or_else(|| DEFAULT_TAG.to_owned()),
+						variants,
+					}),
+				}
+			}
+
+			Data::Union(_) => Err(syn::Error::new_spanned(&input.ident, "unions are not supported")),
+		}
+	}
+}
+
+fn named_fields(fields: &syn::FieldsNamed, case: Option<attrs::Case>) -> syn::Result<Vec<Field>> {
+	fields
+		.named
+		.iter()
+		.map(|field| {
+			let ident = field.ident.clone().expect("named field");
+			let name = ItemAttrs::parse(&field.attrs)?.name(&ident, case);
+			Ok(Field { ident, name })
+		})
+		.collect()
+}
+
+/// Binding of a field when matching on a variant, so it can't shadow anything the generated code uses.
+fn binding(ident: &syn::Ident) -> syn::Ident {
+	format_ident!("__field_{}", ident.unraw())
+}
+
+/// Requires every type parameter to implement `bound`.
+fn add_bounds(generics: &mut syn::Generics, bound: syn::Path) {
+	let params: Vec<syn::Ident> = generics.type_params().map(|param| param.ident.clone()).collect();
+	let where_clause = generics.make_where_clause();
+
+	for param in params {
+		where_clause.predicates.push(parse_quote!(#param: #bound));
+	}
+}
+
+fn into_lua(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+	let shape = Shape::of(&input)?;
+	add_bounds(&mut input.generics, parse_quote!(::autorun_lua::IntoLua));
+
+	let body = match shape {
+		Shape::Struct(fields) => {
+			let len = fields.len() as i32;
+			let set_fields = fields
+				.iter()
+				.map(|Field { ident, name }| quote!(::autorun_lua::derive::set_field(lua, state, #name, self.#ident);));
+
+			quote! {
+				lua.createtable(state, 0, #len);
+				#(#set_fields)*
+			}
+		}
+
+		Shape::Strings(variants) => {
+			let arms = variants
+				.iter()
+				.map(|Variant { ident, name, .. }| quote!(Self::#ident => #name,));
+
+			quote! {
+				let name = match self {
+					#(#arms)*
+				};
+
+				lua.push(state, name);
+			}
+		}
+
+		Shape::Tagged { tag, variants } => {
+			let arms = variants.iter().map(|Variant { ident, name, kind }| match kind {
+				VariantKind::Unit => quote! {
+					Self::#ident => {
+						lua.createtable(state, 0, 1);
+						::autorun_lua::derive::set_field(lua, state, #tag, #name);
+					}
+				},
+
+				VariantKind::Struct(fields) => {
+					let len = fields.len() as i32 + 1;
+					let idents = fields.iter().map(|field| &field.ident);
+					let bindings: Vec<_> = fields.iter().map(|field| binding(&field.ident)).collect();
+					let names = fields.iter().map(|field| &field.name);
+
+					quote! {
+						Self::#ident { #(#idents: #bindings),* } => {
+							lua.createtable(state, 0, #len);
+							::autorun_lua::derive::set_field(lua, state, #tag, #name);
+							#(::autorun_lua::derive::set_field(lua, state, #names, #bindings);)*
+						}
+					}
+				}
+
+				VariantKind::Newtype => quote! {
+					Self::#ident(value) => {
+						lua.createtable(state, 0, 2);
+						::autorun_lua::derive::set_field(lua, state, #tag, #name);
+						::autorun_lua::derive::set_field(lua, state, #NEWTYPE_FIELD, value);
+					}
+				},
+			});
+
+			quote! {
+				match self {
+					#(#arms)*
+				}
+			}
+		}
+	};
+
+	let ident = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+	Ok(quote! {
+		impl #impl_generics ::autorun_lua::IntoLua for #ident #ty_generics #where_clause {
+			fn into_lua(self, lua: &::autorun_lua::RawLuaApi, state: *mut ::autorun_lua::LuaState) {
+				#body
+			}
+		}
+	})
+}
+
+fn try_from_lua(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+	let shape = Shape::of(&input)?;
+	add_bounds(&mut input.generics, parse_quote!(::autorun_lua::TryFromLua));
+
+	let get_fields = |fields: &[Field]| {
+		let fields = fields
+			.iter()
+			.map(|Field { ident, name }| quote!(#ident: ::autorun_lua::derive::get_field(lua, state, table, #name)?,));
+
+		quote!({ #(#fields)* })
+	};
+
+	let body = match shape {
+		Shape::Struct(fields) => {
+			let fields = get_fields(&fields);
+
+			quote! {
+				let table = ::autorun_lua::derive::check_table(lua, state, stack_idx)?;
+				Ok(Self #fields)
+			}
+		}
+
+		Shape::Strings(variants) => {
+			let arms = variants
+				.iter()
+				.map(|Variant { ident, name, .. }| quote!(#name => Ok(Self::#ident),));
+
+			quote! {
+				let name = lua.try_to::<::std::borrow::Cow<'_, str>>(state, stack_idx)?;
+				match name.as_ref() {
+					#(#arms)*
+					other => Err(::autorun_lua::LuaError::unknown_variant(other)),
+				}
+			}
+		}
+
+		Shape::Tagged { tag, variants } => {
+			let arms = variants.iter().map(|Variant { ident, name, kind }| match kind {
+				VariantKind::Unit => quote!(#name => Ok(Self::#ident),),
+
+				VariantKind::Struct(fields) => {
+					let fields = get_fields(fields);
+					quote!(#name => Ok(Self::#ident #fields),)
+				}
+
+				VariantKind::Newtype => quote! {
+					#name => Ok(Self::#ident(::autorun_lua::derive::get_field(lua, state, table, #NEWTYPE_FIELD)?)),
+				},
+			});
+
+			quote! {
+				let table = ::autorun_lua::derive::check_table(lua, state, stack_idx)?;
+				let tag = ::autorun_lua::derive::get_field::<::std::string::String>(lua, state, table, #tag)?;
+
+				match tag.as_str() {
+					#(#arms)*
+					other => Err(::autorun_lua::LuaError::unknown_variant(other).at(#tag)),
+				}
+			}
+		}
+	};
+
+	let ident = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+	Ok(quote! {
+		impl #impl_generics ::autorun_lua::TryFromLua for #ident #ty_generics #where_clause {
+			fn try_from_lua(
+				lua: &::autorun_lua::RawLuaApi,
+				state: *mut ::autorun_lua::LuaState,
+				stack_idx: i32,
+			) -> ::autorun_lua::LuaResult<Self> {
+				#body
+			}
+		}
+	})
+}
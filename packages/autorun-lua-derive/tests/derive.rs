@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use autorun_lua::{IntoLua, LuaError, LuaTypeId, TryFromLua};
+use autorun_test::State;
+
+#[derive(Debug, Clone, PartialEq, IntoLua, TryFromLua)]
+#[lua(rename_all = "camelCase")]
+struct Player {
+	display_name: String,
+	score: f64,
+	#[lua(rename = "isAdmin")]
+	admin: bool,
+	team: Option<Team>,
+}
+
+#[derive(Debug, Clone, PartialEq, IntoLua, TryFromLua)]
+#[lua(rename_all = "lowercase")]
+enum Team {
+	Red,
+	Blue,
+}
+
+#[derive(Debug, Clone, PartialEq, IntoLua, TryFromLua)]
+struct Lobby {
+	players: Vec<Player>,
+	settings: HashMap<String, f64>,
+	status: Status,
+}
+
+#[derive(Debug, Clone, PartialEq, IntoLua, TryFromLua)]
+#[lua(tag = "kind", rename_all = "snake_case")]
+enum Status {
+	Waiting,
+	InGame { map: String, round: i32 },
+	Closed(String),
+}
+
+/// Converts the single result of `code`.
+fn convert<T: TryFromLua>(state: &State, code: &str) -> Result<T, LuaError> {
+	state.eval(code);
+	let value = state.lua().raw.try_to::<T>(state.state, -1);
+	state.lua().raw.pop(state.state, 1);
+	value
+}
+
+fn lobby() -> Lobby {
+	Lobby {
+		players: vec![
+			Player {
+				display_name: "vurv".into(),
+				score: 12.0,
+				admin: true,
+				team: Some(Team::Red),
+			},
+			Player {
+				display_name: "guest".into(),
+				score: 0.0,
+				admin: false,
+				team: None,
+			},
+		],
+		settings: HashMap::from([("gravity".into(), 600.0), ("timescale".into(), 1.0)]),
+		status: Status::InGame {
+			map: "gm_construct".into(),
+			round: 3,
+		},
+	}
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn round_trips_through_lua_tables() {
+	let state = State::new();
+
+	state.set_global("lobby", lobby());
+	let checked = convert::<bool>(
+		&state,
+		"local p = lobby.players
+		return p[1].displayName == 'vurv' and p[1].isAdmin == true and p[1].team == 'red'
+			and p[2].team == nil and #p == 2
+			and lobby.settings.gravity == 600
+			and lobby.status.kind == 'in_game' and lobby.status.map == 'gm_construct' and lobby.status.round == 3",
+	);
+	assert!(checked.unwrap());
+
+	assert_eq!(convert::<Lobby>(&state, "return lobby").unwrap(), lobby());
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn converts_every_kind_of_variant() {
+	let state = State::new();
+
+	for status in [Status::Waiting, Status::Closed("maintenance".into())] {
+		state.set_global("status", status.clone());
+		assert_eq!(convert::<Status>(&state, "return status").unwrap(), status);
+	}
+
+	assert_eq!(convert::<String>(&state, "return status.value").unwrap(), "maintenance");
+	assert_eq!(convert::<Team>(&state, "return 'blue'").unwrap(), Team::Blue);
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn reports_where_conversions_fail() {
+	let state = State::new();
+
+	let patched = |patch: &str| {
+		state.set_global("lobby", lobby());
+		convert::<Lobby>(&state, &format!("{patch} return lobby")).unwrap_err()
+	};
+
+	let err = patched("lobby.players[2].displayName = 5");
+	assert!(matches!(
+		&err,
+		LuaError::TypeMismatch { expected: LuaTypeId::String, found: LuaTypeId::Number, path } if path == "players[2].displayName"
+	));
+
+	let err = patched("lobby.settings.gravity = 'low'");
+	assert!(matches!(&err, LuaError::TypeMismatch { path, .. } if path == "settings[gravity]"));
+
+	let err = patched("lobby.players[1].team = 'green'");
+	assert_eq!(err.to_string(), "Unknown Variant 'green' at players[1].team");
+
+	let err = convert::<Status>(&state, "return { kind = 'paused' }").unwrap_err();
+	assert!(matches!(&err, LuaError::UnknownVariant { found, path } if found == "paused" && path == "kind"));
+
+	let err = convert::<Player>(&state, "return 'vurv'").unwrap_err();
+	assert!(matches!(&err, LuaError::TypeMismatch { expected: LuaTypeId::Table, path, .. } if path.is_empty()));
+}
@@ -0,0 +1,27 @@
+//! Support for the code generated by `#[derive(IntoLua, TryFromLua)]`, which isn't meant to be used directly.
+use crate::{IntoLua, LuaError, LuaResult, LuaState, LuaTypeId, RawLuaApi, TryFromLua};
+
+/// Fails unless the value at `stack_idx` is a table, returning its absolute index.
+pub fn check_table(lua: &RawLuaApi, state: *mut LuaState, stack_idx: i32) -> LuaResult<i32> {
+	match lua.typeid(state, stack_idx) {
+		LuaTypeId::Table => Ok(lua.absindex(state, stack_idx)),
+		other => Err(LuaError::mismatch(LuaTypeId::Table, other)),
+	}
+}
+
+/// Sets `key` of the table at the top of the stack.
+pub fn set_field(lua: &RawLuaApi, state: *mut LuaState, key: &str, value: impl IntoLua) {
+	lua.push(state, key);
+	lua.push(state, value);
+	lua.rawset(state, -3);
+}
+
+/// Converts `key` of the table at the absolute index `table`, with errors pointing at the key.
+pub fn get_field<T: TryFromLua>(lua: &RawLuaApi, state: *mut LuaState, table: i32, key: &str) -> LuaResult<T> {
+	lua.push(state, key);
+	lua.rawget(state, table);
+	let value = lua.try_to::<T>(state, -1);
+	lua.pop(state, 1);
+
+	value.map_err(|why| why.at(key))
+}
@@ -23,3 +23,10 @@ pub fn get_api() -> Result<&'static LuaApi, libloading::Error> {
 
 	Ok(LUA_API.get().expect("Should be initialized"))
 }
+
+/// Makes [`get_api`] return `api` rather than loading lua_shared, for hosts that load Lua themselves such as tests.
+/// Returns `None` if the API was already set or loaded.
+pub fn set_api(api: LuaApi) -> Option<&'static LuaApi> {
+	LUA_API.set(api).ok()?;
+	LUA_API.get()
+}
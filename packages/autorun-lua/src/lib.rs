@@ -5,7 +5,7 @@ mod registry;
 pub use registry::*;
 
 mod interface;
-pub use interface::get_api;
+pub use interface::{get_api, set_api};
 
 mod lua;
 pub use lua::*;
@@ -13,6 +13,12 @@ pub use lua::*;
 pub mod types;
 pub use types::*;
 
+#[cfg(feature = "derive")]
+pub use autorun_lua_derive::{IntoLua, TryFromLua};
+
+#[doc(hidden)]
+pub mod derive;
+
 pub mod prelude {
 	pub use crate::lua::*;
 	pub use crate::types::*;
@@ -0,0 +1,241 @@
+//! Lua functions backed by Rust closures, taking their arguments through [`LuaArgument`], mostly by position.
+//! The closure is kept in a userdata upvalue of the function, and dropped along with it once Lua collects it.
+use core::ffi::c_int;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::{
+	LuaApi, LuaError, LuaFunction, LuaResult, LuaReturn, LuaState, LuaTable, LuaTypeId, OwnedHandle, RawLuaApi, TryFromLua,
+	upvalueindex,
+};
+
+/// An argument a [`LuaCallback`] can take, usually a [`TryFromLua`] type taken from the next position through
+/// [`take_positional`].
+///
+/// # Safety
+/// It must not borrow anything from the Lua stack, as the closure is free to keep it past the call. That's why
+/// `&str` and `&[u8]`, which convert to any lifetime, aren't arguments while `String` and [`LuaStr`] are.
+///
+/// ```compile_fail
+/// # fn f(lua: &'static autorun_lua::LuaApi, state: *mut autorun_lua::LuaState) {
+/// let kept = std::cell::RefCell::new(Vec::new());
+/// lua.create_function(state, move |s: &'static str| Ok::<_, String>(kept.borrow_mut().push(s)));
+/// # }
+/// ```
+pub unsafe trait LuaArgument: Sized + 'static {
+	/// Takes the argument from the Lua arguments after `index`, moving `index` past those it used.
+	/// Errors are raised as is.
+	fn take(lua: &RawLuaApi, state: *mut LuaState, index: &mut c_int) -> Result<Self, String>;
+}
+
+/// Takes the Lua argument at the next position, raising an error naming it if it fails to convert.
+pub fn take_positional<T: TryFromLua>(lua: &RawLuaApi, state: *mut LuaState, index: &mut c_int) -> Result<T, String> {
+	*index += 1;
+	lua.try_to::<T>(state, *index).map_err(|why| bad_argument(*index, why))
+}
+
+macro_rules! positional_arguments {
+	($($T:ty),*) => {
+		$(
+			unsafe impl LuaArgument for $T {
+				fn take(lua: &RawLuaApi, state: *mut LuaState, index: &mut c_int) -> Result<Self, String> {
+					take_positional(lua, state, index)
+				}
+			}
+		)*
+	};
+}
+
+positional_arguments!(f64, bool, i32, String, LuaStr, LuaTable, LuaFunction);
+
+unsafe impl<T: LuaArgument + TryFromLua> LuaArgument for Option<T> {
+	fn take(lua: &RawLuaApi, state: *mut LuaState, index: &mut c_int) -> Result<Self, String> {
+		take_positional(lua, state, index)
+	}
+}
+
+unsafe impl<T: LuaArgument + TryFromLua> LuaArgument for Vec<T> {
+	fn take(lua: &RawLuaApi, state: *mut LuaState, index: &mut c_int) -> Result<Self, String> {
+		take_positional(lua, state, index)
+	}
+}
+
+unsafe impl<K: LuaArgument + TryFromLua + Eq + Hash, V: LuaArgument + TryFromLua> LuaArgument for HashMap<K, V> {
+	fn take(lua: &RawLuaApi, state: *mut LuaState, index: &mut c_int) -> Result<Self, String> {
+		take_positional(lua, state, index)
+	}
+}
+
+/// A string argument borrowed from Lua rather than copied, derefing to `str`. The string is anchored in the registry for
+/// as long as this lives, as Lua never moves or changes strings, so keeping it past the call is fine. Like any other
+/// handle, it must not be used anymore once its state is closed.
+pub struct LuaStr {
+	_anchor: OwnedHandle,
+	str: *const str,
+}
+
+impl TryFromLua for LuaStr {
+	fn try_from_lua(lua: &RawLuaApi, state: *mut LuaState, stack_idx: i32) -> LuaResult<Self> {
+		let str = lua.try_to::<&str>(state, stack_idx)? as *const str;
+
+		lua.pushvalue(state, stack_idx);
+		let anchor = OwnedHandle::from_stack(lua, state).ok_or(LuaError::GenericFailure)?;
+		Ok(Self { _anchor: anchor, str })
+	}
+}
+
+impl std::ops::Deref for LuaStr {
+	type Target = str;
+
+	fn deref(&self) -> &str {
+		// SAFETY: The anchor keeps the string alive, and Lua strings are immutable.
+		unsafe { &*self.str }
+	}
+}
+
+impl std::fmt::Debug for LuaStr {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+/// The state a callback was called in, as an argument taking up none of the Lua ones.
+#[derive(Debug, Clone, Copy)]
+pub struct Caller(pub *mut LuaState);
+
+unsafe impl LuaArgument for Caller {
+	fn take(_lua: &RawLuaApi, state: *mut LuaState, _index: &mut c_int) -> Result<Self, String> {
+		Ok(Caller(state))
+	}
+}
+
+/// A closure callable from Lua with arguments `Args`, a tuple of [`LuaArgument`] types.
+/// Implemented for every `Fn(A1, A2, ...) -> Result<R, E>` where `R` is a [`LuaReturn`], like a value or a tuple of them.
+pub trait LuaCallback<Args>: 'static {
+	/// Calls the closure with the arguments on the stack, returning how many values it pushed or an error message.
+	fn call(&self, lua: &RawLuaApi, state: *mut LuaState) -> Result<c_int, String>;
+}
+
+/// The API functions were created with, which has to outlive them.
+static API: AtomicPtr<RawLuaApi> = AtomicPtr::new(std::ptr::null_mut());
+
+fn api() -> &'static RawLuaApi {
+	// SAFETY: Only ever set from a 'static reference, before any function that could call this exists.
+	unsafe { &*API.load(Ordering::Acquire) }
+}
+
+extern "C-unwind" fn call<F: LuaCallback<Args>, Args>(state: *mut LuaState) -> c_int {
+	let lua = api();
+
+	let result = {
+		// SAFETY: The upvalue is only ever set by create_function, to an F living as long as the function.
+		let func = unsafe { &*(lua.touserdata(state, upvalueindex(1)) as *const F) };
+
+		match std::panic::catch_unwind(AssertUnwindSafe(|| func.call(lua, state))) {
+			Ok(result) => result,
+			Err(panic) => Err(panic_message(&*panic)),
+		}
+	};
+
+	// Nothing needing a drop can be left around, as raising the error skips the rest of this frame.
+	match result {
+		Ok(nresults) => nresults,
+		Err(why) => {
+			lua.push(state, why);
+			lua.error(state);
+		}
+	}
+}
+
+/// `__gc` of the userdata holding the closure.
+extern "C-unwind" fn collect<F>(state: *mut LuaState) -> c_int {
+	let func = api().touserdata(state, 1) as *mut F;
+	unsafe { std::ptr::drop_in_place(func) };
+	0
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+	let message = panic
+		.downcast_ref::<&str>()
+		.copied()
+		.or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+		.unwrap_or("unknown panic");
+
+	format!("Rust panic: {message}")
+}
+
+/// Error for an argument that failed to convert, like `luaL_argerror`.
+fn bad_argument(index: c_int, why: LuaError) -> String {
+	match why {
+		LuaError::TypeMismatch {
+			expected,
+			found: LuaTypeId::None,
+			path,
+		} if path.is_empty() => format!("bad argument #{index} ({expected} expected, got no value)"),
+		LuaError::TypeMismatch { expected, found, path } if path.is_empty() => {
+			format!("bad argument #{index} ({expected} expected, got {found})")
+		}
+		why => format!("bad argument #{index} ({why})"),
+	}
+}
+
+impl LuaApi {
+	/// Creates a Lua function calling `func`, with its arguments converted by position and its results pushed back.
+	/// Errors are raised in Lua, as are arguments failing to convert, naming the argument like builtin functions do.
+	///
+	/// ```ignore
+	/// let add = lua.create_function(state, |a: f64, b: Option<f64>| Ok::<_, String>(a + b.unwrap_or(1.0)));
+	/// let len = lua.create_function(state, |s: LuaStr| Ok::<_, String>(s.len() as f64));
+	/// ```
+	pub fn create_function<F: LuaCallback<Args>, Args>(&'static self, state: *mut LuaState, func: F) -> LuaFunction {
+		API.store(&self.raw as *const RawLuaApi as *mut RawLuaApi, Ordering::Release);
+		self.raw.newuserdata(state, func);
+
+		self.raw.createtable(state, 0, 1);
+		self.raw.push(state, c"__gc");
+		self.raw.pushcfunction(state, collect::<F>);
+		self.raw.rawset(state, -3);
+		self.raw.setmetatable(state, -2);
+
+		self.raw.pushcclosure(state, call::<F, Args>, 1);
+		let handle = OwnedHandle::from_stack(&self.raw, state).expect("Failed to allocate registry value");
+		LuaFunction::from_raw(handle)
+	}
+}
+
+macro_rules! impl_lua_callback {
+	($($A:ident),*) => {
+		impl<F, R, E, $($A),*> LuaCallback<($($A,)*)> for F
+		where
+			F: Fn($($A),*) -> Result<R, E> + 'static,
+			R: LuaReturn,
+			E: std::fmt::Display,
+			$($A: LuaArgument,)*
+		{
+			#[allow(non_snake_case, unused_mut, unused_variables)]
+			fn call(&self, lua: &RawLuaApi, state: *mut LuaState) -> Result<c_int, String> {
+				let mut index = 0;
+				$(
+					let $A = $A::take(lua, state, &mut index)?;
+				)*
+
+				match self($($A),*) {
+					Ok(results) => Ok(results.into_lua_return(lua, state)),
+					Err(why) => Err(why.to_string()),
+				}
+			}
+		}
+	};
+}
+
+impl_lua_callback!();
+impl_lua_callback!(A1);
+impl_lua_callback!(A1, A2);
+impl_lua_callback!(A1, A2, A3);
+impl_lua_callback!(A1, A2, A3, A4);
+impl_lua_callback!(A1, A2, A3, A4, A5);
+impl_lua_callback!(A1, A2, A3, A4, A5, A6);
+impl_lua_callback!(A1, A2, A3, A4, A5, A6, A7);
+impl_lua_callback!(A1, A2, A3, A4, A5, A6, A7, A8);
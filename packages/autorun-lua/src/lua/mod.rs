@@ -7,6 +7,9 @@ pub use result::*;
 mod returns;
 pub use returns::*;
 
+mod callback;
+pub use callback::*;
+
 use crate::{IntoLua, LuaFunction, LuaTable, LuaValue, types::LuaState};
 use std::ffi::{CStr, c_int};
 
@@ -40,7 +43,16 @@ impl LuaApi {
 		let src = src.as_ref();
 		self.raw.loadbufferx(state, src, name, c"t")?;
 		let func = self.raw.try_to(state, -1)?;
-		self.raw.pop(state, -1);
+		self.raw.pop(state, 1);
+
+		Ok(func)
+	}
+
+	/// Loads a chunk of bytecode, as made by [`RawLuaApi::dump`]. Bytecode isn't verified, so it must be trusted.
+	pub fn load_bytecode(&self, state: *mut LuaState, bytecode: &[u8], name: &CStr) -> LuaResult<LuaFunction> {
+		self.raw.loadbufferx(state, bytecode, name, c"b")?;
+		let func = self.raw.try_to(state, -1)?;
+		self.raw.pop(state, 1);
 
 		Ok(func)
 	}
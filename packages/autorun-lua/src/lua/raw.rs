@@ -1,7 +1,7 @@
 use core::ffi::{c_char, c_double, c_int, c_uint, c_void};
 use std::ffi::CStr;
 
-use crate::{FromLua, IntoLua, LuaCFunction, LuaError, LuaResult, LuaState, LuaTypeId, TryIntoLua};
+use crate::{FromLua, IntoLua, LuaCFunction, LuaError, LuaResult, LuaState, LuaTypeId, LuaWriter, TryIntoLua};
 
 #[cfg(feature = "gmod")]
 const LUA_IDSIZE: usize = 128;
@@ -30,6 +30,11 @@ pub const ENVIRON_INDEX: c_int = -10001;
 pub const REGISTRY_INDEX: c_int = -10000;
 pub const LUA_MULTRET: c_int = -1;
 
+/// Pseudo-index of the upvalue `i` of the running C closure, like the `lua_upvalueindex` macro.
+pub const fn upvalueindex(i: c_int) -> c_int {
+	GLOBALS_INDEX - i
+}
+
 pub const LUA_OK: c_int = 0;
 pub const LUA_YIELD: c_int = 1;
 pub const LUA_ERRRUN: c_int = 2;
@@ -38,9 +43,27 @@ pub const LUA_ERRMEM: c_int = 4;
 pub const LUA_ERRGCMM: c_int = 5;
 pub const LUA_ERRERR: c_int = 6;
 
+pub const LUAJIT_MODE_ENGINE: c_int = 0;
+pub const LUAJIT_MODE_ALLFUNC: c_int = 3;
+pub const LUAJIT_MODE_OFF: c_int = 0x0000;
+pub const LUAJIT_MODE_ON: c_int = 0x0100;
+pub const LUAJIT_MODE_FLUSH: c_int = 0x0200;
+
+pub const LUA_MASKCALL: c_int = 1 << 0;
+pub const LUA_MASKRET: c_int = 1 << 1;
+pub const LUA_MASKLINE: c_int = 1 << 2;
+pub const LUA_MASKCOUNT: c_int = 1 << 3;
+
 pub const LUA_REFNIL: c_int = -1;
 pub const LUA_NOREF: c_int = -2;
 
+pub const LUA_GCSTOP: c_int = 0;
+pub const LUA_GCRESTART: c_int = 1;
+pub const LUA_GCCOLLECT: c_int = 2;
+pub const LUA_GCCOUNT: c_int = 3;
+pub const LUA_GCCOUNTB: c_int = 4;
+pub const LUA_GCSTEP: c_int = 5;
+
 macro_rules! define_lua_api {
     (
         $(
@@ -107,6 +130,8 @@ define_lua_api! {
 	pub fn rawset(state: *mut LuaState, index: c_int);
 	#[name = "lua_rawseti"]
 	pub fn rawseti(state: *mut LuaState, index: c_int, n: c_int);
+	#[name = "lua_objlen"]
+	pub fn objlen(state: *mut LuaState, index: c_int) -> usize;
 
 	#[name = "lua_gettable"]
 	pub fn gettable(state: *mut LuaState, index: c_int);
@@ -116,6 +141,8 @@ define_lua_api! {
 	pub fn getfield(state: *mut LuaState, index: c_int, k: *const c_char);
 	#[name = "lua_insert"]
 	pub fn insert(state: *mut LuaState, index: c_int);
+	#[name = "lua_next"]
+	pub fn next(state: *mut LuaState, index: c_int) -> c_int;
 
 	#[name = "luaL_loadbufferx"]
 	pub fn _loadbufferx(
@@ -125,12 +152,18 @@ define_lua_api! {
 		name: *const c_char,
 		mode: *const c_char,
 	) -> c_int;
+	#[name = "lua_close"]
+	pub fn close(state: *mut LuaState);
+	#[name = "lua_dump"]
+	fn _dump(state: *mut LuaState, writer: LuaWriter, data: *mut c_void) -> c_int;
 	#[name = "luaL_loadstring"]
 	pub fn _loadstring(state: *mut LuaState, str: *const c_char) -> c_int;
 	#[name = "luaL_checknumber"]
 	pub fn checknumber(state: *mut LuaState, index: c_int) -> c_double;
 	#[name = "luaL_checklstring"]
 	pub fn checklstring(state: *mut LuaState, index: c_int, len: *mut c_uint) -> *const c_char;
+	#[name = "luaL_traceback"]
+	pub fn traceback(state: *mut LuaState, state1: *mut LuaState, msg: *const c_char, level: c_int);
 
 	#[name = "lua_call"]
 	pub fn call(state: *mut LuaState, n_args: c_int, n_results: c_int);
@@ -148,6 +181,8 @@ define_lua_api! {
 	pub fn settop(state: *mut LuaState, index: c_int);
 	#[name = "lua_gettop"]
 	pub fn gettop(state: *mut LuaState) -> c_int;
+	#[name = "lua_checkstack"]
+	fn _checkstack(state: *mut LuaState, extra: c_int) -> c_int;
 	#[name = "lua_remove"]
 	pub fn remove(state: *mut LuaState, index: c_int);
 	#[name = "lua_status"]
@@ -224,6 +259,9 @@ define_lua_api! {
 
 	#[name = "lua_newuserdata"]
 	fn _newuserdata(state: *mut LuaState, size: usize) -> *mut c_void;
+
+	#[name = "luaJIT_setmode"]
+	pub fn jit_setmode(state: *mut LuaState, index: c_int, mode: c_int) -> c_int;
 }
 
 impl RawLuaApi {
@@ -253,6 +291,24 @@ impl RawLuaApi {
 		Some(debug_info)
 	}
 
+	/// Retrieves debug info for the function at `index` rather than a stack level.
+	/// `what` takes the same options as [`RawLuaApi::getinfo`], without the leading `>`.
+	pub fn getfuncinfo(&self, state: *mut LuaState, index: c_int, what: &CStr) -> Option<RawDebugInfo> {
+		let mut debug_info = unsafe { std::mem::zeroed::<RawDebugInfo>() };
+		let what = std::ffi::CString::new([b">", what.to_bytes()].concat()).ok()?;
+
+		self.pushvalue(state, index);
+		if self._getinfo(state, what.as_ptr(), &raw mut debug_info as _) == 0 {
+			return None;
+		}
+
+		Some(debug_info)
+	}
+
+	pub fn checkstack(&self, state: *mut LuaState, extra: c_int) -> bool {
+		self._checkstack(state, extra) != 0
+	}
+
 	pub fn pop(&self, state: *mut LuaState, n: c_int) {
 		self.settop(state, -n - 1);
 	}
@@ -322,6 +378,15 @@ impl RawLuaApi {
 		self._isnumber(state, index) != 0
 	}
 
+	/// Turns a stack index relative to the top into one that stays valid as values are pushed. Pseudo-indices are kept.
+	pub fn absindex(&self, state: *mut LuaState, index: c_int) -> c_int {
+		if index < 0 && index > REGISTRY_INDEX {
+			self.gettop(state) + index + 1
+		} else {
+			index
+		}
+	}
+
 	pub fn isstring(&self, state: *mut LuaState, index: c_int) -> bool {
 		self._isstring(state, index) != 0
 	}
@@ -382,6 +447,21 @@ impl RawLuaApi {
 		}
 	}
 
+	/// Dumps the Lua function on top of the stack as bytecode, leaving it there.
+	pub fn dump(&self, state: *mut LuaState) -> LuaResult<Vec<u8>> {
+		extern "C-unwind" fn writer(_state: *mut LuaState, p: *const c_void, size: usize, data: *mut c_void) -> c_int {
+			let bytecode = unsafe { &mut *(data as *mut Vec<u8>) };
+			bytecode.extend_from_slice(unsafe { std::slice::from_raw_parts(p as *const u8, size) });
+			0
+		}
+
+		let mut bytecode = Vec::new();
+		match self._dump(state, writer, &raw mut bytecode as _) {
+			0 => Ok(bytecode),
+			_ => Err(LuaError::Runtime("Only Lua functions can be dumped".to_owned())),
+		}
+	}
+
 	pub fn pcall(&self, state: *mut LuaState, n_args: c_int, n_results: c_int, err_func: c_int) -> LuaResult<()> {
 		match self._pcall(state, n_args, n_results, err_func) {
 			LUA_OK | LUA_YIELD => Ok(()),
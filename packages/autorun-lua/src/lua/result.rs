@@ -6,6 +6,13 @@ pub enum LuaError {
 	TypeMismatch {
 		expected: crate::LuaTypeId,
 		found: crate::LuaTypeId,
+		/// Where in the converted value it happened, like `players[2].name`. Empty for the value itself.
+		path: String,
+	},
+	/// A string naming an enum variant that doesn't exist.
+	UnknownVariant {
+		found: String,
+		path: String,
 	},
 	InvalidReference,
 	GenericFailure,
@@ -13,7 +20,31 @@ pub enum LuaError {
 
 impl LuaError {
 	pub fn mismatch(expected: crate::LuaTypeId, found: crate::LuaTypeId) -> Self {
-		LuaError::TypeMismatch { expected, found }
+		LuaError::TypeMismatch {
+			expected,
+			found,
+			path: String::new(),
+		}
+	}
+
+	pub fn unknown_variant(found: impl Into<String>) -> Self {
+		LuaError::UnknownVariant {
+			found: found.into(),
+			path: String::new(),
+		}
+	}
+
+	/// Marks the error as coming from inside the value being converted, at a field name or an index like `[2]`.
+	pub fn at(mut self, segment: impl core::fmt::Display) -> Self {
+		if let LuaError::TypeMismatch { path, .. } | LuaError::UnknownVariant { path, .. } = &mut self {
+			*path = match path.as_str() {
+				"" => segment.to_string(),
+				rest if rest.starts_with('[') => format!("{segment}{rest}"),
+				rest => format!("{segment}.{rest}"),
+			};
+		}
+
+		self
 	}
 }
 
@@ -24,9 +55,16 @@ impl core::fmt::Display for LuaError {
 			LuaError::Runtime(msg) => write!(f, "Runtime Error: {}", msg),
 			LuaError::GenericFailure => write!(f, "Generic Failure"),
 
-			LuaError::TypeMismatch { expected, found } => {
+			LuaError::TypeMismatch { expected, found, path } if path.is_empty() => {
 				write!(f, "Type Mismatch: expected {expected}, found {found}")
 			}
+
+			LuaError::TypeMismatch { expected, found, path } => {
+				write!(f, "Type Mismatch at {path}: expected {expected}, found {found}")
+			}
+
+			LuaError::UnknownVariant { found, path } if path.is_empty() => write!(f, "Unknown Variant '{found}'"),
+			LuaError::UnknownVariant { found, path } => write!(f, "Unknown Variant '{found}' at {path}"),
 		}
 	}
 }
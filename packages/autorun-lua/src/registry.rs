@@ -1,7 +1,13 @@
+use core::ffi::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::ThreadId;
+
 use crate::{IntoLua, LuaApi, LuaResult, LuaState, REGISTRY_INDEX, RawLuaApi};
 
 /// A handle to a value in the lua registry.
 /// Note this does not have any reference counting, hence can be cloned.
+/// See [`OwnedHandle`] for one that frees itself once dropped.
 #[repr(transparent)]
 #[derive(Debug, Clone, Copy)]
 pub struct RawHandle(i32);
@@ -34,3 +40,144 @@ impl IntoLua for &RawHandle {
 		lua.rawgeti(state, REGISTRY_INDEX, self.0);
 	}
 }
+
+/// A handle to a value in the lua registry, freed once the last clone of it is dropped.
+#[derive(Clone)]
+pub struct OwnedHandle(Arc<Slot>);
+
+struct Slot {
+	id: c_int,
+	owner: Arc<Owner>,
+}
+
+impl OwnedHandle {
+	/// Pops the value at the top of the stack and stores it in the registry.
+	pub fn from_stack(lua: &RawLuaApi, state: *mut LuaState) -> Option<Self> {
+		if lua.gettop(state) < 1 {
+			return None;
+		}
+
+		let owner = Owner::of(lua, state);
+		let id = lua.reference(state)?;
+		Some(Self(Arc::new(Slot { id, owner })))
+	}
+
+	pub fn id(&self) -> i32 {
+		self.0.id
+	}
+}
+
+impl std::fmt::Debug for OwnedHandle {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_tuple("OwnedHandle").field(&self.0.id).finish()
+	}
+}
+
+impl Drop for Slot {
+	fn drop(&mut self) {
+		self.owner.release([self.id]);
+	}
+}
+
+impl IntoLua for &OwnedHandle {
+	fn into_lua(self, lua: &RawLuaApi, state: *mut LuaState) {
+		lua.rawgeti(state, REGISTRY_INDEX, self.0.id);
+	}
+}
+
+/// The registry of a state, shared by every owned handle into it so they can be freed outside of a call.
+struct Owner {
+	/// Address of the registry table, the same for every thread of the state.
+	registry: usize,
+	/// Thread references are freed through. Anchored in the registry when it isn't the main thread, so it can't be collected.
+	thread: usize,
+	anchor: Option<c_int>,
+	/// The game thread the state runs on. Handles dropped elsewhere are freed the next time one is dropped there.
+	thread_id: ThreadId,
+	pending: Mutex<Vec<c_int>>,
+	open: AtomicBool,
+	unreference: extern "C-unwind" fn(state: *mut LuaState, t: c_int, r: c_int),
+}
+
+static OWNERS: Mutex<Vec<Weak<Owner>>> = Mutex::new(Vec::new());
+
+impl Owner {
+	fn of(lua: &RawLuaApi, state: *mut LuaState) -> Arc<Self> {
+		let registry = lua.topointer(state, REGISTRY_INDEX) as usize;
+
+		let mut owners = OWNERS.lock().unwrap();
+		owners.retain(|owner| owner.strong_count() > 0);
+
+		if let Some(owner) = owners
+			.iter()
+			.filter_map(Weak::upgrade)
+			.find(|owner| owner.registry == registry)
+		{
+			return owner;
+		}
+
+		let anchor = match lua.pushthread(state) {
+			1 => {
+				lua.pop(state, 1);
+				None
+			}
+			_ => lua.reference(state),
+		};
+
+		let owner = Arc::new(Self {
+			registry,
+			thread: state as usize,
+			anchor,
+			thread_id: std::thread::current().id(),
+			pending: Mutex::new(Vec::new()),
+			open: AtomicBool::new(true),
+			unreference: lua._unreference,
+		});
+
+		owners.push(Arc::downgrade(&owner));
+		owner
+	}
+
+	fn release(&self, ids: impl IntoIterator<Item = c_int>) {
+		if !self.open.load(Ordering::Acquire) {
+			return;
+		}
+
+		let mut pending = self.pending.lock().unwrap();
+		if std::thread::current().id() != self.thread_id {
+			pending.extend(ids);
+			return;
+		}
+
+		let thread = self.thread as *mut LuaState;
+		for id in pending.drain(..).chain(ids) {
+			(self.unreference)(thread, REGISTRY_INDEX, id);
+		}
+	}
+}
+
+impl Drop for Owner {
+	fn drop(&mut self) {
+		// Whatever is still pending is leaked if this isn't the game thread, as nothing is left to free it later.
+		self.release(self.anchor);
+	}
+}
+
+/// Forgets about the registry of a state that is about to close, so owned handles still into it are left alone once
+/// dropped rather than freed through a closed state. Must be called before closing a state that may have some left.
+pub fn forget_registry(lua: &RawLuaApi, state: *mut LuaState) {
+	forget_registry_at(lua.topointer(state, REGISTRY_INDEX) as usize);
+}
+
+/// Same as [`forget_registry`], from the address of the registry of a state that may already be closed.
+pub fn forget_registry_at(registry: usize) {
+	let mut owners = OWNERS.lock().unwrap();
+	owners.retain(|owner| match owner.upgrade() {
+		Some(owner) if owner.registry == registry => {
+			owner.open.store(false, Ordering::Release);
+			false
+		}
+		Some(_) => true,
+		None => false,
+	});
+}
@@ -2,6 +2,7 @@ use core::ffi::{c_int, c_void};
 
 pub type LuaState = c_void;
 pub type LuaCFunction = extern "C-unwind" fn(state: *mut LuaState) -> c_int;
+pub type LuaWriter = extern "C-unwind" fn(state: *mut LuaState, p: *const c_void, size: usize, data: *mut c_void) -> c_int;
 pub type LuaLightUserdata = *mut c_void;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
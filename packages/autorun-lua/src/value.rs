@@ -1,4 +1,4 @@
-use crate::{LuaCFunction, LuaState, LuaTypeId, RawHandle, RawLuaApi};
+use crate::{LuaCFunction, LuaState, LuaTypeId, OwnedHandle, RawLuaApi};
 use core::ffi::c_void;
 
 mod from;
@@ -19,6 +19,11 @@ pub use userdata::*;
 mod function;
 pub use function::*;
 
+mod stack;
+pub use stack::*;
+
+mod collections;
+
 #[derive(Debug, Clone)]
 pub enum LuaValue<'a> {
 	Nil,
@@ -55,8 +60,8 @@ impl IntoLua for LuaValue<'_> {
 			LuaValue::Boolean(b) => lua.pushboolean(state, b),
 			LuaValue::Number(n) => lua.pushnumber(state, n),
 			LuaValue::String(s) => lua.pushlstring(state, s.as_ptr() as *const i8, s.len()),
-			LuaValue::Table(t) => t.into_lua(lua, state),
-			LuaValue::Function(f) => f.into_lua(lua, state),
+			LuaValue::Table(t) => (&t).into_lua(lua, state),
+			LuaValue::Function(f) => (&f).into_lua(lua, state),
 			LuaValue::CFunction(f) => lua.pushcfunction(state, f),
 			LuaValue::Userdata(u) => lua.pushlightuserdata(state, u),
 			LuaValue::LightUserdata(u) => lua.pushlightuserdata(state, u),
@@ -82,7 +87,7 @@ impl FromLua for LuaValue<'_> {
 
 			LuaTypeId::Table => {
 				lua.pushvalue(state, index);
-				let handle = RawHandle::from_stack(lua, state).expect("Failed to allocate registry value");
+				let handle = OwnedHandle::from_stack(lua, state).expect("Failed to allocate registry value");
 				LuaValue::Table(LuaTable::from_raw(handle))
 			}
 
@@ -92,7 +97,7 @@ impl FromLua for LuaValue<'_> {
 					LuaValue::CFunction(func)
 				} else {
 					lua.pushvalue(state, index);
-					let handle = RawHandle::from_stack(lua, state).expect("Failed to allocate registry value");
+					let handle = OwnedHandle::from_stack(lua, state).expect("Failed to allocate registry value");
 					LuaValue::Function(LuaFunction::from_raw(handle))
 				}
 			}
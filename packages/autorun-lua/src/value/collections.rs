@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::derive::check_table;
+use crate::{IntoLua, LuaResult, LuaState, LuaTypeId, RawLuaApi, TryFromLua};
+
+/// Vectors are pushed as sequences, starting at 1.
+impl<T: IntoLua> IntoLua for Vec<T> {
+	fn into_lua(self, lua: &RawLuaApi, state: *mut LuaState) {
+		lua.createtable(state, self.len() as _, 0);
+
+		for (i, value) in self.into_iter().enumerate() {
+			lua.push(state, value);
+			lua.rawseti(state, -2, i as i32 + 1);
+		}
+	}
+}
+
+impl<T: TryFromLua> TryFromLua for Vec<T> {
+	fn try_from_lua(lua: &RawLuaApi, state: *mut LuaState, stack_idx: i32) -> LuaResult<Self> {
+		let table = check_table(lua, state, stack_idx)?;
+		let len = lua.objlen(state, table);
+
+		let mut out = Vec::with_capacity(len);
+		for i in 1..=len as i32 {
+			lua.rawgeti(state, table, i);
+			let value = lua.try_to::<T>(state, -1);
+			lua.pop(state, 1);
+
+			out.push(value.map_err(|why| why.at(format_args!("[{i}]")))?);
+		}
+
+		Ok(out)
+	}
+}
+
+impl<K: IntoLua, V: IntoLua> IntoLua for HashMap<K, V> {
+	fn into_lua(self, lua: &RawLuaApi, state: *mut LuaState) {
+		lua.createtable(state, 0, self.len() as _);
+
+		for (key, value) in self {
+			lua.push(state, key);
+			lua.push(state, value);
+			lua.rawset(state, -3);
+		}
+	}
+}
+
+impl<K: TryFromLua + Eq + Hash, V: TryFromLua> TryFromLua for HashMap<K, V> {
+	fn try_from_lua(lua: &RawLuaApi, state: *mut LuaState, stack_idx: i32) -> LuaResult<Self> {
+		let table = check_table(lua, state, stack_idx)?;
+
+		let mut out = HashMap::new();
+		lua.pushnil(state);
+		while lua.next(state, table) != 0 {
+			let entry = lua
+				.try_to::<K>(state, -2)
+				.and_then(|key| Ok((key, lua.try_to::<V>(state, -1)?)))
+				.map_err(|why| why.at(format_args!("[{}]", key_name(lua, state, -2))));
+
+			match entry {
+				Ok((key, value)) => {
+					out.insert(key, value);
+					lua.pop(state, 1);
+				}
+				Err(why) => {
+					lua.pop(state, 2);
+					return Err(why);
+				}
+			}
+		}
+
+		Ok(out)
+	}
+}
+
+/// Describes a table key for error paths, without converting it in place and confusing `lua_next`.
+fn key_name(lua: &RawLuaApi, state: *mut LuaState, stack_idx: i32) -> String {
+	match lua.typeid(state, stack_idx) {
+		LuaTypeId::String | LuaTypeId::Number => {
+			lua.pushvalue(state, stack_idx);
+			let name = lua.tostring(state, -1).map(|name| name.to_string()).unwrap_or_default();
+			lua.pop(state, 1);
+			name
+		}
+		other => other.to_string(),
+	}
+}
@@ -18,10 +18,7 @@ impl TryFromLua for f64 {
 	fn try_from_lua(lua: &RawLuaApi, state: *mut LuaState, stack_idx: i32) -> LuaResult<Self> {
 		match lua.to(state, stack_idx) {
 			LuaValue::Number(n) => Ok(n),
-			other => Err(LuaError::TypeMismatch {
-				expected: LuaTypeId::Number,
-				found: other.typeid(),
-			}),
+			other => Err(LuaError::mismatch(LuaTypeId::Number, other.typeid())),
 		}
 	}
 }
@@ -30,10 +27,7 @@ impl TryFromLua for bool {
 	fn try_from_lua(lua: &RawLuaApi, state: *mut LuaState, stack_idx: i32) -> LuaResult<Self> {
 		match lua.to(state, stack_idx) {
 			LuaValue::Boolean(b) => Ok(b),
-			other => Err(LuaError::TypeMismatch {
-				expected: LuaTypeId::Boolean,
-				found: other.typeid(),
-			}),
+			other => Err(LuaError::mismatch(LuaTypeId::Boolean, other.typeid())),
 		}
 	}
 }
@@ -56,6 +50,13 @@ impl TryFromLua for &[u8] {
 	}
 }
 
+impl TryFromLua for &str {
+	fn try_from_lua(lua: &RawLuaApi, state: *mut LuaState, stack_idx: i32) -> LuaResult<Self> {
+		let bytes = lua.try_to::<&[u8]>(state, stack_idx)?;
+		std::str::from_utf8(bytes).map_err(|why| LuaError::Runtime(format!("String is not valid UTF-8: {why}")))
+	}
+}
+
 impl TryFromLua for String {
 	fn try_from_lua(lua: &RawLuaApi, state: *mut LuaState, stack_idx: i32) -> LuaResult<Self> {
 		match lua.to::<LuaValue>(state, stack_idx) {
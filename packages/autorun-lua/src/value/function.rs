@@ -1,12 +1,13 @@
-use crate::{IntoLua, LuaApi, LuaError, LuaResult, LuaState, LuaTypeId, LuaValue, RawHandle, RawLuaApi, TryFromLua};
+use crate::{IntoLua, LuaApi, LuaError, LuaResult, LuaState, LuaTypeId, LuaValue, OwnedHandle, RawLuaApi, TryFromLua};
 
-#[derive(Debug, Clone, Copy)]
+/// A function kept alive in the registry until the last clone of it is dropped.
+#[derive(Debug, Clone)]
 pub struct LuaFunction {
-	handle: RawHandle,
+	handle: OwnedHandle,
 }
 
 impl LuaFunction {
-	pub(crate) fn from_raw(handle: RawHandle) -> Self {
+	pub(crate) fn from_raw(handle: OwnedHandle) -> Self {
 		Self { handle }
 	}
 }
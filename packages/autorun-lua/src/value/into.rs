@@ -43,6 +43,12 @@ impl IntoLua for i32 {
 	}
 }
 
+impl IntoLua for u32 {
+	fn into_lua(self, lua: &RawLuaApi, state: *mut LuaState) {
+		lua.pushnumber(state, self as f64);
+	}
+}
+
 impl IntoLua for Vec<u8> {
 	fn into_lua(self, lua: &RawLuaApi, state: *mut LuaState) {
 		lua.pushlstring(state, self.as_ptr() as *const i8, self.len());
@@ -0,0 +1,39 @@
+use core::ffi::c_int;
+
+use crate::{IntoLua, LuaResult, LuaState, LuaTypeId, RawLuaApi, TryFromLua};
+
+/// A value borrowed from the stack, which unlike [`crate::LuaTable`] and [`crate::LuaFunction`] takes no registry slot.
+/// Only valid until its slot is popped, so it must not outlive the call it was taken in.
+#[derive(Debug, Clone, Copy)]
+pub struct StackRef {
+	index: c_int,
+	typeid: LuaTypeId,
+}
+
+impl StackRef {
+	/// Absolute index of the value on the stack.
+	pub fn index(&self) -> c_int {
+		self.index
+	}
+
+	pub fn typeid(&self) -> LuaTypeId {
+		self.typeid
+	}
+}
+
+impl TryFromLua for StackRef {
+	fn try_from_lua(lua: &RawLuaApi, state: *mut LuaState, index: i32) -> LuaResult<Self> {
+		let index = lua.absindex(state, index);
+
+		Ok(Self {
+			index,
+			typeid: lua.typeid(state, index),
+		})
+	}
+}
+
+impl IntoLua for StackRef {
+	fn into_lua(self, lua: &RawLuaApi, state: *mut LuaState) {
+		lua.pushvalue(state, self.index);
+	}
+}
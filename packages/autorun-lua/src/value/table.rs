@@ -1,14 +1,15 @@
 use crate::{
-	FromLua, Globals, IntoLua, LuaApi, LuaError, LuaResult, LuaState, LuaTypeId, LuaValue, RawHandle, RawLuaApi, TryFromLua,
+	FromLua, Globals, IntoLua, LuaApi, LuaError, LuaResult, LuaState, LuaTypeId, LuaValue, OwnedHandle, RawLuaApi, TryFromLua,
 };
 
-#[derive(Debug, Clone, Copy)]
+/// A table kept alive in the registry until the last clone of it is dropped.
+#[derive(Debug, Clone)]
 pub struct LuaTable {
-	handle: RawHandle,
+	handle: OwnedHandle,
 }
 
 impl LuaTable {
-	pub(crate) fn from_raw(handle: RawHandle) -> LuaTable {
+	pub(crate) fn from_raw(handle: OwnedHandle) -> LuaTable {
 		LuaTable { handle }
 	}
 }
@@ -16,13 +17,13 @@ impl LuaTable {
 impl LuaApi {
 	pub fn globals(&self, state: *mut LuaState) -> LuaTable {
 		self.raw.push(state, Globals);
-		let handle = RawHandle::from_stack(&self.raw, state).expect("Failed to allocate registry value");
+		let handle = OwnedHandle::from_stack(&self.raw, state).expect("Failed to allocate registry value");
 		LuaTable { handle }
 	}
 
 	pub fn table(&self, state: *mut LuaState) -> LuaTable {
 		self.raw.createtable(state, 0, 0);
-		let handle = RawHandle::from_stack(&self.raw, state).expect("Failed to allocate registry value");
+		let handle = OwnedHandle::from_stack(&self.raw, state).expect("Failed to allocate registry value");
 		LuaTable { handle }
 	}
 
@@ -0,0 +1,114 @@
+use std::rc::Rc;
+
+use autorun_lua::{Caller, LUA_GCCOLLECT, LuaStr, LuaTable, LuaValue};
+use autorun_test::State;
+
+fn eval_string(state: &State, code: &str) -> String {
+	state.eval(code);
+	let result = state.lua().raw.try_to::<String>(state.state, -1).unwrap();
+	state.lua().raw.pop(state.state, 1);
+	result
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn converts_arguments_and_results() {
+	let state = State::new();
+
+	let describe = state
+		.lua()
+		.create_function(state.state, |a: f64, b: String, t: Option<LuaTable>| -> Result<_, String> {
+			Ok((a * 2.0, format!("{b}!"), t.is_some()))
+		});
+	state.set_global("describe", &describe);
+
+	let results = state.lua().call(state.state, &describe, (1.5, "hi"));
+	assert!(matches!(
+		results.as_slice(),
+		[LuaValue::Number(3.0), LuaValue::String(b"hi!"), LuaValue::Boolean(false)]
+	));
+
+	let code = "local a, b, c = describe(2, 'x', {}) return table.concat({ a, b, tostring(c) }, ',')";
+	assert_eq!(eval_string(&state, code), "4,x!,true");
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn raises_errors_naming_the_argument() {
+	let state = State::new();
+
+	let lua = state.lua();
+	let f = lua.create_function(state.state, |_: f64, _: String| Ok::<_, String>(()));
+	state.set_global("f", &f);
+	let fail = lua.create_function(state.state, |reason: String| Err::<(), _>(format!("failed: {reason}")));
+	state.set_global("fail", &fail);
+	let panics = lua.create_function(state.state, || -> Result<(), String> { panic!("oops") });
+	state.set_global("panics", &panics);
+
+	let error = |code: &str| eval_string(&state, &format!("return select(2, pcall({code}))"));
+	assert_eq!(error("f, 'one', 'two'"), "bad argument #1 (Number expected, got String)");
+	assert_eq!(error("f, 1"), "bad argument #2 (String expected, got no value)");
+	assert_eq!(error("fail, 'nope'"), "failed: nope");
+	assert_eq!(error("panics"), "Rust panic: oops");
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn passes_the_calling_state_without_taking_an_argument() {
+	let state = State::new();
+
+	let main = state.state;
+	let f = state.lua().create_function(state.state, move |caller: Caller, n: f64| {
+		Ok::<_, String>((caller.0 == main, n))
+	});
+	state.set_global("f", &f);
+
+	assert_eq!(state.results("tostring((f(3))), select(2, f(3))"), "true,3");
+	assert_eq!(state.results("tostring(coroutine.wrap(f)(3))"), "false");
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn drops_the_closure_once_collected() {
+	let state = State::new();
+
+	let captured = Rc::new(());
+	let inner = captured.clone();
+	let f = state
+		.lua()
+		.create_function(state.state, move || Ok::<_, String>(Rc::strong_count(&inner) as f64));
+	assert_eq!(Rc::strong_count(&captured), 2);
+
+	drop(f);
+	state.lua().raw.gc(state.state, LUA_GCCOLLECT, 0);
+	assert_eq!(Rc::strong_count(&captured), 1);
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn keeps_borrowed_strings_alive_past_the_call() {
+	let state = State::new();
+
+	let kept = Rc::new(std::cell::RefCell::new(Vec::new()));
+	let inner = kept.clone();
+	let keep = state.lua().create_function(state.state, move |s: LuaStr, t: Option<LuaStr>| {
+		let len = s.len() + t.as_ref().map_or(0, |t| t.len());
+		inner.borrow_mut().push(s);
+		Ok::<_, String>(len as f64)
+	});
+	state.set_global("keep", &keep);
+
+	assert_eq!(state.results("keep('a' .. string.rep('b', 100)), keep('c', 'de')"), "101,3");
+	assert_eq!(
+		eval_string(&state, "return select(2, pcall(keep, '\\255'))")
+			.split(" (")
+			.next(),
+		Some("bad argument #1")
+	);
+
+	state.lua().raw.gc(state.state, LUA_GCCOLLECT, 0);
+	let kept = kept.borrow();
+	assert_eq!(kept[0].len(), 101);
+	assert!(kept[0].starts_with("abb") && kept[0].ends_with("bbb"));
+	assert_eq!(&*kept[1], "c");
+}
@@ -0,0 +1,75 @@
+use autorun_lua::{LuaFunction, LuaTable, LuaTypeId, LuaValue, StackRef, forget_registry};
+use autorun_test::State;
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn conversions_do_not_grow_the_registry() {
+	let state = State::new();
+
+	let (lua, l) = (state.lua(), state.state);
+	let code = c"return function() end";
+	lua.raw.loadstring(l, code.as_ptr()).unwrap();
+	lua.raw.createtable(l, 0, 0);
+
+	let before = state.registry_len();
+	for _ in 0..1000 {
+		let _ = lua.raw.to::<LuaValue>(l, -1);
+		let _ = lua.raw.try_to::<LuaTable>(l, -1).unwrap();
+		let _ = lua.raw.try_to::<LuaFunction>(l, -2).unwrap();
+		let _ = lua.globals(l);
+		let _ = lua.table(l);
+	}
+
+	assert_eq!(state.registry_len(), before);
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn clones_free_their_reference_once_all_are_dropped() {
+	let state = State::new();
+
+	let before = state.registry_len();
+	let table = state.lua().table(state.state);
+	let clone = table.clone();
+	assert_eq!(state.registry_len(), before + 1);
+
+	drop(table);
+	assert_eq!(state.registry_len(), before + 1);
+
+	state.lua().set(state.state, &clone, "key", 5.0);
+	assert_eq!(state.lua().get::<f64>(state.state, &clone, "key").unwrap(), 5.0);
+
+	drop(clone);
+	assert_eq!(state.registry_len(), before);
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn stack_refs_take_no_registry_slot() {
+	let state = State::new();
+
+	let (lua, l) = (state.lua(), state.state);
+	lua.raw.createtable(l, 0, 0);
+
+	let before = state.registry_len();
+	let table = lua.raw.try_to::<StackRef>(l, -1).unwrap();
+	assert_eq!(state.registry_len(), before);
+	assert_eq!(table.index(), lua.raw.gettop(l));
+	assert_eq!(table.typeid(), LuaTypeId::Table);
+
+	lua.raw.push(l, table);
+	assert!(lua.raw.rawequal(l, -1, -2));
+}
+
+#[test]
+#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+fn handles_into_forgotten_registries_are_left_alone() {
+	let state = State::new();
+
+	let table = state.lua().table(state.state);
+	let before = state.registry_len();
+
+	forget_registry(&state.lua().raw, state.state);
+	drop(table);
+	assert_eq!(state.registry_len(), before);
+}
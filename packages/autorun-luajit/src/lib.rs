@@ -1,5 +1,7 @@
 mod helpers;
+mod proto;
 mod types;
 
 pub use helpers::*;
+pub use proto::*;
 pub use types::*;
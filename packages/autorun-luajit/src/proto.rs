@@ -0,0 +1,357 @@
+// Redirecting Lua functions at the prototype level.
+//
+// Every closure of a prototype runs the bytecode stored inline after it, and takes its constants from the prototype
+// too. Overwriting the start of that bytecode with a stub, and pointing the prototype at the constants of the stub,
+// redirects all of its closures at once, including ones created later. The original code lives on in a relocated copy
+// of the prototype, which any closure with the same upvalues can be pointed at to run it.
+use crate::*;
+
+/// The parts of a prototype that get swapped out when patching it.
+#[derive(Debug, Clone)]
+struct Patch {
+	numparams: u8,
+	framesize: u8,
+	flags: u8,
+	k: MRef,
+	sizekgc: MSize,
+	sizekn: MSize,
+	code: Vec<BCIns>,
+}
+
+impl Patch {
+	fn read(proto: &GCProto, len: usize) -> Self {
+		let code = unsafe { std::slice::from_raw_parts(proto.bytecode(), len) }.to_vec();
+
+		Self {
+			numparams: proto.numparams,
+			framesize: proto.framesize,
+			flags: proto.flags,
+			k: proto.k,
+			sizekgc: proto.sizekgc,
+			sizekn: proto.sizekn,
+			code,
+		}
+	}
+
+	unsafe fn apply(&self, proto: *mut GCProto) {
+		unsafe {
+			let proto = &mut *proto;
+			proto.numparams = self.numparams;
+			proto.framesize = self.framesize;
+			proto.flags = self.flags;
+			proto.k = self.k;
+			proto.sizekgc = self.sizekgc;
+			proto.sizekn = self.sizekn;
+			std::ptr::copy_nonoverlapping(self.code.as_ptr(), proto.bytecode(), self.code.len());
+		}
+	}
+}
+
+/// Redirects every closure of a prototype to the code of a stub prototype.
+///
+/// The stub runs with the upvalues and environment of whichever closure was called, so it can't rely on either.
+/// Its constants are borrowed, so the stub must outlive the detour, as must the target while the detour is enabled.
+pub struct ProtoDetour {
+	target: *mut GCProto,
+	/// Relocated copy of the target, which the GC doesn't know about.
+	original: Box<[u64]>,
+	saved: Patch,
+	stub: Patch,
+	enabled: bool,
+}
+
+impl ProtoDetour {
+	/// Prepares a detour of `target`, leaving it untouched until enabled, except for JIT compilation being disabled
+	/// for it for good. Traces recorded before must be flushed first, as they could still run the original code.
+	///
+	/// # Safety
+	/// Both protos must be alive, and `target` must not be running on any stack.
+	pub unsafe fn new(target: *mut GCProto, stub: *const GCProto) -> anyhow::Result<Self> {
+		let (target_ref, stub_ref) = unsafe { (&mut *target, &*stub) };
+
+		if target_ref.trace != 0 {
+			anyhow::bail!("Function has JIT traces attached, flush them first.");
+		}
+
+		if stub_ref.sizebc > target_ref.sizebc {
+			anyhow::bail!(
+				"Function is too small to detour ({} instructions, at least {} are needed).",
+				target_ref.sizebc,
+				stub_ref.sizebc
+			);
+		}
+
+		// Traces would inline the function and skip the stub, so the function is never compiled again.
+		target_ref.flags |= PROTO_NOJIT;
+
+		let size = target_ref.sizept as usize;
+		let mut original = vec![0u64; size.div_ceil(size_of::<u64>())].into_boxed_slice();
+		unsafe { std::ptr::copy_nonoverlapping(target as *const u8, original.as_mut_ptr() as *mut u8, size) };
+
+		// Everything a proto points to is allocated inline with it, so the pointers just move along with the copy.
+		let copy = unsafe { &mut *(original.as_mut_ptr() as *mut GCProto) };
+		let start = target as u64;
+		let delta = (copy as *mut GCProto as u64).wrapping_sub(start);
+		for mref in [
+			&mut copy.k,
+			&mut copy.uv,
+			&mut copy.lineinfo,
+			&mut copy.uvinfo,
+			&mut copy.varinfo,
+		] {
+			if (start..start + size as u64).contains(&mref.ptr64) {
+				mref.ptr64 = mref.ptr64.wrapping_add(delta);
+			}
+		}
+
+		// A black object is never traversed by the GC again, and it can't be swept as it is in none of its lists.
+		copy.header.marked = LJ_GC_BLACK;
+		copy.header.nextgc = GCRef { gcptr64: 0 };
+
+		let len = stub_ref.sizebc as usize;
+		let saved = Patch::read(target_ref, len);
+		let mut patch = Patch::read(stub_ref, len);
+		patch.flags |= saved.flags;
+
+		Ok(Self {
+			target,
+			original,
+			saved,
+			stub: patch,
+			enabled: false,
+		})
+	}
+
+	pub fn target(&self) -> *mut GCProto {
+		self.target
+	}
+
+	/// The relocated copy still running the original code of the target.
+	pub fn original(&self) -> *const GCProto {
+		self.original.as_ptr() as *const GCProto
+	}
+
+	/// What to set the `pc` of a closure of the target to, for it to run the original code.
+	pub fn original_pc(&self) -> MRef {
+		MRef {
+			ptr64: unsafe { (*self.original()).bytecode() } as u64,
+		}
+	}
+
+	/// Collectable constants of the original code. The copy is invisible to the GC, so these need to be kept alive
+	/// by the caller for as long as the original may run.
+	pub fn constants(&self) -> Vec<TValue> {
+		let original = unsafe { &*self.original() };
+
+		(1..=original.sizekgc as isize)
+			.map(|i| {
+				let gcr = original.kgc(-i);
+				let gct = unsafe { (*gcr.as_ptr::<GCHeader>()).gct };
+				TValue::from_gcref(gcr, !(gct as u32))
+			})
+			.collect()
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled
+	}
+
+	/// # Safety
+	/// The target and stub must still be alive, and the target must not be running on any stack, including those of
+	/// coroutines other than the calling one.
+	pub unsafe fn enable(&mut self) {
+		if !self.enabled {
+			unsafe { self.stub.apply(self.target) };
+			self.enabled = true;
+		}
+	}
+
+	/// # Safety
+	/// The target must still be alive, and the stub must not be running on any stack.
+	pub unsafe fn disable(&mut self) {
+		if self.enabled {
+			unsafe { self.saved.apply(self.target) };
+			self.enabled = false;
+		}
+	}
+}
+
+/// Makes `closure` run `pc` with the environment and upvalues of `from`, sharing them rather than copying values.
+///
+/// # Safety
+/// Both closures must have the same amount of upvalues, and `closure` must have just been created, so that it is
+/// still white and needs no write barrier.
+pub unsafe fn retarget_closure(closure: &mut GCfuncL, from: &mut GCfuncL, pc: MRef) -> anyhow::Result<()> {
+	let nupvalues = from.header.nupvalues;
+	if closure.header.nupvalues != nupvalues {
+		anyhow::bail!("Closure has {} upvalues, expected {}.", closure.header.nupvalues, nupvalues);
+	}
+
+	closure.header.env = from.header.env;
+	closure.header.pc = pc;
+	unsafe { std::ptr::copy_nonoverlapping(from.uvptr_mut(), closure.uvptr_mut(), nupvalues as usize) };
+
+	Ok(())
+}
+
+/// Whether any closure of `proto` is running on the stack of the state.
+/// Only walks this one thread, so closures running in other coroutines, suspended or not, aren't seen.
+pub fn is_running(state: *mut LJState, proto: *const GCProto) -> bool {
+	Frame::walk_stack(state).iter().any(|frame| {
+		frame
+			.get_gc_func()
+			.ok()
+			.and_then(|func| func.as_l())
+			.is_some_and(|func| std::ptr::eq(func.proto_ptr(), proto))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autorun_lua::{GLOBALS_INDEX, LUAJIT_MODE_ENGINE, LUAJIT_MODE_FLUSH, LuaState};
+	use autorun_test::State;
+	use std::ffi::c_int;
+	use std::sync::atomic::{AtomicU64, Ordering};
+
+	const STUB: &str = "return function(...) return ({true})[1]()(...) end";
+
+	/// The LuaJIT state behind `state`.
+	fn lj<'a>(state: *mut LuaState) -> &'a mut LJState {
+		unsafe { &mut *(state as *mut LJState) }
+	}
+
+	/// The proto of the Lua function at `idx`.
+	fn proto(state: &State, idx: i32) -> *mut GCProto {
+		get_gcobj_mut::<GCfunc>(lj(state.state), idx)
+			.unwrap()
+			.as_l()
+			.unwrap()
+			.proto_ptr()
+	}
+
+	static ORIGINAL_PC: AtomicU64 = AtomicU64::new(0);
+
+	/// Returns a closure running the original code with the upvalues of the closure passed in.
+	extern "C-unwind" fn original_of(state: *mut LuaState) -> c_int {
+		let lua = autorun_test::lua();
+		let lj = lj(state);
+
+		lua.raw.getfield(state, GLOBALS_INDEX, c"factory".as_ptr());
+		lua.raw.call(state, 0, 1);
+
+		let from = get_gcobj_mut::<GCfunc>(lj, 1).unwrap().as_l_mut().unwrap() as *mut GCfuncL;
+		let closure = get_gcobj_mut::<GCfunc>(lj, -1).unwrap().as_l_mut().unwrap();
+		let pc = MRef {
+			ptr64: ORIGINAL_PC.load(Ordering::SeqCst),
+		};
+
+		unsafe { retarget_closure(closure, &mut *from, pc) }.unwrap();
+		1
+	}
+
+	/// Detours the proto of the function on top of the stack, routing calls through the global `hook`.
+	fn detour(state: &State) -> anyhow::Result<ProtoDetour> {
+		let lua = state.lua();
+		let target = proto(state, -1);
+
+		state.run(STUB, 1);
+		let stub = proto(state, -1);
+		state.pop_global("stub");
+
+		// The stub calls the first entry of its template table to get the function to tail call.
+		let template = unsafe { (*stub).kgc(-1) };
+		push_tvalue(lj(state.state), &TValue::from_gcref(template, LJ_TTAB));
+		state.run(
+			"return function() return hook(original_of(debug.getinfo(2, 'f').func)) end",
+			1,
+		);
+		lua.raw.rawseti(state.state, -2, 1);
+		lua.raw.pop(state.state, 1);
+
+		let detour = unsafe { ProtoDetour::new(target, stub)? };
+		ORIGINAL_PC.store(detour.original_pc().ptr64, Ordering::SeqCst);
+
+		lua.raw.createtable(state.state, 0, 0);
+		for (i, constant) in detour.constants().iter().enumerate() {
+			push_tvalue(lj(state.state), constant);
+			lua.raw.rawseti(state.state, -2, i as i32 + 1);
+		}
+		state.pop_global("constants");
+
+		Ok(detour)
+	}
+
+	const SETUP: &str = r#"
+		function counter(start)
+			local n = start
+			return function(step)
+				n = n + (step or 1)
+				return n, "original"
+			end
+		end
+
+		function factory()
+			local n
+			return function() return n end
+		end
+
+		function hook(original)
+			return function(...)
+				return "hooked", original(...)
+			end
+		end
+
+		function spin()
+			local result
+			for _ = 1, 1000 do result = a(0) end
+			return result
+		end
+
+		a = counter(10)
+		spin()
+		return a
+	"#;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn detours_every_closure_of_a_proto() {
+		let state = State::new();
+		let lua = state.lua();
+
+		lua.raw.pushcfunction(state.state, original_of);
+		state.pop_global("original_of");
+
+		state.run(SETUP, 1);
+		assert!(!is_running(lj(state.state), proto(&state, -1)));
+
+		lua.raw.jit_setmode(state.state, 0, LUAJIT_MODE_ENGINE | LUAJIT_MODE_FLUSH);
+		let mut detour = detour(&state).unwrap();
+		unsafe { detour.enable() };
+
+		assert_eq!(state.results("a(1)"), "hooked,11,original");
+		assert_eq!(state.results("counter(100)(5)"), "hooked,105,original");
+
+		// Traces compiled before don't get to skip the stub, and upvalues are shared with the original.
+		assert_eq!(state.results("spin()"), "hooked");
+		state.run("collectgarbage() collectgarbage()", 0);
+		assert_eq!(state.results("a(1)"), "hooked,12,original");
+
+		unsafe { detour.disable() };
+		assert_eq!(state.results("a(1)"), "13,original");
+		assert_eq!(state.results("counter(0)()"), "1,original");
+	}
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn refuses_functions_smaller_than_the_stub() {
+		let state = State::new();
+
+		state.run("return function() end", 1);
+		let target = proto(&state, -1);
+		state.run(STUB, 1);
+		let stub = proto(&state, -1);
+
+		assert!(unsafe { ProtoDetour::new(target, stub) }.is_err());
+	}
+}
@@ -108,6 +108,9 @@ pub struct GCHeader {
 	pub gct: u8,
 }
 
+// #define LJ_GC_BLACK	0x04
+pub const LJ_GC_BLACK: u8 = 0x04;
+
 // #define LJ_GCVMASK		(((uint64_t)1 << 47) - 1)
 pub const LJ_GCVMASK: u64 = (1u64 << 47) - 1;
 
@@ -154,6 +157,13 @@ impl TValue {
 		Ok(unsafe { &mut *self.as_ptr::<T>()? })
 	}
 
+	// equivalent to the setgcV macro in LuaJIT
+	pub fn from_gcref(gcr: GCRef, itype: u32) -> Self {
+		Self {
+			u64: (gcr.gcptr64 & LJ_GCVMASK) | ((itype as u64) << 47),
+		}
+	}
+
 	pub fn itype(&self) -> u32 {
 		unsafe { ((self.it64 >> 47) & 0xFFFFFFFF) as u32 }
 	}
@@ -174,7 +184,8 @@ impl TValue {
 	impl_tvalue_type_check!(is_numx, LJ_TNUMX);
 }
 
-#[repr(C, packed)]
+// Not packed, env is aligned to 8 bytes after ffid and nupvalues just like in LuaJIT
+#[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct GCFuncHeader {
 	pub header: GCHeader,
@@ -209,6 +220,15 @@ impl GCfuncL {
 
 		Ok(proto_ref)
 	}
+
+	pub fn proto_ptr(&self) -> *mut GCProto {
+		unsafe { self.header.pc.as_ptr::<GCProto>().offset(-1) }
+	}
+
+	/// Pointer to the first of the `nupvalues` upvalue refs stored inline after the header.
+	pub fn uvptr_mut(&mut self) -> *mut GCRef {
+		self.uvptr.as_mut_ptr()
+	}
 }
 
 #[repr(C)]
@@ -400,6 +420,7 @@ impl IntoLJType for GCUpval {
 	const LJ_TYPE: u32 = LJ_TUPVAL;
 }
 
+#[repr(transparent)]
 #[derive(Debug, Clone, Copy)]
 pub struct BCIns(u32);
 
@@ -415,7 +436,7 @@ pub type BCLine = u32;
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct GCProto {
-	header: GCHeader,
+	pub header: GCHeader,
 	pub numparams: u8,
 	pub framesize: u8,
 	pub sizebc: MSize,
@@ -437,7 +458,21 @@ pub struct GCProto {
 	pub varinfo: MRef,
 }
 
+pub const PROTO_CHILD: u8 = 0x01;
+pub const PROTO_VARARG: u8 = 0x02;
+pub const PROTO_NOJIT: u8 = 0x08;
+
 impl GCProto {
+	/// Bytecode is stored inline, immediately after the proto
+	pub fn bytecode(&self) -> *mut BCIns {
+		unsafe { (self as *const GCProto).add(1) as *mut BCIns }
+	}
+
+	/// Collectable constants are stored below `k`, with `idx` counting down from -1 like proto_kgc
+	pub fn kgc(&self, idx: isize) -> GCRef {
+		unsafe { *self.k.as_ptr::<GCRef>().offset(idx) }
+	}
+
 	pub fn chunk_name_str(&self) -> anyhow::Result<&str> {
 		let chunk_name = unsafe {
 			self.chunkname
@@ -166,6 +166,15 @@ macro_rules! autorun_menu_entrypoint {
 	};
 }
 
+#[macro_export]
+macro_rules! autorun_server_entrypoint {
+	($init_fn:expr) => {
+		autorun_entrypoint!($init_fn, autorun_server_init);
+	};
+}
+
 pub mod prelude {
-	pub use crate::{AutorunApi, AutorunError, AutorunResult, autorun_client_entrypoint, autorun_menu_entrypoint};
+	pub use crate::{
+		AutorunApi, AutorunError, AutorunResult, autorun_client_entrypoint, autorun_menu_entrypoint, autorun_server_entrypoint,
+	};
 }
@@ -0,0 +1,127 @@
+//! LuaJIT states for tests, from the shared library at the path in `AUTORUN_TEST_LUAJIT`.
+//! Panics when it isn't set, so tests using this must be `#[ignore]`d rather than silently passing without it.
+use std::ffi::CString;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+use autorun_lua::{GLOBALS_INDEX, IntoLua, LuaApi, LuaState, LuaTypeId, REGISTRY_INDEX};
+
+struct LuaJit {
+	lib: libloading::Library,
+	api: &'static LuaApi,
+}
+
+// SAFETY: Only function pointers, the library itself is never unloaded.
+unsafe impl Sync for LuaJit {}
+
+fn luajit() -> &'static LuaJit {
+	static LUAJIT: OnceLock<LuaJit> = OnceLock::new();
+
+	LUAJIT.get_or_init(|| {
+		let path = std::env::var("AUTORUN_TEST_LUAJIT")
+			.expect("AUTORUN_TEST_LUAJIT must point to a LuaJIT shared library to run this test");
+		let lib = unsafe { libloading::Library::new(&path) }.unwrap_or_else(|why| panic!("Failed to load '{path}': {why}"));
+		// Also what autorun_lua::get_api returns, for code that looks the API up on its own.
+		let api = LuaApi::new(&lib).expect("Failed to load Lua API");
+		let api = autorun_lua::set_api(api).expect("The Lua API was already loaded");
+		LuaJit { lib, api }
+	})
+}
+
+/// The Lua API of the LuaJIT build, for C functions that only get a state.
+pub fn lua() -> &'static LuaApi {
+	luajit().api
+}
+
+/// Keeps tests that go through process wide state, such as which state a realm is tracked as, from running at once.
+pub fn serial() -> MutexGuard<'static, ()> {
+	static SERIAL: Mutex<()> = Mutex::new(());
+	SERIAL.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A fresh state with the standard libraries, closed once dropped.
+pub struct State {
+	pub state: *mut LuaState,
+}
+
+impl State {
+	pub fn new() -> Self {
+		let lib = &luajit().lib;
+		let state = unsafe {
+			let newstate = lib.get::<extern "C" fn() -> *mut LuaState>(b"luaL_newstate\0").unwrap();
+			let openlibs = lib.get::<extern "C" fn(*mut LuaState)>(b"luaL_openlibs\0").unwrap();
+
+			let state = newstate();
+			openlibs(state);
+			state
+		};
+
+		Self { state }
+	}
+
+	pub fn lua(&self) -> &'static LuaApi {
+		lua()
+	}
+
+	/// Runs `code`, leaving `n_results` of its results on the stack.
+	pub fn run(&self, code: &str, n_results: i32) {
+		let code = CString::new(code).unwrap();
+		self.lua().raw.loadstring(self.state, code.as_ptr()).unwrap();
+		self.lua().raw.pcall(self.state, 0, n_results, 0).unwrap();
+	}
+
+	/// Runs `code`, leaving its single result on the stack.
+	pub fn eval(&self, code: &str) {
+		self.run(code, 1);
+	}
+
+	/// Evaluates the comma separated expressions in `code`, joined back together with commas.
+	pub fn results(&self, code: &str) -> String {
+		self.eval(&format!("return table.concat({{ {code} }}, ',')"));
+		let result = self.lua().raw.tostring(self.state, -1).unwrap().into_owned();
+		self.lua().raw.pop(self.state, 1);
+		result
+	}
+
+	pub fn set_global(&self, name: &str, value: impl IntoLua) {
+		self.lua().raw.push(self.state, name);
+		self.lua().raw.push(self.state, value);
+		self.lua().raw.settable(self.state, GLOBALS_INDEX);
+	}
+
+	/// Pops the value at the top of the stack into the global `name`.
+	pub fn pop_global(&self, name: &str) {
+		self.lua().raw.push(self.state, name);
+		self.lua().raw.insert(self.state, -2);
+		self.lua().raw.settable(self.state, GLOBALS_INDEX);
+	}
+
+	/// Number of values in the registry, leaving out the numbers luaL_unref chains free slots with.
+	pub fn registry_len(&self) -> usize {
+		let raw = &self.lua().raw;
+		let mut len = 0;
+
+		raw.pushnil(self.state);
+		while raw.next(self.state, REGISTRY_INDEX) != 0 {
+			if raw.typeid(self.state, -1) != LuaTypeId::Number {
+				len += 1;
+			}
+
+			raw.pop(self.state, 1);
+		}
+
+		len
+	}
+}
+
+impl Default for State {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Drop for State {
+	fn drop(&mut self) {
+		autorun_lua::forget_registry(&self.lua().raw, self.state);
+		self.lua().raw.close(self.state);
+	}
+}
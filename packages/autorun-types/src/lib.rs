@@ -6,6 +6,7 @@ pub type LuaState = std::ffi::c_void;
 pub enum Realm {
 	Menu,
 	Client,
+	Server,
 }
 
 impl std::fmt::Display for Realm {
@@ -13,6 +14,73 @@ impl std::fmt::Display for Realm {
 		match self {
 			Realm::Menu => write!(f, "Menu"),
 			Realm::Client => write!(f, "Client"),
+			Realm::Server => write!(f, "Server"),
 		}
 	}
 }
+
+/// A Lua error caught by Autorun, as collected by the error sink of autorun-env.
+#[derive(Debug, Clone, DeBin, SerBin)]
+pub struct ErrorReport {
+	pub message: String,
+	pub traceback: Option<String>,
+	pub realm: Realm,
+	/// Plugin the erroring code belongs to, if known.
+	pub plugin: Option<String>,
+	/// What Autorun was running when the error happened, like `timer` or `event 'loadbuffer'`.
+	pub site: String,
+	/// Milliseconds since the unix epoch at which the error first happened.
+	pub time: u64,
+	/// How many more times the same error happened at the same site shortly after.
+	pub repeats: u32,
+}
+
+/// Time spent in Lua called from one site of Autorun, as aggregated by the profiler of autorun-env.
+#[derive(Debug, Clone, DeBin, SerBin)]
+pub struct ProfileEntry {
+	pub realm: Realm,
+	pub plugin: Option<String>,
+	pub site: String,
+	pub calls: u64,
+	/// Total wall time of every call, in microseconds.
+	pub total_us: u64,
+	/// Wall time of the slowest call, in microseconds.
+	pub max_us: u64,
+}
+
+/// How the sampling profiler of autorun-env records a realm.
+#[derive(Debug, Clone, DeBin, SerBin)]
+pub struct SamplingOptions {
+	/// Milliseconds after which sampling stops by itself, if any.
+	pub duration_ms: Option<u64>,
+	/// Number of Lua instructions between two samples.
+	pub interval: u32,
+	/// Whether to keep the frames of Autorun's own chunks, like plugins, in the samples.
+	pub include_autorun: bool,
+}
+
+impl Default for SamplingOptions {
+	fn default() -> Self {
+		Self {
+			duration_ms: None,
+			interval: 1000,
+			include_autorun: false,
+		}
+	}
+}
+
+/// Limits on a call into Lua, past which the watchdog of autorun-env stops it with an error.
+#[derive(Debug, Clone, Copy, Default, DeBin, SerBin, PartialEq)]
+pub struct Budget {
+	/// Number of Lua instructions the call may run.
+	pub instructions: Option<u64>,
+	/// Milliseconds the call may take.
+	pub time_ms: Option<u64>,
+}
+
+impl Budget {
+	/// Whether there is anything to enforce.
+	pub fn is_limited(&self) -> bool {
+		self.instructions.is_some() || self.time_ms.is_some()
+	}
+}
@@ -0,0 +1 @@
+SERVER = true
@@ -127,5 +127,39 @@ tostring = _G.tostring
 type = _G.type
 xpcall = _G.xpcall
 _VERSION = _G._VERSION
-getfenv = _G.getfenv
-setfenv = _G.setfenv
+
+-- Functions defined here run in the environment shared by every plugin, which plugins only get a read-only view of.
+-- Hand them that view instead, and don't let them swap the environment of those functions out from under everyone.
+local rawGetfenv, rawSetfenv, getRawMetatable = _G.getfenv, _G.setfenv, _G.debug.getmetatable
+local shared = rawGetfenv(1)
+
+-- Levels are counted from the caller, one above these wrappers.
+local function callerLevel(f)
+    if f == nil then
+        return 2
+    elseif type(f) == "number" and f > 0 then
+        return f + 1
+    end
+
+    return f
+end
+
+getfenv = function(f)
+    local env = rawGetfenv(callerLevel(f))
+    if env ~= shared then
+        return env
+    end
+
+    local caller = rawGetfenv(2)
+    local meta = caller ~= shared and getRawMetatable(caller)
+    return meta and meta.__index or env
+end
+
+setfenv = function(f, env)
+    f = callerLevel(f)
+    if rawGetfenv(f) == shared and rawGetfenv(2) ~= shared then
+        error("Can't change the environment of a function shared by every plugin", 2)
+    end
+
+    return rawSetfenv(f, env)
+end
@@ -2,27 +2,36 @@ local tostring = _G.tostring
 local error = _G.error
 local setfenv = _G.setfenv
 local getfenv = _G.getfenv
+local type = _G.type
 
-local autorunEnv = getfenv(1)
+local shared = getfenv(1)
+
+function Autorun.include(path, env)
+    -- Run the file in the environment of whoever included it, so plugins keep their globals to themselves.
+    local caller = getfenv(2)
+    env = env or caller
+
+    if type(env) ~= "table" then
+        error("Environment for include '" .. path .. "' must be a table", 2)
+    elseif env == shared and caller ~= shared then
+        error("Can't include '" .. path .. "' in the environment shared by every plugin", 2)
+    end
 
-function Autorun.include(path)
     local content = Autorun.read("src/" .. path)
     if not content then
         error("Failed to read file for include '" .. path .. "'")
     end
 
-    local ok, err = Autorun.load(content)
+    local ok, err = Autorun.load(content, path)
     if not ok then
         error("Failed to compile file " .. path .. ": " .. tostring(err))
     end
 
-    setfenv(ok, autorunEnv)
+    setfenv(ok, env)
 
     return ok()
 end
 
 Autorun.include("shared/builtins.lua")
-Autorun.include("shared/require.lua")
-Autorun.include("shared/event.lua")
 Autorun.include("shared/remote.lua")
 Autorun.include("shared/color.lua")
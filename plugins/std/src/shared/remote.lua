@@ -1,26 +1,25 @@
----@type table<string, function[]>
-local events = {}
+local getfenv = getfenv
 
----@type table<string, integer>
-local eventCounters = {}
+-- Each callback remembers the environment of the plugin that added it, so it can be removed when that plugin unloads.
+---@type table<string, { callback: function, env: table }[]>
+local events = {}
 
 function Autorun.onRemote(eventName, callback)
-    local currentPlugin = Autorun.PLUGIN
     events[eventName] = events[eventName] or {}
 
-    local idx = (eventCounters[eventName] or 0) + 1
-
-    events[eventName][idx] = function(v)
-        local previousPlugin = Autorun.PLUGIN
-
-        Autorun.PLUGIN = currentPlugin
-        local fnReturn = callback(v)
-        Autorun.PLUGIN = previousPlugin
+    local callbacks = events[eventName]
+    callbacks[#callbacks + 1] = { callback = callback, env = getfenv(2) }
+end
 
-        return fnReturn
+--- Called by Autorun when a plugin unloads.
+function Autorun.removeRemoteCallbacks(env)
+    for _, callbacks in pairs(events) do
+        for i = #callbacks, 1, -1 do
+            if callbacks[i].env == env then
+                table.remove(callbacks, i)
+            end
+        end
     end
-
-    eventCounters[eventName] = idx
 end
 
 ---@type table<string, boolean>
@@ -33,8 +32,8 @@ function Autorun.runRemoteCallbacks(eventName, v)
     currentlyTriggering[eventName] = true
 
     local success, err = pcall(function()
-        for _, callback in ipairs(events[eventName]) do
-            local result = callback(v)
+        for _, entry in ipairs(events[eventName]) do
+            local result = entry.callback(v)
 
             if result ~= nil then
                 currentlyTriggering[eventName] = nil
@@ -10,6 +10,8 @@ pub fn get_workspace() -> anyhow::Result<&'static Workspace> {
 
 pub fn set_workspace_path(path: &str) -> anyhow::Result<()> {
 	let workspace = Workspace::from_dir(path)?;
+	autorun_env::modules::set_cache_dir(workspace.cache()?)?;
+
 	AUTORUN_WORKSPACE
 		.set(workspace)
 		.map_err(|_| anyhow::anyhow!("Failed to set workspace"))?;
@@ -17,6 +19,5 @@ pub fn set_workspace_path(path: &str) -> anyhow::Result<()> {
 	Ok(())
 }
 
-pub mod client_init;
 pub mod hook;
-pub mod menu_init;
+pub mod init;
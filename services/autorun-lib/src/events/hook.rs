@@ -1,47 +1,82 @@
+use autorun_log::info;
+use autorun_lua::LuaValue;
+
 /// Function that triggers all plugins hook (each file) scripts.
+/// Listeners of the loadbuffer event can return a string to replace the script, or false to block it, in which case it
+/// is replaced by an empty one. Like any event, the first listener to return something decides, in priority order.
 pub fn run(state: *mut autorun_types::LuaState, buffer: &[u8], name: &[u8], mode: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
 	let lua = autorun_lua::get_api()?;
 
 	let realm = autorun_env::global::get_realm(state);
 	let env = autorun_env::global::get_realm_env(realm).expect("env should exist here");
-	env.trigger(lua, state, (c"loadbuffer", name, buffer, mode))?;
-
-	// let n_returns = lua.get_top(state);
-	// match n_returns {
-	// 	0 => (),
-	// 	1 => match lua.type_id(state, -1) {
-	// 		autorun_lua::LuaTypeId::Nil => {
-	// 			lua.pop(state, 1);
-	// 		}
-
-	// 		autorun_lua::LuaTypeId::String => {
-	// 			let str = lua.to::<&[u8]>(state, -1);
-	// 			let str = std::str::from_utf8(str).unwrap_or("<invalid utf8>");
-	// 			lua.pop(state, 1);
-
-	// 			// Replace buffer
-	// 			anyhow::bail!("Replacing buffer in loadbuffer event"));
-	// 		}
-
-	// 		autorun_lua::LuaTypeId::Boolean => {
-	// 			let bool = lua.to_bool(state, -1);
-	// 			lua.pop(state, 1);
-
-	// 			if bool {
-	// 				anyhow::bail!("Blocking loadbuffer in loadbuffer event"));
-	// 			}
-	// 		}
-
-	// 		_ => {
-	// 			lua.pop(state, 1);
-	// 			anyhow::bail!("loadbuffer event returned invalid type"));
-	// 		}
-	// 	},
-
-	// 	_ => {
-	// 		anyhow::bail!("loadbuffer event returned too many values: {}", n_returns));
-	// 	}
-	// }
-
-	Ok(None)
+	env.trigger(lua, state, "loadbuffer", (name, buffer, mode), |results| {
+		let name = String::from_utf8_lossy(name);
+		if results.is_empty() {
+			return Ok(None);
+		}
+
+		match lua.raw.to::<LuaValue>(state, results.start) {
+			LuaValue::Nil => Ok(None),
+
+			LuaValue::String(replacement) => {
+				info!(
+					"Replaced script {name} ({} bytes -> {} bytes)",
+					buffer.len(),
+					replacement.len()
+				);
+
+				// Copied out while the results are still on the stack.
+				Ok(Some(replacement.to_vec()))
+			}
+
+			LuaValue::Boolean(false) => {
+				info!("Blocked script {name}");
+				Ok(Some(Vec::new()))
+			}
+
+			other => anyhow::bail!("loadbuffer event returned {}, expected a string or false", other.typeid()),
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autorun_env::{EnvHandle, lifecycle};
+	use autorun_test::State;
+	use autorun_types::Realm;
+
+	#[test]
+	#[ignore = "needs AUTORUN_TEST_LUAJIT"]
+	fn lets_listeners_replace_or_block_scripts() {
+		let _serial = autorun_test::serial();
+		let state = State::new();
+		let lua = state.lua();
+		lifecycle::open(lua, Realm::Menu, state.state);
+		let env = EnvHandle::create(lua, state.state, Realm::Menu, vec![]).unwrap();
+		autorun_env::global::set_realm_env(Realm::Menu, env.clone());
+
+		let listeners = b"
+			Autorun.on('loadbuffer', function(name, src) if name == 'patched.lua' then return src .. ' -- low' end end)
+			Autorun.on('loadbuffer', function(name, src, mode)
+				if name == 'patched.lua' then return src .. ' -- ' .. mode end
+				if name == 'blocked.lua' then return false end
+				if name == 'broken.lua' then return 1 end
+			end, 1)
+		";
+		env.execute(lua, state.state, c"test", listeners).unwrap();
+
+		let run = |name: &[u8]| run(state.state, b"return 1", name, b"bt");
+		assert_eq!(run(b"patched.lua").unwrap().as_deref(), Some(&b"return 1 -- bt"[..]));
+		assert_eq!(run(b"blocked.lua").unwrap().as_deref(), Some(&b""[..]));
+		assert_eq!(run(b"other.lua").unwrap(), None);
+		assert!(
+			run(b"broken.lua")
+				.unwrap_err()
+				.to_string()
+				.contains("expected a string or false")
+		);
+
+		lifecycle::close(lua, state.state);
+	}
 }
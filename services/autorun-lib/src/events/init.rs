@@ -1,24 +1,24 @@
 use autorun_log::warn;
 use autorun_types::Realm;
 
-#[allow(unused)]
-pub fn run(state: *mut autorun_types::LuaState) -> anyhow::Result<()> {
+/// Function that triggers all plugins init scripts of a realm, once its state is created.
+pub fn run(state: *mut autorun_types::LuaState, realm: Realm) -> anyhow::Result<()> {
 	let workspace = super::get_workspace()?;
 	let lua = autorun_lua::get_api()?;
 
-	let env = autorun_env::EnvHandle::create(lua, state, Realm::Menu)?;
-	autorun_env::global::set_realm_env(Realm::Menu, env);
+	let shared_plugins = workspace.get_settings()?.autorun.shared_plugins.clone();
+	let env = autorun_env::EnvHandle::create(lua, state, realm, shared_plugins)?;
+	autorun_env::global::set_realm_env(realm, env.clone());
 
 	let (mut plugins, _errors) = workspace.get_plugins()?;
 	if plugins.is_empty() {
 		return Ok(());
 	}
 
-	plugins.sort_by_key(|p| p.config().plugin.ordering.unwrap_or(9999));
-
-	for plugin in &plugins {
-		env.set_plugin(lua, state, plugin);
-		run_entrypoint(lua, state, plugin, &env)?;
+	autorun_core::plugins::sort(&mut plugins);
+	for plugin in plugins {
+		let plugin = env.add_plugin(lua, state, plugin)?;
+		run_entrypoint(lua, state, &plugin, &env, realm)?;
 	}
 
 	Ok(())
@@ -27,20 +27,15 @@ pub fn run(state: *mut autorun_types::LuaState) -> anyhow::Result<()> {
 fn run_entrypoint(
 	lua: &autorun_lua::LuaApi,
 	state: *mut autorun_types::LuaState,
-	plugin: &autorun_core::plugins::Plugin,
+	plugin: &std::sync::Arc<autorun_core::plugins::Plugin>,
 	env: &autorun_env::EnvHandle,
+	realm: Realm,
 ) -> anyhow::Result<()> {
 	let config = plugin.config();
 
 	match config.plugin.language {
 		autorun_core::plugins::ConfigPluginLanguage::Lua => {
-			if let Ok(menu_init) = plugin.read_menu_init() {
-				env.execute(lua, state, c"menu/init.lua", &menu_init)?;
-			}
-
-			if let Ok(shared_init) = plugin.read_shared_init() {
-				env.execute(lua, state, c"shared/init.lua", &shared_init)?;
-			}
+			env.run_entrypoints(lua, state, plugin)?;
 		}
 
 		autorun_core::plugins::ConfigPluginLanguage::Native => {
@@ -55,7 +50,7 @@ fn run_entrypoint(
 			let lib_path = path.join(PLUGIN_PATH);
 			if !lib_path.exists() {
 				warn!(
-					"Native menu plugin library not found for plugin '{plugin}': {}",
+					"Native {realm} plugin library not found for plugin '{plugin}': {}",
 					lib_path.display()
 				);
 
@@ -64,10 +59,14 @@ fn run_entrypoint(
 
 			let library = unsafe { libloading::Library::new(lib_path)? };
 
-			if let Ok(autorun_menu_init) =
-				unsafe { library.get::<extern "C" fn(plugin: *const core::ffi::c_void)>(b"autorun_menu_init\0") }
-			{
-				autorun_menu_init((&raw const *plugin).cast());
+			let symbol: &[u8] = match realm {
+				Realm::Menu => b"autorun_menu_init\0",
+				Realm::Client => b"autorun_client_init\0",
+				Realm::Server => b"autorun_server_init\0",
+			};
+
+			if let Ok(init) = unsafe { library.get::<extern "C" fn(plugin: *const core::ffi::c_void)>(symbol) } {
+				init((&raw const **plugin).cast());
 			}
 		}
 
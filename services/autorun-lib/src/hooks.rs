@@ -1,3 +1,4 @@
 pub mod load_buffer;
+pub mod lua_close;
 pub mod paint_traverse;
 pub mod the_fn;
@@ -7,10 +7,6 @@ type LoadBufferFn = extern "C-unwind" fn(*mut LuaState, *const c_char, usize, *c
 
 static LOAD_BUFFER_H: std::sync::OnceLock<retour::GenericDetour<LoadBufferFn>> = std::sync::OnceLock::new();
 
-// Holds a usize but really holding *mut LuaState
-// This stores the previous Lua state acquired upon init's run.
-static PREVIOUS_LUA_STATE: std::sync::Mutex<usize> = std::sync::Mutex::new(0);
-
 extern "C-unwind" fn load_buffer_h(
 	state: *mut LuaState,
 	mut buff: *const c_char,
@@ -18,41 +14,51 @@ extern "C-unwind" fn load_buffer_h(
 	name: *const c_char,
 	mode: *const c_char,
 ) -> c_int {
-	// Ignore menu code
-	if autorun_env::global::get_realm(state) != Realm::Client {
+	let realm = autorun_env::global::get_realm(state);
+	if realm == Realm::Menu {
+		// Ignore menu code
 		return call_original(state, buff, size, name, mode);
 	}
 
-	// Init
-	if *PREVIOUS_LUA_STATE.lock().unwrap() != (state as usize) {
-		*PREVIOUS_LUA_STATE.lock().unwrap() = state as usize;
+	let Ok(lua) = autorun_lua::get_api() else {
+		return call_original(state, buff, size, name, mode);
+	};
 
+	// Init, the first time code loads in a new state
+	if autorun_env::lifecycle::open(lua, realm, state) {
 		disable();
-		if let Err(why) = crate::events::client_init::run(state) {
+		if let Err(why) = crate::events::init::run(state, realm) {
 			let name = unsafe { std::ffi::CStr::from_ptr(name) };
 			let name = name.to_string_lossy();
 			error!("Failed to run init for {name}: {why}");
 		}
 		enable();
-	} else {
-		// Hook
-		let name_cstr = unsafe { std::ffi::CStr::from_ptr(name) };
-		let buff_bytes = unsafe { std::ffi::CStr::from_ptr(buff).to_bytes() };
-		let mode_bytes = unsafe { std::ffi::CStr::from_ptr(mode).to_bytes() };
+		return call_original(state, buff, size, name, mode);
+	}
 
-		disable();
-		match crate::events::hook::run(state, buff_bytes, name_cstr.to_bytes(), mode_bytes) {
-			Ok(Some(x)) => {
-				buff = x.as_ptr().cast::<c_char>();
-				size = x.len();
-			}
-			Err(why) => {
-				let name_cstr = name_cstr.to_string_lossy();
-				error!("Failed to run hook for {name_cstr}: {why}");
-			}
-			_ => (),
+	// Hook
+	let name_cstr = unsafe { std::ffi::CStr::from_ptr(name) };
+	let buff_bytes = unsafe { std::slice::from_raw_parts(buff.cast::<u8>(), size) };
+	let mode_bytes = match mode.is_null() {
+		true => &b"bt"[..],
+		false => unsafe { std::ffi::CStr::from_ptr(mode).to_bytes() },
+	};
+
+	disable();
+	// Kept alive until the original is done reading it.
+	let replacement = match crate::events::hook::run(state, buff_bytes, name_cstr.to_bytes(), mode_bytes) {
+		Ok(replacement) => replacement,
+		Err(why) => {
+			let name_cstr = name_cstr.to_string_lossy();
+			error!("Failed to run hook for {name_cstr}: {why}");
+			None
 		}
-		enable();
+	};
+	enable();
+
+	if let Some(replacement) = &replacement {
+		buff = replacement.as_ptr().cast::<c_char>();
+		size = replacement.len();
 	}
 
 	call_original(state, buff, size, name, mode)
@@ -0,0 +1,34 @@
+use autorun_types::LuaState;
+
+type LuaCloseFn = extern "C-unwind" fn(state: *mut LuaState);
+
+static LUA_CLOSE_H: std::sync::OnceLock<retour::GenericDetour<LuaCloseFn>> = std::sync::OnceLock::new();
+
+extern "C-unwind" fn lua_close_h(state: *mut LuaState) {
+	// Tear down while the state can still run the events and shutdown functions of plugins.
+	if let Ok(lua) = autorun_lua::get_api() {
+		crate::hooks::load_buffer::disable();
+		autorun_env::lifecycle::close(lua, state);
+		crate::hooks::load_buffer::enable();
+
+		// Also covers states that were never tracked, which close() leaves alone.
+		autorun_lua::forget_registry(&lua.raw, state);
+	}
+
+	LUA_CLOSE_H.get().unwrap().call(state);
+}
+
+pub fn init() -> anyhow::Result<()> {
+	let lua = autorun_lua::get_api()?;
+	let target_fn = lua.raw.close;
+
+	let detour = unsafe {
+		let detour = retour::GenericDetour::new(target_fn, lua_close_h)?;
+		detour.enable()?;
+		detour
+	};
+
+	LUA_CLOSE_H.set(detour).unwrap();
+
+	Ok(())
+}
@@ -10,14 +10,14 @@ extern "C-unwind" fn paint_traverse_h(this: *mut std::ffi::c_void, panel_id: usi
 		.unwrap()
 		.call(this, panel_id, force_repaint, force_allow);
 
-	let Some(callback) = crate::lua_queue::pop() else {
-		return;
-	};
-
 	crate::hooks::load_buffer::disable();
 
 	let lua = autorun_lua::get_api().unwrap();
-	if let Err(why) = callback(lua) {
+	autorun_env::timers::tick(lua);
+
+	if let Some(callback) = autorun_env::lua_queue::pop()
+		&& let Err(why) = callback(lua)
+	{
 		error!("Error in PaintTraverse Lua callback: {}", why);
 	}
 
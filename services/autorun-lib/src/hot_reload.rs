@@ -0,0 +1,188 @@
+//! Hot reload of Lua plugins, enabled through the `hot_reload` setting.
+//! Changes to the sources or config of a plugin are debounced, then the plugin is reloaded on the game thread in every
+//! realm it is loaded in. Changes to its data directory are ignored, since plugins write there themselves.
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use autorun_core::Workspace;
+use autorun_log::*;
+use autorun_types::Realm;
+
+/// How long files have to stay untouched before reloading, as editors tend to write several times when saving.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Source of file changes, so tests can provide their own.
+pub trait FileWatcher {
+	/// Starts sending the path of every file changed inside of `dir` to `changes`.
+	fn watch(&mut self, dir: &Path, changes: mpsc::Sender<PathBuf>) -> anyhow::Result<()>;
+}
+
+/// Watches the filesystem through notify.
+#[derive(Default)]
+pub struct NotifyWatcher {
+	watcher: Option<notify::RecommendedWatcher>,
+}
+
+impl FileWatcher for NotifyWatcher {
+	fn watch(&mut self, dir: &Path, changes: mpsc::Sender<PathBuf>) -> anyhow::Result<()> {
+		use notify::Watcher;
+
+		let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+			Ok(event) if !event.kind.is_access() => {
+				for path in event.paths {
+					let _ = changes.send(path);
+				}
+			}
+			Ok(_) => (),
+			Err(why) => error!("Failed to watch plugins: {why}"),
+		})?;
+
+		watcher.watch(dir, notify::RecursiveMode::Recursive)?;
+		self.watcher = Some(watcher);
+		Ok(())
+	}
+}
+
+/// Starts reloading plugins of the workspace as they change, if enabled in its settings.
+pub fn start(workspace: &'static Workspace) -> anyhow::Result<()> {
+	if !workspace.get_settings()?.autorun.hot_reload {
+		return Ok(());
+	}
+
+	info!("Watching plugins for changes");
+	std::thread::spawn(move || {
+		if let Err(why) = run(NotifyWatcher::default(), workspace, schedule_reload) {
+			error!("Stopped watching plugins: {why}");
+		}
+	});
+
+	Ok(())
+}
+
+/// Calls `reload` with the name of each plugin whose files changed, until the watcher stops.
+pub fn run(mut watcher: impl FileWatcher, workspace: &Workspace, mut reload: impl FnMut(&str)) -> anyhow::Result<()> {
+	let dir = workspace.unsafe_plugins_path();
+	let dir = dir.canonicalize().unwrap_or(dir);
+
+	let (sender, changes) = mpsc::channel();
+	watcher.watch(&dir, sender)?;
+
+	while let Some(paths) = debounce(&changes) {
+		for plugin_dir in changed_plugin_dirs(&dir, &paths) {
+			match workspace.get_plugin(&plugin_dir) {
+				Ok(plugin) => reload(&plugin.config().plugin.name),
+				Err(why) => warn!("Not reloading plugin in '{plugin_dir}': {why}"),
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Waits for a change, then for the ones following it to settle. Returns None once the watcher is gone.
+fn debounce(changes: &mpsc::Receiver<PathBuf>) -> Option<Vec<PathBuf>> {
+	let mut paths = vec![changes.recv().ok()?];
+
+	loop {
+		match changes.recv_timeout(DEBOUNCE) {
+			Ok(path) => paths.push(path),
+			Err(_) => return Some(paths),
+		}
+	}
+}
+
+/// Names of the plugin directories whose sources or config are among `paths`.
+fn changed_plugin_dirs(plugins_dir: &Path, paths: &[PathBuf]) -> BTreeSet<String> {
+	paths
+		.iter()
+		.filter_map(|path| {
+			let mut components = path.strip_prefix(plugins_dir).ok()?.components();
+			let Some(Component::Normal(dir)) = components.next() else {
+				return None;
+			};
+
+			match components.next() {
+				Some(Component::Normal(part)) if part == "src" || part == "plugin.toml" => {
+					Some(dir.to_string_lossy().into_owned())
+				}
+				_ => None,
+			}
+		})
+		.collect()
+}
+
+/// Reloads a plugin on the game thread in every realm it is loaded in.
+fn schedule_reload(name: &str) {
+	for realm in [Realm::Menu, Realm::Client, Realm::Server] {
+		let name = name.to_owned();
+
+		autorun_env::lua_queue::push(move |lua| {
+			let Some(env) = autorun_env::global::get_realm_env(realm) else {
+				return Ok(());
+			};
+
+			let Some(state) = autorun_interfaces::lua::get_state(realm)? else {
+				return Ok(());
+			};
+
+			if env.get_plugin(&name).is_none() {
+				return Ok(());
+			}
+
+			info!("Reloading plugin '{name}' in the {realm} realm");
+			env.reload_plugin(lua, state, &name)?;
+			Ok(())
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Sends a fixed set of changes, then stops.
+	struct FakeWatcher(Vec<&'static str>);
+
+	impl FileWatcher for FakeWatcher {
+		fn watch(&mut self, dir: &Path, changes: mpsc::Sender<PathBuf>) -> anyhow::Result<()> {
+			for path in &self.0 {
+				changes.send(dir.join(path))?;
+			}
+
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn reloads_plugins_with_changed_sources() {
+		let root = std::env::temp_dir().join(format!("autorun-hot-reload-{}", std::process::id()));
+		let workspace = Workspace::from_dir(&root).unwrap();
+
+		for (dir, name) in [("first", "one"), ("second", "two"), ("third", "three")] {
+			let path = workspace.unsafe_plugins_path().join(dir);
+			std::fs::create_dir_all(path.join("src")).unwrap();
+			std::fs::write(
+				path.join("plugin.toml"),
+				format!("[plugin]\nname = \"{name}\"\nauthor = \"\"\nversion = \"\"\ndescription = \"\"\nlanguage = \"lua\"\n"),
+			)
+			.unwrap();
+		}
+
+		let watcher = FakeWatcher(vec![
+			"first/src/client/init.lua",
+			"first/src/shared/init.lua",
+			"second/plugin.toml",
+			"third/data/state.json",
+			"stray.txt",
+		]);
+
+		let mut reloaded = Vec::new();
+		let result = run(watcher, &workspace, |name| reloaded.push(name.to_owned()));
+		std::fs::remove_dir_all(&root).unwrap();
+
+		result.unwrap();
+		assert_eq!(reloaded, ["one", "two"]);
+	}
+}
@@ -1,6 +1,6 @@
 mod events;
 mod hooks;
-mod lua_queue;
+mod hot_reload;
 mod menu;
 mod server;
 
@@ -17,6 +17,7 @@ pub fn main() -> anyhow::Result<()> {
 
 	hooks::paint_traverse::init()?;
 	hooks::load_buffer::init()?;
+	hooks::lua_close::init()?;
 	// hooks::the_fn::init()?;
 
 	Ok(())
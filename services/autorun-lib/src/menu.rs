@@ -8,7 +8,11 @@ pub fn start_waiting_for_menu() {
 
 			if let Some(menu) = autorun_interfaces::lua::get_state(autorun_types::Realm::Menu).unwrap() {
 				let menu = menu as usize;
-				crate::lua_queue::push(move |_| crate::events::menu_init::run(menu as *mut core::ffi::c_void));
+				autorun_env::lua_queue::push(move |lua| {
+					let menu = menu as *mut core::ffi::c_void;
+					autorun_env::lifecycle::open(lua, autorun_types::Realm::Menu, menu);
+					crate::events::init::run(menu, autorun_types::Realm::Menu)
+				});
 
 				break;
 			}
@@ -1,2 +1,5 @@
 pub mod execute;
+pub mod get_errors;
+pub mod get_profile;
+pub mod sampling;
 pub mod set_workspace_path;
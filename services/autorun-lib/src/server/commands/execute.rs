@@ -2,7 +2,7 @@ use autorun_ipc::Message;
 use autorun_log::error;
 
 pub fn handle(_messenger: &mut autorun_ipc::Messenger, message: Message) -> anyhow::Result<()> {
-	let Message::RunCode(realm, code) = message else {
+	let Message::RunCode(realm, code, budget) = message else {
 		anyhow::bail!("Expected RunCode message");
 	};
 
@@ -11,11 +11,13 @@ pub fn handle(_messenger: &mut autorun_ipc::Messenger, message: Message) -> anyh
 		return Ok(());
 	}
 
-	crate::lua_queue::push(move |lua| {
+	autorun_env::lua_queue::push(move |lua| {
 		let state = autorun_interfaces::lua::get_state(realm)?.unwrap();
 		let env = autorun_env::global::get_realm_env(realm).ok_or(anyhow::anyhow!("Failed to get env"))?;
 
-		env.execute(lua, state, c"RunString", code.as_bytes())?;
+		autorun_env::watchdog::run(lua, state, Some(&budget), || {
+			env.execute(lua, state, c"RunString", code.as_bytes())
+		})?;
 
 		Ok(())
 	});
@@ -0,0 +1,11 @@
+use autorun_ipc::Message;
+
+pub fn handle(messenger: &mut autorun_ipc::Messenger, message: Message) -> anyhow::Result<()> {
+	let Message::GetErrors = message else {
+		anyhow::bail!("Expected GetErrors message");
+	};
+
+	messenger.send(Message::Errors(autorun_env::errors::recent()))?;
+
+	Ok(())
+}
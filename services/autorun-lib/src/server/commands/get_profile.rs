@@ -0,0 +1,11 @@
+use autorun_ipc::Message;
+
+pub fn handle(messenger: &mut autorun_ipc::Messenger, message: Message) -> anyhow::Result<()> {
+	let Message::GetProfile = message else {
+		anyhow::bail!("Expected GetProfile message");
+	};
+
+	messenger.send(Message::Profile(autorun_env::profiler::snapshot()))?;
+
+	Ok(())
+}
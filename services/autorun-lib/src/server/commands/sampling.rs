@@ -0,0 +1,42 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use autorun_ipc::Message;
+use autorun_log::error;
+
+/// How long to wait for the game thread to hand back the samples.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn handle(messenger: &mut autorun_ipc::Messenger, message: Message) -> anyhow::Result<()> {
+	match message {
+		Message::StartSampling(realm, options) => {
+			autorun_env::lua_queue::push(move |lua| {
+				let state = autorun_interfaces::lua::get_state(realm)?
+					.ok_or_else(|| anyhow::anyhow!("Lua state for realm {realm:?} is not ready"))?;
+
+				autorun_env::sampler::start(lua, state, realm, &options)
+			});
+		}
+
+		Message::StopSampling(realm) => {
+			let (sender, receiver) = mpsc::channel();
+			autorun_env::lua_queue::push(move |lua| {
+				let _ = sender.send(autorun_env::sampler::stop(lua, realm));
+				Ok(())
+			});
+
+			match receiver.recv_timeout(STOP_TIMEOUT) {
+				Ok(Ok(folded)) => messenger.send(Message::Samples(realm, folded))?,
+				Ok(Err(why)) => messenger.send(Message::Print(format!("Failed to stop sampling: {why}")))?,
+				Err(_) => {
+					error!("Timed out stopping the sampler of realm {realm:?}");
+					messenger.send(Message::Print("Timed out stopping the sampler".to_owned()))?;
+				}
+			}
+		}
+
+		_ => anyhow::bail!("Expected a sampling message"),
+	}
+
+	Ok(())
+}
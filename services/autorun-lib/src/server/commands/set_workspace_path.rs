@@ -1,4 +1,5 @@
 use autorun_ipc::Message;
+use autorun_log::error;
 
 pub fn handle(_messenger: &mut autorun_ipc::Messenger, message: Message) -> anyhow::Result<()> {
 	let Message::SetWorkspacePath(text) = message else {
@@ -10,6 +11,10 @@ pub fn handle(_messenger: &mut autorun_ipc::Messenger, message: Message) -> anyh
 
 	if !had_workspace_path {
 		crate::menu::start_waiting_for_menu();
+
+		if let Err(why) = crate::hot_reload::start(crate::events::get_workspace()?) {
+			error!("Failed to start hot reload: {why}");
+		}
 	}
 
 	Ok(())
@@ -36,6 +36,18 @@ fn handle_message(messenger: &mut autorun_ipc::Messenger, message: Message) -> a
 			commands::execute::handle(messenger, message)?;
 		}
 
+		Message::GetErrors => {
+			commands::get_errors::handle(messenger, message)?;
+		}
+
+		Message::GetProfile => {
+			commands::get_profile::handle(messenger, message)?;
+		}
+
+		Message::StartSampling(..) | Message::StopSampling(..) => {
+			commands::sampling::handle(messenger, message)?;
+		}
+
 		Message::SetWorkspacePath(..) => {
 			commands::set_workspace_path::handle(messenger, message)?;
 		}